@@ -3,6 +3,8 @@ use crate::{
     chess_move::{ChessMove, FLAG_CASTLE, FLAG_EN_PASSANT, FLAG_PROMOTION},
     move_generator::MoveGenerator,
     piece::{Color, PieceType},
+    variant::Variant,
+    zobrist::KEYS,
 };
 
 pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
@@ -13,12 +15,20 @@ pub struct Board {
     pub occupied: [BitBoard; 2],       // Occupied squares for each color
     pub en_passant: Option<u8>,        // En passant target square, if any
     pub castling_rights: [bool; 4], // Castling rights: [White Kingside, White Queenside, Black Kingside, Black Queenside]
+    pub castling_files: [u8; 4],    // Files of the castling rooks, in the order of the rights
+    pub chess960: bool,             // Chess960 rules: castling moves are written king takes rook
+    pub variant: Variant,           // The rules the game is played by
+    pub remaining_checks: [u8; 2],  // Checks each color still has to give, in Three-check
+    pub pockets: [[u8; 5]; 2],      // Pieces in hand in Crazyhouse, indexed by [Color][PieceType]
+    pub promoted: BitBoard,         // Pieces that were pawns, which go to a pocket as pawns
     pub side_to_move: Color,        // Current player to move
     pub half_move_clock: u32,       // Half-move clock for the fifty-move rule
     pub full_move_number: u32,      // Full-move counter, incremented after Black's move
     moves: Vec<ChessMove>,
     pub combined: BitBoard,
     positions: Vec<BitBoard>,
+    promoted_history: Vec<BitBoard>, // The promoted pieces before each move of `moves`
+    explosions: Vec<[[BitBoard; 6]; 2]>, // The pieces each capture exploded, in Atomic
 }
 
 // Board creation, FEN, and display methods.
@@ -28,44 +38,101 @@ impl Board {
         let occupied = [BitBoard::default(), BitBoard::default()];
         let en_passant = None;
         let castling_rights = [false; 4];
+        let castling_files = [7, 0, 7, 0];
+        let chess960 = false;
+        let variant = Variant::Standard;
+        let remaining_checks = [3, 3];
+        let pockets = [[0; 5]; 2];
+        let promoted = BitBoard::default();
         let side_to_move = Color::White;
         let half_move_clock = 0;
         let full_move_number = 1;
         let moves = Vec::new();
         let combined = BitBoard::default();
         let positions = Vec::new();
+        let promoted_history = Vec::new();
+        let explosions = Vec::new();
 
         Board {
             bitboards,
             occupied,
             en_passant,
             castling_rights,
+            castling_files,
+            chess960,
+            variant,
+            remaining_checks,
+            pockets,
+            promoted,
             side_to_move,
             half_move_clock,
             full_move_number,
             moves,
             combined,
             positions,
+            promoted_history,
+            explosions,
         }
     }
     pub fn from_fen(fen: &str) -> Result<Self, String> {
-        let parts: Vec<&str> = fen.split_whitespace().collect();
-        if parts.len() != 6 {
-            return Err("Invalid FEN: Wrong number of parts".to_owned());
-        }
+        let mut parts: Vec<&str> = fen.split_whitespace().collect();
+        // Three-check positions have the remaining checks after the en passant square
+        let checks = match parts.len() {
+            6 => None,
+            7 => Some(parts.remove(4)),
+            _ => return Err("Invalid FEN: Wrong number of parts".to_owned()),
+        };
 
         let mut board = Board::new(); // Assuming `new` initializes an empty board
+        if let Some(checks) = checks {
+            let invalid = || "Invalid FEN: Invalid remaining checks".to_owned();
+            let (white, black) = checks.split_once('+').ok_or_else(invalid)?;
+            board.remaining_checks = [
+                white.parse().map_err(|_| invalid())?,
+                black.parse().map_err(|_| invalid())?,
+            ];
+        }
 
-        // Parse pieces
-        let ranks: Vec<&str> = parts[0].split('/').collect();
+        // Parse pieces. In Crazyhouse the pockets follow in brackets or as a ninth rank
+        let mut ranks: Vec<&str> = parts[0].split('/').collect();
+        let mut pocket = None;
+        if let Some((placement, rest)) = parts[0].split_once('[') {
+            let invalid = || "Invalid FEN: Invalid pocket".to_owned();
+            pocket = Some(rest.strip_suffix(']').ok_or_else(invalid)?);
+            ranks = placement.split('/').collect();
+        } else if ranks.len() == 9 {
+            pocket = ranks.pop();
+        }
         if ranks.len() != 8 {
             return Err("Invalid FEN: Incorrect number of ranks".to_owned());
         }
+        if let Some(pocket) = pocket {
+            board.variant = Variant::Crazyhouse;
+            for ch in pocket.chars() {
+                let color = if ch.is_ascii_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                match PieceType::from_letter(ch) {
+                    Some(PieceType::King) | None => {
+                        return Err("Invalid FEN: Invalid pocket".to_owned())
+                    }
+                    Some(piece) => board.pockets[color as usize][piece as usize] += 1,
+                }
+            }
+        }
 
         for (i, rank) in ranks.iter().enumerate() {
             let mut file = 0;
             for ch in rank.chars() {
-                if ch.is_ascii_digit() {
+                if ch == '~' {
+                    // The piece before was promoted
+                    if file == 0 {
+                        return Err("Invalid FEN: Invalid promoted piece".to_owned());
+                    }
+                    board.promoted |= 1 << ((7 - i) * 8 + file - 1);
+                } else if ch.is_ascii_digit() {
                     file += ch.to_digit(10).unwrap() as usize;
                 } else {
                     let color = if ch.is_uppercase() {
@@ -96,16 +163,56 @@ impl Board {
             _ => return Err("Invalid FEN: Invalid active color".to_owned()),
         };
 
-        // Parse castling availability
+        // Parse castling availability, in standard, Shredder (file letters) or X-FEN
+        // notation, where K and Q name the outermost rook on that side of the king
         board.castling_rights = [false; 4];
         for ch in parts[2].chars() {
-            match ch {
-                'K' => board.castling_rights[0] = true,
-                'Q' => board.castling_rights[1] = true,
-                'k' => board.castling_rights[2] = true,
-                'q' => board.castling_rights[3] = true,
-                '-' => {}
+            if ch == '-' {
+                continue;
+            }
+            let color = if ch.is_ascii_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+            let back_rank = color as u8 * 56;
+            let king_file = board.bitboards[color as usize][PieceType::King as usize]
+                .into_iter()
+                .find(|&square| square / 8 == back_rank / 8)
+                .map_or(4, |square| square % 8);
+            let rook_files: Vec<u8> = (0..8)
+                .filter(|&file| board.piece_at(back_rank + file, color) == Some(PieceType::Rook))
+                .collect();
+            let (kingside, file) = match ch.to_ascii_lowercase() {
+                'k' => (
+                    true,
+                    *rook_files
+                        .iter()
+                        .rfind(|&&file| file > king_file)
+                        .unwrap_or(&7),
+                ),
+                'q' => (
+                    false,
+                    *rook_files
+                        .iter()
+                        .find(|&&file| file < king_file)
+                        .unwrap_or(&0),
+                ),
+                file @ 'a'..='h' => {
+                    let file = file as u8 - b'a';
+                    board.chess960 = true;
+                    (file > king_file, file)
+                }
                 _ => return Err("Invalid FEN: Invalid castling flags".to_owned()),
+            };
+            let (right, standard_file) = match kingside {
+                true => (2 * color as usize, 7),
+                false => (2 * color as usize + 1, 0),
+            };
+            board.castling_rights[right] = true;
+            board.castling_files[right] = file;
+            if king_file != 4 || file != standard_file {
+                board.chess960 = true;
             }
         }
 
@@ -126,6 +233,67 @@ impl Board {
 
         Ok(board)
     }
+    /// Returns the Chess960 starting position numbered `index` (0 to 959) in the
+    /// standard numbering, where 518 is the usual starting position.
+    pub fn chess960_start(index: u32) -> Result<Self, String> {
+        if index >= 960 {
+            return Err(format!("Invalid Chess960 position: {}", index));
+        }
+        let mut rank = [None; 8];
+        let mut n = index as usize;
+        // One bishop on each color, then the queen and the knights on the empty squares
+        rank[2 * (n % 4) + 1] = Some('B');
+        n /= 4;
+        rank[2 * (n % 4)] = Some('B');
+        n /= 4;
+        let place = |piece, nth: usize, rank: &mut [Option<char>; 8]| {
+            let square = (0..8).filter(|&file| rank[file].is_none()).nth(nth);
+            rank[square.expect("An empty square")] = Some(piece);
+        };
+        place('Q', n % 6, &mut rank);
+        n /= 6;
+        // The placements of the knights on the five squares left, in order
+        let (first, second) = (0..5)
+            .flat_map(|first| (first + 1..5).map(move |second| (first, second)))
+            .nth(n)
+            .expect("Fewer than ten knight placements");
+        // The second knight counts the first one's square as taken
+        place('N', first, &mut rank);
+        place('N', second - 1, &mut rank);
+        // The king between the rooks on the three squares left
+        for piece in ['R', 'K', 'R'] {
+            place(piece, 0, &mut rank);
+        }
+
+        let pieces: String = rank
+            .iter()
+            .map(|piece| piece.expect("A full rank"))
+            .collect();
+        let fen = format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+            pieces.to_ascii_lowercase(),
+            pieces
+        );
+        let mut board = Board::from_fen(&fen)?;
+        board.chess960 = true;
+        Ok(board)
+    }
+
+    /// Parses a FEN of a position of `variant`. Racing Kings and Antichess have no
+    /// castling, and only Crazyhouse has pockets.
+    pub fn from_variant_fen(fen: &str, variant: Variant) -> Result<Self, String> {
+        let mut board = Board::from_fen(fen)?;
+        let castling = matches!(variant, Variant::RacingKings | Variant::Antichess);
+        if castling && board.castling_rights.contains(&true) {
+            return Err(format!("Invalid FEN: No castling in {}", variant));
+        }
+        if board.variant == Variant::Crazyhouse && variant != Variant::Crazyhouse {
+            return Err(format!("Invalid FEN: No pockets in {}", variant));
+        }
+        board.variant = variant;
+        Ok(board)
+    }
+
     pub fn to_fen(&self) -> String {
         let mut fen = String::new();
 
@@ -141,6 +309,9 @@ impl Board {
                         empty_squares = 0;
                     }
                     fen.push(piece);
+                    if self.variant == Variant::Crazyhouse && self.promoted & (1 << index) != 0 {
+                        fen.push('~');
+                    }
                 } else {
                     empty_squares += 1;
                 }
@@ -153,6 +324,29 @@ impl Board {
             }
         }
 
+        // Pockets
+        if self.variant == Variant::Crazyhouse {
+            fen.push('[');
+            for color in [Color::White, Color::Black] {
+                for piece in [
+                    PieceType::Queen,
+                    PieceType::Rook,
+                    PieceType::Bishop,
+                    PieceType::Knight,
+                    PieceType::Pawn,
+                ] {
+                    let letter = match color {
+                        Color::White => piece.letter(),
+                        Color::Black => piece.letter().to_ascii_lowercase(),
+                    };
+                    for _ in 0..self.pockets[color as usize][piece as usize] {
+                        fen.push(letter);
+                    }
+                }
+            }
+            fen.push(']');
+        }
+
         // Active color
         fen.push(' ');
         fen.push(match self.side_to_move {
@@ -165,17 +359,10 @@ impl Board {
         if self.castling_rights.iter().all(|&v| !v) {
             fen.push('-');
         } else {
-            if self.castling_rights[0] {
-                fen.push('K');
-            }
-            if self.castling_rights[1] {
-                fen.push('Q');
-            }
-            if self.castling_rights[2] {
-                fen.push('k');
-            }
-            if self.castling_rights[3] {
-                fen.push('q');
+            for right in 0..4 {
+                if self.castling_rights[right] {
+                    fen.push(self.castling_char(right));
+                }
             }
         }
 
@@ -190,6 +377,12 @@ impl Board {
             fen.push('-');
         }
 
+        // Remaining checks
+        if self.variant == Variant::ThreeCheck {
+            let [white, black] = self.remaining_checks;
+            fen.push_str(&format!(" {}+{}", white, black));
+        }
+
         // Half-move clock
         fen.push(' ');
         fen.push_str(&self.half_move_clock.to_string());
@@ -201,6 +394,32 @@ impl Board {
         fen
     }
 
+    /// The letter of a castling right in a FEN: K, Q, k or q as usual, or the file of
+    /// the rook in Shredder notation when it is not the outermost on its side, as X-FEN
+    /// does in Chess960.
+    fn castling_char(&self, right: usize) -> char {
+        let color = if right < 2 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let back_rank = color as u8 * 56;
+        let file = self.castling_files[right];
+        let kingside = matches!(right, 0 | 2);
+        let mut beyond = if kingside { file + 1..8 } else { 0..file };
+        let outermost = !self.chess960
+            || !beyond.any(|f| self.piece_at(back_rank + f, color) == Some(PieceType::Rook));
+        let letter = match (outermost, kingside) {
+            (true, true) => 'K',
+            (true, false) => 'Q',
+            (false, _) => (b'A' + file) as char,
+        };
+        match color {
+            Color::White => letter,
+            Color::Black => letter.to_ascii_lowercase(),
+        }
+    }
+
     /// Prints the board in a human-readable format.
     pub fn print_board(&self) {
         println!("  +------------------------+");
@@ -324,16 +543,128 @@ impl Board {
         None
     }
 
-    /// Checks if the current player's king is in check.
+    /// Checks if the current player's king is in check. Never in Antichess, where the
+    /// king is an ordinary piece, nor in Atomic once a king has exploded.
     pub fn is_king_in_check(&self, color: Color) -> bool {
-        let king_position = self.bitboards[color as usize][PieceType::King as usize].to_square();
-        self.is_square_attacked(king_position, color.opposite())
+        let kings = |color: Color| self.bitboards[color as usize][PieceType::King as usize];
+        match self.variant {
+            Variant::Antichess => return false,
+            Variant::Atomic if kings(color) == 0 || kings(color.opposite()) == 0 => return false,
+            _ => {}
+        }
+        self.is_king_attacked_on(kings(color).to_square(), color.opposite(), self.combined)
+    }
+
+    /// Returns true if a king on `square` would be attacked by `attacker_color`, with
+    /// the pieces of `occupied` blocking the sliders. In Atomic kings cannot capture,
+    /// and a king next to the enemy king is never attacked since capturing it would
+    /// explode the capturer's own king.
+    pub fn is_king_attacked_on(
+        &self,
+        square: u8,
+        attacker_color: Color,
+        occupied: BitBoard,
+    ) -> bool {
+        if self.variant == Variant::Atomic {
+            let attacker_king = self.bitboards[attacker_color as usize][PieceType::King as usize];
+            if MoveGenerator::king_attacks(square) & attacker_king != 0 {
+                return false;
+            }
+        }
+        self.is_square_attacked_through(square, attacker_color, occupied)
+    }
+
+    /// The legality check of Atomic, which takes the place of `is_king_in_check`:
+    /// the king of `color` must not have exploded, and must not be in check unless
+    /// the enemy king exploded.
+    pub fn is_atomic_king_safe(&self, color: Color) -> bool {
+        let kings = |color: Color| self.bitboards[color as usize][PieceType::King as usize];
+        kings(color) != 0 && (kings(color.opposite()) == 0 || !self.is_king_in_check(color))
     }
 
     pub fn is_draw(&self) -> bool {
         self.is_insufficient_material() || self.is_50_move_rule() || self.is_threefold_repetition()
     }
 
+    /// Returns the last move made on the board, if any.
+    pub fn last_move(&self) -> Option<&ChessMove> {
+        self.moves.last()
+    }
+
+    /// The moves made on the board, oldest first, as `unmake` takes them back.
+    pub fn moves(&self) -> &[ChessMove] {
+        &self.moves
+    }
+
+    /// The occupied squares after each move of `moves`, which the threefold
+    /// repetition check compares.
+    pub fn positions(&self) -> &[BitBoard] {
+        &self.positions
+    }
+
+    /// Returns true if a pawn of the side to move stands next to the pawn that just
+    /// moved two squares, so that en passant is a capture it could make. Without one
+    /// the en passant square makes no difference to the position.
+    pub fn can_capture_en_passant(&self) -> bool {
+        self.en_passant.is_some_and(|square| {
+            let us = self.side_to_move;
+            MoveGenerator::pawn_attacks(square, us.opposite()).0
+                & self.bitboards[us as usize][PieceType::Pawn as usize].0
+                != 0
+        })
+    }
+
+    /// Computes the Zobrist hash of the current position.
+    /// Two boards with the same pieces, side to move, castling rights and
+    /// en passant square always hash to the same value. The en passant square only
+    /// counts when a pawn can capture on it, so that repetitions are recognised.
+    pub fn hash(&self) -> u64 {
+        let mut hash = 0;
+        for color in [Color::White, Color::Black] {
+            for piece_type in 0..6 {
+                for square in self.bitboards[color as usize][piece_type] {
+                    hash ^= KEYS.pieces[color as usize][piece_type][square as usize];
+                }
+            }
+        }
+        for (i, &right) in self.castling_rights.iter().enumerate() {
+            if right {
+                hash ^= KEYS.castling[i];
+            }
+        }
+        if let Some(square) = self.en_passant.filter(|_| self.can_capture_en_passant()) {
+            hash ^= KEYS.en_passant[(square % 8) as usize];
+        }
+        if self.side_to_move == Color::Black {
+            hash ^= KEYS.side_to_move;
+        }
+        if self.variant == Variant::ThreeCheck {
+            for (color, &checks) in self.remaining_checks.iter().enumerate() {
+                hash ^= KEYS.remaining_checks[color][checks.min(3) as usize];
+            }
+        }
+        for (color, pocket) in self.pockets.iter().enumerate() {
+            for (piece, &count) in pocket.iter().enumerate() {
+                if count > 0 {
+                    hash ^= KEYS.pockets[color][piece][count.min(16) as usize];
+                }
+            }
+        }
+        hash
+    }
+
+    /// Computes a Zobrist hash of the pawns alone, so that positions with the same
+    /// pawn structure share it. Used to cache pawn structure evaluations.
+    pub fn pawn_hash(&self) -> u64 {
+        let mut hash = 0;
+        for color in [Color::White, Color::Black] {
+            for square in self.bitboards[color as usize][PieceType::Pawn as usize] {
+                hash ^= KEYS.pieces[color as usize][PieceType::Pawn as usize][square as usize];
+            }
+        }
+        hash
+    }
+
     /// Make a move on the board.
     pub fn make_move(&mut self, mut m: ChessMove) {
         // Store the castling rights before the move
@@ -341,14 +672,22 @@ impl Board {
         m.old_en_passant_square = self.en_passant;
         m.old_halfmove_clock = self.half_move_clock;
         self.en_passant = None;
+        self.promoted_history.push(self.promoted);
+
+        // A drop moves the piece from the pocket to the square, `from` being `to`
+        let piece = match m.dropped_piece {
+            Some(piece) => {
+                self.pockets[self.side_to_move as usize][piece as usize] -= 1;
+                piece
+            }
+            None => self.piece_at(m.from, self.side_to_move).unwrap(),
+        };
 
         // Special move handling before the actual move (for castling)
         if m.flags & FLAG_CASTLE != 0 {
             self.handle_castling(m);
         }
 
-        let piece = self.piece_at(m.from, self.side_to_move).unwrap();
-
         // Update pieces on the board: moving the piece
         self.move_piece(m.from, m.to, piece);
 
@@ -357,23 +696,9 @@ impl Board {
             self.remove_piece(m.to, captured);
         }
 
-        // update castling rights if the captured piece is a rook
-        if let Some(captured) = m.captured_piece {
-            if captured == PieceType::Rook {
-                if self.side_to_move == Color::White {
-                    if m.to == 56 {
-                        self.castling_rights[3] = false;
-                    } else if m.to == 63 {
-                        self.castling_rights[2] = false;
-                    }
-                } else if m.to == 0 {
-                    self.castling_rights[1] = false;
-                }
-                // Black queen side rook
-                else if m.to == 7 {
-                    self.castling_rights[0] = false;
-                } // Black king side rook
-            }
+        // update castling rights if the captured piece is a castling rook
+        if m.captured_piece == Some(PieceType::Rook) {
+            self.remove_castling_rook(m.to);
         }
 
         // Handle en passant
@@ -386,6 +711,23 @@ impl Board {
             self.promote_pawn(m.to, m.promoted_piece.unwrap()); // Assuming m.piece is the promoted piece type
         }
 
+        // In Crazyhouse the captured piece goes to the pocket, as a pawn if it was promoted
+        if let Some(captured) = m
+            .captured_piece
+            .filter(|_| self.variant == Variant::Crazyhouse)
+        {
+            let captured = match self.promoted & (1 << m.to) != 0 {
+                true => PieceType::Pawn,
+                false => captured,
+            };
+            self.pockets[self.side_to_move as usize][captured as usize] += 1;
+        }
+        self.update_promoted(m);
+
+        if self.variant == Variant::Atomic && m.captured_piece.is_some() {
+            self.explode(m.to);
+        }
+
         // Update castling rights if the moved piece is a king or rook
         if piece == PieceType::King {
             if self.side_to_move == Color::White {
@@ -396,21 +738,7 @@ impl Board {
                 self.castling_rights[3] = false; // Black queen side
             }
         } else if piece == PieceType::Rook {
-            if self.side_to_move == Color::White {
-                if m.from == 0 {
-                    self.castling_rights[1] = false;
-                }
-                // White queen side rook
-                else if m.from == 7 {
-                    self.castling_rights[0] = false;
-                } // White king side rook
-            } else if m.from == 56 {
-                self.castling_rights[3] = false;
-            }
-            // Black queen side rook
-            else if m.from == 63 {
-                self.castling_rights[2] = false;
-            }
+            self.remove_castling_rook(m.from);
         }
 
         // Update en passant target
@@ -432,13 +760,36 @@ impl Board {
         self.positions.push(self.combined);
         // Update the side to move
         self.side_to_move = self.side_to_move.opposite();
+        if self.variant == Variant::ThreeCheck && self.is_king_in_check(self.side_to_move) {
+            let checks = &mut self.remaining_checks[self.side_to_move.opposite() as usize];
+            *checks = checks.saturating_sub(1);
+        }
     }
 
     pub fn unmake(&mut self) {
+        // A move that gave check in Three-check counted against the checks left
+        if self.variant == Variant::ThreeCheck && self.is_king_in_check(self.side_to_move) {
+            self.remaining_checks[self.side_to_move.opposite() as usize] += 1;
+        }
         self.side_to_move = self.side_to_move.opposite();
         let last_move = self.moves.pop().unwrap();
+        self.promoted = self.promoted_history.pop().unwrap();
+        // Put back what the capture exploded, the capturing piece included
+        if self.variant == Variant::Atomic && last_move.captured_piece.is_some() {
+            let exploded = self.explosions.pop().unwrap();
+            for (bitboards, exploded) in self.bitboards.iter_mut().zip(exploded) {
+                for (bitboard, exploded) in bitboards.iter_mut().zip(exploded) {
+                    *bitboard |= exploded;
+                }
+            }
+        }
         let piece = self.piece_at(last_move.to, self.side_to_move).unwrap();
-        self.move_piece(last_move.to, last_move.from, piece);
+        if last_move.dropped_piece.is_some() {
+            self.bitboards[self.side_to_move as usize][piece as usize] &= !(1 << last_move.to);
+            self.pockets[self.side_to_move as usize][piece as usize] += 1;
+        } else {
+            self.move_piece(last_move.to, last_move.from, piece);
+        }
         // Handle special moves
         if last_move.flags & FLAG_CASTLE != 0 {
             self.unhandle_castling(last_move);
@@ -452,6 +803,16 @@ impl Board {
         } else if let Some(captured) = last_move.captured_piece {
             self.set_piece(last_move.to.into(), captured, self.side_to_move.opposite());
         }
+        if let Some(captured) = last_move
+            .captured_piece
+            .filter(|_| self.variant == Variant::Crazyhouse)
+        {
+            let captured = match self.promoted & (1 << last_move.to) != 0 {
+                true => PieceType::Pawn,
+                false => captured,
+            };
+            self.pockets[self.side_to_move as usize][captured as usize] -= 1;
+        }
 
         self.en_passant = last_move.old_en_passant_square;
         self.castling_rights = last_move.old_castling_rights;
@@ -465,6 +826,17 @@ impl Board {
 
     /// Checks if a particular square is attacked by any piece of the specified color.
     pub fn is_square_attacked(&self, square: u8, attacker_color: Color) -> bool {
+        self.is_square_attacked_through(square, attacker_color, self.combined)
+    }
+
+    /// Checks if a square is attacked when the sliders are blocked by `occupied`
+    /// rather than by the pieces on the board.
+    fn is_square_attacked_through(
+        &self,
+        square: u8,
+        attacker_color: Color,
+        occupied: BitBoard,
+    ) -> bool {
         let opponent_pieces = self.bitboards[attacker_color as usize];
 
         // check attacks from pawns
@@ -487,7 +859,7 @@ impl Board {
         }
 
         // Check attacks from rooks and queens (horizontal and vertical attacks)
-        if (MoveGenerator::rook_attacks(square, self.combined)
+        if (MoveGenerator::rook_attacks(square, occupied)
             & (opponent_pieces[PieceType::Rook as usize]
                 | opponent_pieces[PieceType::Queen as usize]))
             != 0
@@ -496,7 +868,7 @@ impl Board {
         }
 
         // Check attacks from bishops and queens (diagonal attacks)
-        if (MoveGenerator::bishop_attacks(square, self.combined)
+        if (MoveGenerator::bishop_attacks(square, occupied)
             & (opponent_pieces[PieceType::Bishop as usize]
                 | opponent_pieces[PieceType::Queen as usize]))
             != 0
@@ -528,24 +900,37 @@ impl Board {
         self.bitboards[self.side_to_move.opposite() as usize][piece as usize] &= !mask;
     }
 
-    fn handle_castling(&mut self, m: ChessMove) {
-        if self.side_to_move == Color::White {
-            if m.to == 6 {
-                // e1 to g1 (White Kingside)
-                self.move_piece(7, 5, PieceType::Rook); // Move the rook from h1 to f1
-            } else if m.to == 2 {
-                // e1 to c1 (White Queenside)
-                self.move_piece(0, 3, PieceType::Rook); // Move the rook from a1 to d1
+    /// Returns the squares the rook of the castling move `m` moves from and to.
+    fn castling_rook(&self, m: ChessMove) -> (u8, u8) {
+        let kingside = m.to % 8 == 6;
+        let right = 2 * self.side_to_move as usize + if kingside { 0 } else { 1 };
+        let to = m.to - m.to % 8 + if kingside { 5 } else { 3 };
+        (self.castling_rook_square(right), to)
+    }
+
+    /// Returns the square of the rook of a castling right, the right being an index
+    /// into `castling_rights`.
+    pub fn castling_rook_square(&self, right: usize) -> u8 {
+        let back_rank = if right < 2 { 0 } else { 56 };
+        back_rank + self.castling_files[right]
+    }
+
+    /// Takes away the castling right of the rook on `square`, when it has one.
+    fn remove_castling_rook(&mut self, square: u8) {
+        for right in 0..4 {
+            if self.castling_rook_square(right) == square {
+                self.castling_rights[right] = false;
             }
-        } else if m.to == 62 {
-            // e8 to g8 (Black Kingside)
-            self.move_piece(63, 61, PieceType::Rook); // Move the rook from h8 to f8
-        } else if m.to == 58 {
-            // e8 to c8 (Black Queenside)
-            self.move_piece(56, 59, PieceType::Rook); // Move the rook from a8 to d8
         }
     }
 
+    fn handle_castling(&mut self, m: ChessMove) {
+        // The king goes to the g or c file and the rook next to it on the f or d file,
+        // wherever they started in Chess960
+        let (from, to) = self.castling_rook(m);
+        self.move_piece(from, to, PieceType::Rook);
+    }
+
     fn promote_pawn(&mut self, square: u8, new_piece: PieceType) {
         let mask = 1 << square;
         self.bitboards[self.side_to_move as usize][PieceType::Pawn as usize] &= !mask;
@@ -562,21 +947,8 @@ impl Board {
     }
 
     fn unhandle_castling(&mut self, m: ChessMove) {
-        if self.side_to_move == Color::White {
-            if m.to == 6 {
-                // Move the rook back from f1 to h1
-                self.move_piece(5, 7, PieceType::Rook);
-            } else if m.to == 2 {
-                // Move the rook back from d1 to a1
-                self.move_piece(3, 0, PieceType::Rook);
-            }
-        } else if m.to == 62 {
-            // Move the rook back from f8 to h8
-            self.move_piece(61, 63, PieceType::Rook);
-        } else if m.to == 58 {
-            // Move the rook back from d8 to a8
-            self.move_piece(59, 56, PieceType::Rook);
-        }
+        let (from, to) = self.castling_rook(m);
+        self.move_piece(to, from, PieceType::Rook);
     }
 
     fn unhandle_en_passant(&mut self, m: ChessMove) {
@@ -599,6 +971,43 @@ impl Board {
         self.bitboards[self.side_to_move as usize][PieceType::Pawn as usize] |= mask;
     }
 
+    /// Removes the pieces an Atomic capture on `square` explodes: the capturing piece
+    /// and every piece but the pawns next to it. They are kept for `unmake`. Castling
+    /// rights go with an exploded king or rook.
+    fn explode(&mut self, square: u8) {
+        let blast = MoveGenerator::king_attacks(square) | 1 << square;
+        let mut exploded = [[BitBoard::default(); 6]; 2];
+        for color in [Color::White, Color::Black] {
+            let bitboards = &mut self.bitboards[color as usize];
+            for (piece, pieces) in exploded[color as usize].iter_mut().enumerate() {
+                let reach = match PieceType::from(piece) {
+                    PieceType::Pawn => 1 << square,
+                    _ => blast,
+                };
+                *pieces = bitboards[piece] & reach;
+                bitboards[piece] &= !pieces.0;
+            }
+            if exploded[color as usize][PieceType::King as usize] != 0 {
+                self.castling_rights[2 * color as usize] = false;
+                self.castling_rights[2 * color as usize + 1] = false;
+            }
+            for rook in exploded[color as usize][PieceType::Rook as usize] {
+                self.remove_castling_rook(rook);
+            }
+        }
+        self.explosions.push(exploded);
+    }
+
+    /// Moves the promoted mark of the moved piece along with it, marks the piece a
+    /// pawn promoted to and clears the mark of a captured piece.
+    fn update_promoted(&mut self, m: ChessMove) {
+        let promoted = self.promoted & (1 << m.from) != 0 || m.flags & FLAG_PROMOTION != 0;
+        self.promoted &= !(1 << m.from | 1 << m.to);
+        if promoted {
+            self.promoted |= 1 << m.to;
+        }
+    }
+
     /// Updates the en passant target square based on the given move.
     /// If a pawn moves two squares forward, the en passant target is
     /// set to the square between the from and to squares.
@@ -644,8 +1053,18 @@ impl Board {
         // self.update_pinned_and_checkers();
     }
 
-    // Check for insufficient material on the board
-    fn is_insufficient_material(&self) -> bool {
+    /// Returns true if neither side has the material to mate, whatever the moves. In
+    /// the variants that end otherwise, a lone king or a lone check is enough.
+    pub fn is_insufficient_material(&self) -> bool {
+        match self.variant {
+            Variant::Standard => {}
+            Variant::ThreeCheck => {
+                let kings = self.bitboards[Color::White as usize][PieceType::King as usize]
+                    | self.bitboards[Color::Black as usize][PieceType::King as usize];
+                return self.combined == kings;
+            }
+            _ => return false,
+        }
         let mut knight_count = [0, 0];
         let mut light_squared_bishops = [0, 0];
         let mut dark_squared_bishops = [0, 0];
@@ -790,6 +1209,7 @@ mod tests {
             from: 9,
             to: 17,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: None,
             flags: 0,
             old_castling_rights: [false; 4],
@@ -809,6 +1229,7 @@ mod tests {
             from: 9,
             to: 25,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: Some(PieceType::Pawn),
             flags: 0,
             old_castling_rights: [false; 4],
@@ -832,6 +1253,7 @@ mod tests {
             from: 4,
             to: 6,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: None,
             flags: FLAG_CASTLE,
             old_castling_rights: [true, true, false, false],
@@ -857,6 +1279,7 @@ mod tests {
             from: 4,
             to: 2,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: None,
             flags: FLAG_CASTLE,
             old_castling_rights: [true, true, false, false],
@@ -882,6 +1305,7 @@ mod tests {
             from: 60,
             to: 58,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: None,
             flags: FLAG_CASTLE,
             old_castling_rights: [false, false, true, true],
@@ -910,6 +1334,7 @@ mod tests {
             from: 60,
             to: 62,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: None,
             flags: FLAG_CASTLE,
             old_castling_rights: [false, false, true, true],
@@ -937,6 +1362,7 @@ mod tests {
             from: 7,
             to: 15,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: None,
             flags: 0,
             old_castling_rights: [true, true, false, false],
@@ -955,6 +1381,7 @@ mod tests {
             from: 48,
             to: 56,
             promoted_piece: Some(PieceType::Queen),
+            dropped_piece: None,
             captured_piece: None,
             flags: FLAG_PROMOTION,
             old_castling_rights: [false; 4],
@@ -979,6 +1406,7 @@ mod tests {
             from: 35,
             to: 44,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: Some(PieceType::Pawn),
             flags: FLAG_EN_PASSANT,
             old_castling_rights: [false; 4],
@@ -1004,6 +1432,7 @@ mod tests {
             from: 8,
             to: 16,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: None,
             flags: 0,
             old_castling_rights: [false; 4],
@@ -1031,6 +1460,7 @@ mod tests {
             from: 8,
             to: 17,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: Some(PieceType::Pawn),
             flags: 0,
             old_castling_rights: [false; 4],
@@ -1061,6 +1491,7 @@ mod tests {
             from: 4,
             to: 6,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: None,
             flags: FLAG_CASTLE,
             old_castling_rights: [true, true, false, false],
@@ -1094,6 +1525,7 @@ mod tests {
             from: 48,
             to: 56,
             promoted_piece: Some(PieceType::Queen),
+            dropped_piece: None,
             captured_piece: None,
             flags: FLAG_PROMOTION,
             old_castling_rights: [false; 4],
@@ -1128,6 +1560,7 @@ mod tests {
             from: 35,
             to: 44,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: Some(PieceType::Pawn),
             flags: FLAG_EN_PASSANT,
             old_castling_rights: [false; 4],
@@ -1157,6 +1590,7 @@ mod tests {
             from: 2,
             to: 38,
             promoted_piece: None,
+            dropped_piece: None,
             captured_piece: Some(PieceType::Pawn),
             flags: 0,
             old_castling_rights: [true, true, true, true],
@@ -1168,6 +1602,50 @@ mod tests {
         board.print_board();
         assert_eq!(board.combined, board_copy.combined);
     }
+    #[test]
+    fn test_hash_restored_after_unmake() {
+        let mut board = Board::default();
+        let hash = board.hash();
+        for m in MoveGenerator::generate_legal_moves(&mut board) {
+            board.make_move(m);
+            assert_ne!(board.hash(), hash);
+            board.unmake();
+            assert_eq!(board.hash(), hash);
+        }
+    }
+
+    #[test]
+    fn test_hash_transposition() {
+        let mut board = Board::default();
+        for m in ["g1f3", "g8f6", "b1c3"] {
+            let m = ChessMove::from_standard_notation(m, &board).unwrap();
+            board.make_move(m);
+        }
+        let mut transposed = Board::default();
+        for m in ["b1c3", "g8f6", "g1f3"] {
+            let m = ChessMove::from_standard_notation(m, &transposed).unwrap();
+            transposed.make_move(m);
+        }
+        assert_eq!(board.hash(), transposed.hash());
+        assert_ne!(board.hash(), Board::default().hash());
+    }
+
+    #[test]
+    fn test_pawn_hash_only_changes_with_pawns() {
+        let mut board = Board::default();
+        let start = board.pawn_hash();
+        for m in ["g1f3", "g8f6"] {
+            let m = ChessMove::from_standard_notation(m, &board).unwrap();
+            board.make_move(m);
+        }
+        assert_eq!(board.pawn_hash(), start);
+        let m = ChessMove::from_standard_notation("e2e4", &board).unwrap();
+        board.make_move(m);
+        assert_ne!(board.pawn_hash(), start);
+        board.unmake();
+        assert_eq!(board.pawn_hash(), start);
+    }
+
     #[test]
     fn insufficient_material_king_vs_king() {
         let mut board = Board::new();
@@ -1252,4 +1730,62 @@ mod tests {
             "Multiple knights and bishops vs king should not be insufficient material for a draw."
         );
     }
+
+    #[test]
+    fn test_chess960_start_positions() {
+        assert_eq!(Board::chess960_start(518).unwrap().to_fen(), STARTING_FEN);
+        assert_eq!(
+            Board::chess960_start(0).unwrap().to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        let last = Board::chess960_start(959).unwrap();
+        assert_eq!(last.castling_files, [2, 0, 2, 0]);
+        assert!(last.chess960);
+        assert!(Board::chess960_start(960).is_err());
+
+        let mut back_ranks: Vec<String> = (0..960)
+            .map(|index| {
+                let fen = Board::chess960_start(index).unwrap().to_fen();
+                fen[fen.rfind('/').unwrap() + 1..fen.find(' ').unwrap()].to_string()
+            })
+            .collect();
+        back_ranks.sort();
+        back_ranks.dedup();
+        assert_eq!(back_ranks.len(), 960);
+    }
+
+    #[test]
+    fn test_shredder_and_x_fen() {
+        let board = Board::from_fen("1r2k1r1/8/8/8/8/8/8/RR2K2R w HBg - 0 1").unwrap();
+        assert!(board.chess960);
+        assert_eq!(board.castling_rights, [true, true, true, false]);
+        assert_eq!(&board.castling_files[..3], [7, 1, 6]);
+        // The b1 rook is not the outermost, so it keeps its file letter
+        let fen = "1r2k1r1/8/8/8/8/8/8/RR2K2R w KBk - 0 1";
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(
+            Board::from_fen(fen).unwrap().castling_files,
+            board.castling_files
+        );
+
+        let standard = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert!(!standard.chess960);
+        assert_eq!(standard.castling_files, [7, 0, 7, 0]);
+        assert!(Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KX - 0 1").is_err());
+    }
+
+    #[test]
+    fn test_chess960_castling() {
+        let fen = "1r4k1/8/8/8/8/8/8/1R4K1 w Qq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let castle = ChessMove::from_standard_notation("g1b1", &board).unwrap();
+        assert_eq!((castle.to, castle.flags), (2, FLAG_CASTLE));
+        assert_eq!(castle.to_uci(&board), "g1b1");
+        assert_eq!(castle.to_standard_notation(), "g1c1");
+
+        board.make_move(castle);
+        assert_eq!(board.to_fen(), "1r4k1/8/8/8/8/8/8/2KR4 b q - 1 1");
+        board.unmake();
+        assert_eq!(board.to_fen(), fen);
+    }
 }
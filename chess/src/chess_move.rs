@@ -8,6 +8,7 @@ pub struct ChessMove {
     pub from: u8,
     pub to: u8,
     pub promoted_piece: Option<PieceType>,
+    pub dropped_piece: Option<PieceType>, // The piece put on `to` from the pocket, in Crazyhouse
     pub captured_piece: Option<PieceType>,
     pub flags: u8,
     pub old_castling_rights: [bool; 4], // Store the castling rights before the move
@@ -16,6 +17,33 @@ pub struct ChessMove {
 }
 
 impl ChessMove {
+    /// Returns true if both moves go between the same squares with the same promotion,
+    /// and both or neither castle (in Chess960 a king may step to where it castles to),
+    /// or drop the same piece on the same square.
+    /// The board state stored in the move (castling rights, en passant, clock) is ignored.
+    pub fn is_same_move(&self, other: &ChessMove) -> bool {
+        self.from == other.from
+            && self.to == other.to
+            && self.promoted_piece == other.promoted_piece
+            && self.dropped_piece == other.dropped_piece
+            && self.flags & FLAG_CASTLE == other.flags & FLAG_CASTLE
+    }
+
+    /// Returns a Crazyhouse move putting `piece` from the pocket on the empty `square`.
+    pub fn drop(piece: PieceType, square: u8, board: &Board) -> ChessMove {
+        ChessMove {
+            from: square,
+            to: square,
+            promoted_piece: None,
+            dropped_piece: Some(piece),
+            captured_piece: None,
+            flags: FLAG_DROP,
+            old_castling_rights: board.castling_rights,
+            old_en_passant_square: board.en_passant,
+            old_halfmove_clock: board.half_move_clock,
+        }
+    }
+
     // Convert board index to algebraic chess notation, e.g., 0 -> "a1"
     fn index_to_algebraic(index: u8) -> String {
         let file = (index % 8) as char; // file, from 'a' to 'h'
@@ -23,7 +51,11 @@ impl ChessMove {
         format!("{}{}", (file as u8 + b'a') as char, rank)
     }
 
+    /// Returns the move in UCI notation, with drops written as `N@f3`.
     pub fn to_standard_notation(&self) -> String {
+        if let Some(piece) = self.dropped_piece {
+            return format!("{}@{}", piece.letter(), Self::index_to_algebraic(self.to));
+        }
         let mut move_string = format!(
             "{}{}",
             Self::index_to_algebraic(self.from),
@@ -37,6 +69,8 @@ impl ChessMove {
                 PieceType::Rook => 'r',
                 PieceType::Bishop => 'b',
                 PieceType::Knight => 'n',
+                // Only in Antichess
+                PieceType::King => 'k',
                 _ => unreachable!(), // Only these types are valid for promotion
             });
         }
@@ -48,6 +82,28 @@ impl ChessMove {
 
         move_string
     }
+    /// Returns the move in UCI notation for a position of the game of `board`.
+    /// The same as `to_standard_notation`, except that castling is written as the
+    /// king taking its own rook when the game is Chess960.
+    pub fn to_uci(&self, board: &Board) -> String {
+        if !board.chess960 || self.flags & FLAG_CASTLE == 0 {
+            return self.to_standard_notation();
+        }
+        // White castles on the first rank, Black on the eighth
+        let kingside = self.to % 8 == 6;
+        let right = if self.to < 8 { 0 } else { 2 } + if kingside { 0 } else { 1 };
+        format!(
+            "{}{}",
+            Self::index_to_algebraic(self.from),
+            Self::index_to_algebraic(board.castling_rook_square(right))
+        )
+    }
+
+    fn is_square(algebraic: &str) -> bool {
+        let bytes = algebraic.as_bytes();
+        bytes.len() == 2 && (b'a'..=b'h').contains(&bytes[0]) && (b'1'..=b'8').contains(&bytes[1])
+    }
+
     fn algebraic_to_index(algebraic: &str) -> u8 {
         let bytes = algebraic.as_bytes();
         let file = bytes[0] - b'a'; // 'a' to 'h' -> 0 to 7
@@ -56,12 +112,20 @@ impl ChessMove {
     }
 
     pub fn from_standard_notation(s: &str, board: &Board) -> Option<ChessMove> {
+        // Drops, such as N@f3
+        if let Some((piece, square)) = s.split_once('@') {
+            let mut letters = piece.chars();
+            let piece = letters.next().and_then(PieceType::from_letter)?;
+            if letters.next().is_some() || !Self::is_square(square) {
+                return None;
+            }
+            return Some(Self::drop(piece, Self::algebraic_to_index(square), board));
+        }
+
         // Castling
         if s == "O-O" || s == "O-O-O" {
-            let from = match board.side_to_move {
-                Color::White => 4,
-                Color::Black => 60,
-            };
+            let from =
+                board.bitboards[board.side_to_move as usize][PieceType::King as usize].to_square();
             let to = match board.side_to_move {
                 Color::White => {
                     if s == "O-O" {
@@ -82,6 +146,7 @@ impl ChessMove {
                 from,
                 to,
                 promoted_piece: None,
+                dropped_piece: None,
                 captured_piece: None,
                 flags: FLAG_CASTLE,
                 old_castling_rights: board.castling_rights,
@@ -90,42 +155,56 @@ impl ChessMove {
             });
         }
 
-        let mut chars = s.chars();
-
-        let from_file = chars.next().unwrap();
-        let from_rank = chars.next().unwrap();
-        let to_file = chars.next().unwrap();
-        let to_rank = chars.next().unwrap();
-
-        let from = Self::algebraic_to_index(&format!("{}{}", from_file, from_rank));
-        let to = Self::algebraic_to_index(&format!("{}{}", to_file, to_rank));
+        // Regular moves, such as e2e4 or e7e8q, from a square with a piece to move
+        let (from, to) = (s.get(..2)?, s.get(2..4)?);
+        if s.len() > 5 || !Self::is_square(from) || !Self::is_square(to) {
+            return None;
+        }
+        let from = Self::algebraic_to_index(from);
+        let to = Self::algebraic_to_index(to);
+        let moved = board.piece_at(from, board.side_to_move)?;
 
         let mut promoted_piece = None;
         let mut flags = 0;
 
-        // if from piece is a king and the distance is 2, then it is a castle move
-        if board.piece_at(from, board.side_to_move).unwrap() == PieceType::King
-            && (from as i8 - to as i8).abs() == 2
-        {
+        // if from piece is a king and the distance is 2, then it is a castle move.
+        // In Chess960 castling is written as the king taking its own rook instead
+        let is_king = moved == PieceType::King;
+        if is_king && board.chess960 {
+            if board.piece_at(to, board.side_to_move) == Some(PieceType::Rook) {
+                let back_rank = to - to % 8;
+                let king_file = if to > from { 6 } else { 2 };
+                return Some(ChessMove {
+                    from,
+                    to: back_rank + king_file,
+                    promoted_piece: None,
+                    dropped_piece: None,
+                    captured_piece: None,
+                    flags: FLAG_CASTLE,
+                    old_castling_rights: board.castling_rights,
+                    old_en_passant_square: board.en_passant,
+                    old_halfmove_clock: board.half_move_clock,
+                });
+            }
+        } else if is_king && (from as i8 - to as i8).abs() == 2 {
             flags |= FLAG_CASTLE;
         }
 
         // Check for promotion
-        if let Some(promotion) = chars.next() {
+        if let Some(promotion) = s[4..].chars().next() {
             promoted_piece = Some(match promotion {
                 'q' => PieceType::Queen,
                 'r' => PieceType::Rook,
                 'b' => PieceType::Bishop,
                 'n' => PieceType::Knight,
+                'k' => PieceType::King,
                 _ => return None,
             });
             flags |= FLAG_PROMOTION;
         }
 
         let captured_piece = board.piece_at(to, board.side_to_move.opposite());
-        if board.piece_at(from, board.side_to_move).unwrap() == PieceType::Pawn
-            && board.en_passant.is_some()
-            && to == board.en_passant.unwrap()
+        if moved == PieceType::Pawn && board.en_passant.is_some() && to == board.en_passant.unwrap()
         {
             flags |= FLAG_EN_PASSANT;
         }
@@ -134,6 +213,7 @@ impl ChessMove {
             from,
             to,
             promoted_piece,
+            dropped_piece: None,
             captured_piece,
             flags,
             old_castling_rights: board.castling_rights,
@@ -146,3 +226,4 @@ impl ChessMove {
 pub const FLAG_CASTLE: u8 = 0b0001;
 pub const FLAG_EN_PASSANT: u8 = 0b0010;
 pub const FLAG_PROMOTION: u8 = 0b0100;
+pub const FLAG_DROP: u8 = 0b1000;
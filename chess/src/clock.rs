@@ -0,0 +1,541 @@
+//! Time controls and a chess clock that keeps them.
+//!
+//! A `TimeControl` is written as in the PGN `TimeControl` tag: stages separated by
+//! `:`, each `moves/seconds` or just `seconds` for the rest of the game, an optional
+//! `+seconds` increment, and `*seconds` for an hourglass. `-` is a game without time
+//! control. Beyond the PGN standard, a stage may end with `dseconds` for a simple
+//! delay or `bseconds` for a Bronstein delay, as in `300d5`.
+//!
+//! The `Clock` reads the time from a `TimeSource`, so that tests can move time on by
+//! hand with `ManualTime` instead of waiting.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::piece::Color;
+
+/// A delay at the start of every move before the clock counts down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Delay {
+    #[default]
+    None,
+    /// The clock waits for the delay before it starts.
+    Simple(Duration),
+    /// The clock runs at once, and the time used is given back after the move, up to
+    /// the delay.
+    Bronstein(Duration),
+}
+
+/// A stage of a time control: `time` for `moves` moves, or for the rest of the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stage {
+    /// The moves to play in this stage, None for the rest of the game. When the last
+    /// stage has a number of moves, it repeats.
+    pub moves: Option<u32>,
+    pub time: Duration,
+    /// Added after every move of the stage.
+    pub increment: Duration,
+    pub delay: Delay,
+}
+
+impl Stage {
+    pub fn sudden_death(time: Duration) -> Self {
+        Stage {
+            moves: None,
+            time,
+            increment: Duration::ZERO,
+            delay: Delay::None,
+        }
+    }
+}
+
+/// How much time the players have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeControl {
+    Unlimited,
+    /// One or more stages played in order.
+    Stages(Vec<Stage>),
+    /// Both players start with the time, and the time one uses goes to the other.
+    Hourglass(Duration),
+}
+
+impl TimeControl {
+    pub fn sudden_death(time: Duration) -> Self {
+        TimeControl::Stages(vec![Stage::sudden_death(time)])
+    }
+
+    /// The time for the game and an increment after every move.
+    pub fn fischer(time: Duration, increment: Duration) -> Self {
+        TimeControl::Stages(vec![Stage {
+            increment,
+            ..Stage::sudden_death(time)
+        }])
+    }
+
+    /// The time for the game with a delay before the clock runs on every move.
+    pub fn delay(time: Duration, delay: Delay) -> Self {
+        TimeControl::Stages(vec![Stage {
+            delay,
+            ..Stage::sudden_death(time)
+        }])
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeControl::Unlimited => write!(f, "-"),
+            TimeControl::Hourglass(time) => write!(f, "*{}", Seconds(*time)),
+            TimeControl::Stages(stages) => {
+                for (i, stage) in stages.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ":")?;
+                    }
+                    if let Some(moves) = stage.moves {
+                        write!(f, "{}/", moves)?;
+                    }
+                    write!(f, "{}", Seconds(stage.time))?;
+                    if !stage.increment.is_zero() {
+                        write!(f, "+{}", Seconds(stage.increment))?;
+                    }
+                    match stage.delay {
+                        Delay::None => {}
+                        Delay::Simple(delay) => write!(f, "d{}", Seconds(delay))?,
+                        Delay::Bronstein(delay) => write!(f, "b{}", Seconds(delay))?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A duration written in seconds, with a fraction only when it has one.
+struct Seconds(Duration);
+
+impl fmt::Display for Seconds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self.0.as_millis();
+        match millis % 1000 {
+            0 => write!(f, "{}", millis / 1000),
+            _ => {
+                let seconds = format!("{}.{:03}", millis / 1000, millis % 1000);
+                write!(f, "{}", seconds.trim_end_matches('0'))
+            }
+        }
+    }
+}
+
+fn parse_seconds(text: &str) -> Result<Duration, String> {
+    text.parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("Invalid number of seconds: {}", text))
+}
+
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        match text {
+            "-" => return Ok(TimeControl::Unlimited),
+            "?" | "" => return Err("Unknown time control".to_string()),
+            _ => {}
+        }
+        if let Some(time) = text.strip_prefix('*') {
+            return Ok(TimeControl::Hourglass(parse_seconds(time)?));
+        }
+
+        let mut stages = Vec::new();
+        for field in text.split(':') {
+            let (moves, rest) = match field.split_once('/') {
+                Some((moves, rest)) => {
+                    let moves = moves
+                        .parse()
+                        .ok()
+                        .filter(|&moves| moves > 0)
+                        .ok_or_else(|| format!("Invalid number of moves: {}", moves))?;
+                    (Some(moves), rest)
+                }
+                None => (None, field),
+            };
+            let (rest, delay) = match rest.find(['d', 'b']) {
+                Some(at) => {
+                    let delay = parse_seconds(&rest[at + 1..])?;
+                    let delay = match &rest[at..at + 1] {
+                        "d" => Delay::Simple(delay),
+                        _ => Delay::Bronstein(delay),
+                    };
+                    (&rest[..at], delay)
+                }
+                None => (rest, Delay::None),
+            };
+            let (time, increment) = match rest.split_once('+') {
+                Some((time, increment)) => (time, parse_seconds(increment)?),
+                None => (rest, Duration::ZERO),
+            };
+            stages.push(Stage {
+                moves,
+                time: parse_seconds(time)?,
+                increment,
+                delay,
+            });
+        }
+        Ok(TimeControl::Stages(stages))
+    }
+}
+
+/// Where a `Clock` reads the time: a duration since a fixed but arbitrary moment.
+pub trait TimeSource {
+    fn now(&self) -> Duration;
+}
+
+/// The time of the system's monotonic clock.
+#[derive(Debug, Clone, Copy)]
+pub struct WallTime {
+    start: Instant,
+}
+
+impl Default for WallTime {
+    fn default() -> Self {
+        WallTime {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl TimeSource for WallTime {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A time that only moves when told to. Clones share the same time, so a test keeps
+/// one to move the time of the clock it gave the other to.
+#[derive(Debug, Clone, Default)]
+pub struct ManualTime {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualTime {
+    pub fn advance(&self, duration: Duration) {
+        self.nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl TimeSource for ManualTime {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
+
+/// The time of one player.
+#[derive(Debug, Clone, Copy, Default)]
+struct Side {
+    remaining: Duration,
+    /// The index of the current stage.
+    stage: usize,
+    /// The moves played in the current stage.
+    moves_in_stage: u32,
+}
+
+/// A chess clock for two players under a time control.
+///
+/// The clock of the side to move runs from `start` until it presses the clock after
+/// its move, which starts the other side's clock. A side that runs out of time has
+/// lost on time, which `flagged` tells as soon as it happens.
+#[derive(Debug, Clone)]
+pub struct Clock<T: TimeSource = WallTime> {
+    control: TimeControl,
+    source: T,
+    sides: [Side; 2],
+    /// The side whose clock runs, and since when.
+    running: Option<(Color, Duration)>,
+    flagged: Option<Color>,
+}
+
+impl Clock<WallTime> {
+    pub fn new(control: TimeControl) -> Self {
+        Clock::with_source(control, WallTime::default())
+    }
+}
+
+impl<T: TimeSource> Clock<T> {
+    pub fn with_source(control: TimeControl, source: T) -> Self {
+        let time = match &control {
+            TimeControl::Unlimited => Duration::MAX,
+            TimeControl::Stages(stages) => stages.first().map_or(Duration::MAX, |stage| stage.time),
+            TimeControl::Hourglass(time) => *time,
+        };
+        let side = Side {
+            remaining: time,
+            ..Side::default()
+        };
+        Clock {
+            control,
+            source,
+            sides: [side; 2],
+            running: None,
+            flagged: None,
+        }
+    }
+
+    pub fn time_control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    /// The side whose clock runs.
+    pub fn running(&self) -> Option<Color> {
+        self.running.map(|(color, _)| color)
+    }
+
+    /// Starts the clock of `color`, stopping the other one without counting it as a
+    /// move. The delay starts again.
+    pub fn start(&mut self, color: Color) {
+        self.pause();
+        if self.flagged.is_none() {
+            self.running = Some((color, self.source.now()));
+        }
+    }
+
+    /// Stops the clock, charging the running side for the time it used.
+    pub fn pause(&mut self) {
+        if let Some((color, _)) = self.running {
+            let (charged, _) = self.charge();
+            let side = &mut self.sides[color as usize];
+            side.remaining = side.remaining.saturating_sub(charged);
+            if side.remaining.is_zero() {
+                self.flagged = Some(color);
+            }
+            self.running = None;
+        }
+    }
+
+    /// The time the running side is charged for its move so far, and the time it has
+    /// been thinking.
+    fn charge(&self) -> (Duration, Duration) {
+        let Some((color, since)) = self.running else {
+            return (Duration::ZERO, Duration::ZERO);
+        };
+        let elapsed = self.source.now().saturating_sub(since);
+        let charged = match self.stage(color).map(|stage| stage.delay) {
+            Some(Delay::Simple(delay)) => elapsed.saturating_sub(delay),
+            _ => elapsed,
+        };
+        (charged, elapsed)
+    }
+
+    fn stage(&self, color: Color) -> Option<&Stage> {
+        match &self.control {
+            TimeControl::Stages(stages) => stages.get(self.sides[color as usize].stage),
+            _ => None,
+        }
+    }
+
+    /// Ends the move of the running side and starts the other side's clock. Returns
+    /// false if the side ran out of time, or no clock was running.
+    pub fn press(&mut self) -> bool {
+        let Some((color, _)) = self.running else {
+            return false;
+        };
+        let (charged, elapsed) = self.charge();
+        let index = color as usize;
+        if charged >= self.sides[index].remaining {
+            self.sides[index].remaining = Duration::ZERO;
+            self.flagged = Some(color);
+            self.running = None;
+            return false;
+        }
+        self.sides[index].remaining -= charged;
+
+        match &self.control {
+            TimeControl::Unlimited => {}
+            TimeControl::Hourglass(_) => {
+                let other = &mut self.sides[color.opposite() as usize];
+                other.remaining = other.remaining.saturating_add(elapsed);
+            }
+            TimeControl::Stages(stages) => {
+                let side = &mut self.sides[index];
+                let stage = stages[side.stage];
+                if let Delay::Bronstein(delay) = stage.delay {
+                    side.remaining += elapsed.min(delay);
+                }
+                side.remaining += stage.increment;
+                side.moves_in_stage += 1;
+                if stage.moves == Some(side.moves_in_stage) {
+                    // The last stage repeats
+                    side.stage = (side.stage + 1).min(stages.len() - 1);
+                    side.moves_in_stage = 0;
+                    side.remaining += stages[side.stage].time;
+                }
+            }
+        }
+        self.running = Some((color.opposite(), self.source.now()));
+        true
+    }
+
+    /// The time `color` has left, counting the move it is thinking on.
+    pub fn remaining(&self, color: Color) -> Duration {
+        let remaining = self.sides[color as usize].remaining;
+        match self.running {
+            Some((running, _)) if running == color => remaining.saturating_sub(self.charge().0),
+            Some((_, _)) if matches!(self.control, TimeControl::Hourglass(_)) => {
+                remaining.saturating_add(self.charge().1)
+            }
+            _ => remaining,
+        }
+    }
+
+    /// The increment `color` gets after its next move.
+    pub fn increment(&self, color: Color) -> Duration {
+        self.stage(color)
+            .map_or(Duration::ZERO, |stage| stage.increment)
+    }
+
+    /// The moves `color` has to play before its next time control, if there is one.
+    pub fn moves_to_go(&self, color: Color) -> Option<u32> {
+        let moves = self.stage(color)?.moves?;
+        Some(moves - self.sides[color as usize].moves_in_stage)
+    }
+
+    /// The side that ran out of time, if one did.
+    pub fn flagged(&self) -> Option<Color> {
+        self.flagged.or_else(|| {
+            let (color, _) = self.running?;
+            self.remaining(color).is_zero().then_some(color)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    fn start_clock(control: &str) -> (Clock<ManualTime>, ManualTime) {
+        let time = ManualTime::default();
+        let mut clock = Clock::with_source(control.parse().unwrap(), time.clone());
+        clock.start(Color::White);
+        (clock, time)
+    }
+
+    #[test]
+    fn test_pgn_round_trip() {
+        for text in [
+            "-",
+            "300",
+            "180+2",
+            "40/5400+30:1800+30",
+            "40/7200",
+            "*60",
+            "300d5",
+            "0.5+0.1b2",
+        ] {
+            let control: TimeControl = text.parse().unwrap();
+            assert_eq!(control.to_string(), text);
+        }
+        assert_eq!(
+            "40/5400:900".parse::<TimeControl>().unwrap(),
+            TimeControl::Stages(vec![
+                Stage {
+                    moves: Some(40),
+                    ..Stage::sudden_death(seconds(5400))
+                },
+                Stage::sudden_death(seconds(900)),
+            ])
+        );
+        assert_eq!(
+            TimeControl::fischer(seconds(180), seconds(2)).to_string(),
+            "180+2"
+        );
+        for invalid in [
+            "?", "", "0/60", "abc", "60+x", "-5", "1e30", "60+1e30", "inf",
+        ] {
+            assert!(invalid.parse::<TimeControl>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_fischer_increment() {
+        let (mut clock, time) = start_clock("60+2");
+        time.advance(seconds(10));
+        assert_eq!(clock.remaining(Color::White), seconds(50));
+        assert!(clock.press());
+        assert_eq!(clock.remaining(Color::White), seconds(52));
+        assert_eq!(clock.running(), Some(Color::Black));
+        time.advance(seconds(61));
+        assert_eq!(clock.flagged(), Some(Color::Black));
+        assert!(!clock.press());
+        assert_eq!(clock.remaining(Color::Black), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_delays() {
+        let (mut clock, time) = start_clock("60d5");
+        time.advance(seconds(3));
+        assert_eq!(clock.remaining(Color::White), seconds(60));
+        time.advance(seconds(4));
+        assert!(clock.press());
+        assert_eq!(clock.remaining(Color::White), seconds(58));
+
+        let (mut clock, time) = start_clock("60b5");
+        time.advance(seconds(3));
+        assert_eq!(clock.remaining(Color::White), seconds(57));
+        assert!(clock.press());
+        assert_eq!(clock.remaining(Color::White), seconds(60));
+        time.advance(seconds(8));
+        assert!(clock.press());
+        assert_eq!(clock.remaining(Color::Black), seconds(57));
+    }
+
+    #[test]
+    fn test_moves_per_period() {
+        let (mut clock, time) = start_clock("2/100+10:50");
+        assert_eq!(clock.moves_to_go(Color::White), Some(2));
+        for _ in 0..2 {
+            time.advance(seconds(20));
+            clock.press();
+            clock.press();
+        }
+        // 100 - 40 + 2 * 10 for the moves, and 50 for the second stage
+        assert_eq!(clock.remaining(Color::White), seconds(130));
+        assert_eq!(clock.moves_to_go(Color::White), None);
+        assert_eq!(clock.increment(Color::White), Duration::ZERO);
+
+        // The last stage repeats
+        let (mut clock, time) = start_clock("1/10");
+        time.advance(seconds(4));
+        clock.press();
+        assert_eq!(clock.remaining(Color::White), seconds(16));
+        assert_eq!(clock.moves_to_go(Color::White), Some(1));
+    }
+
+    #[test]
+    fn test_hourglass_and_pause() {
+        let (mut clock, time) = start_clock("*30");
+        time.advance(seconds(10));
+        assert_eq!(clock.remaining(Color::Black), seconds(40));
+        clock.press();
+        assert_eq!(clock.remaining(Color::White), seconds(20));
+        clock.pause();
+        time.advance(seconds(100));
+        assert_eq!(clock.remaining(Color::Black), seconds(40));
+        assert_eq!(clock.flagged(), None);
+
+        let (clock, time) = start_clock("-");
+        time.advance(seconds(1_000_000));
+        assert_eq!(clock.flagged(), None);
+    }
+}
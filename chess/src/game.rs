@@ -0,0 +1,432 @@
+//! A game of chess: a starting position and the moves played from it.
+//!
+//! `Game` keeps every move, so moves can be taken back and played again and the game
+//! can be shown at any ply. It also knows how the game stands: `status` tells mates
+//! and the draws by rule apart, and resignations and flag falls end it by decision.
+
+use crate::{
+    board::{Board, STARTING_FEN},
+    chess_move::ChessMove,
+    move_generator::MoveGenerator,
+    pgn::parse_san,
+    piece::Color,
+    variant::Variant,
+};
+
+/// How a game stands at its current ply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    /// The side to move is mated; the color is the winner.
+    Checkmate(Color),
+    Stalemate,
+    /// Fifty moves by each side without a capture or pawn move, a draw either player
+    /// may claim.
+    FiftyMove,
+    /// The position occurred for the third time, a draw either player may claim.
+    Repetition,
+    /// Neither side can mate.
+    InsufficientMaterial,
+    /// Seventy-five moves by each side without a capture or pawn move, drawn without
+    /// a claim.
+    SeventyFiveMove,
+    /// The position occurred for the fifth time, drawn without a claim.
+    FivefoldRepetition,
+    /// A player resigned; the color is the winner.
+    Resignation(Color),
+    /// A player ran out of time; the color is the winner.
+    Timeout(Color),
+    /// The rules of the variant ended the game, as a third check or a king on the
+    /// hill do; the winner, or None for a draw.
+    VariantEnd(Option<Color>),
+}
+
+impl GameStatus {
+    /// Returns true if no more moves can be played. The draws that have to be
+    /// claimed, `FiftyMove` and `Repetition`, do not end the game by themselves.
+    pub fn is_over(self) -> bool {
+        !matches!(
+            self,
+            GameStatus::Ongoing | GameStatus::FiftyMove | GameStatus::Repetition
+        )
+    }
+
+    /// The winner of a decided game.
+    pub fn winner(self) -> Option<Color> {
+        match self {
+            GameStatus::Checkmate(winner)
+            | GameStatus::Resignation(winner)
+            | GameStatus::Timeout(winner) => Some(winner),
+            GameStatus::VariantEnd(winner) => winner,
+            _ => None,
+        }
+    }
+
+    /// The result as written in PGN: `1-0`, `0-1`, `1/2-1/2`, or `*` while the game is
+    /// still going on, including a draw nobody has claimed yet.
+    pub fn result(self) -> &'static str {
+        match self.winner() {
+            Some(Color::White) => "1-0",
+            Some(Color::Black) => "0-1",
+            None if self.is_over() => "1/2-1/2",
+            None => "*",
+        }
+    }
+}
+
+/// A game from a starting position, with the moves played and those taken back.
+#[derive(Debug, Clone)]
+pub struct Game {
+    start: Board,
+    board: Board,
+    /// Every move played from the start, including the ones taken back, which
+    /// `redo` plays again until a different move is played.
+    history: Vec<ChessMove>,
+    /// How many moves of `history` lead to `board`.
+    ply: usize,
+    /// The hashes of the positions from the start up to `board`.
+    hashes: Vec<u64>,
+    /// A resignation or flag fall at the last ply of the game.
+    decided: Option<GameStatus>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game::new(Board::from_fen(STARTING_FEN).unwrap())
+    }
+}
+
+impl Game {
+    pub fn new(start: Board) -> Self {
+        Game {
+            hashes: vec![start.hash()],
+            board: start.clone(),
+            start,
+            history: Vec::new(),
+            ply: 0,
+            decided: None,
+        }
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Self, String> {
+        Board::from_fen(fen).map(Game::new)
+    }
+
+    /// The position the game started from.
+    pub fn start(&self) -> &Board {
+        &self.start
+    }
+
+    /// The position at the current ply.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// The number of moves leading to the current position.
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    /// The moves leading to the current position.
+    pub fn moves(&self) -> &[ChessMove] {
+        &self.history[..self.ply]
+    }
+
+    /// Every move of the game, including the ones taken back after the current ply.
+    pub fn history(&self) -> &[ChessMove] {
+        &self.history
+    }
+
+    /// Plays `mv` if it is legal. Moves taken back before are forgotten, unless `mv`
+    /// is the next of them.
+    pub fn play(&mut self, mv: ChessMove) -> Result<(), String> {
+        if self.status().is_over() {
+            return Err(format!("The game is over: {}", self.status().result()));
+        }
+        let legal = MoveGenerator::generate_legal_moves(&mut self.board)
+            .into_iter()
+            .find(|legal| legal.is_same_move(&mv))
+            .ok_or_else(|| format!("Illegal move: {}", mv.to_standard_notation()))?;
+        let replays = self
+            .history
+            .get(self.ply)
+            .is_some_and(|next| next.is_same_move(&legal));
+        if !replays {
+            self.history.truncate(self.ply);
+            self.history.push(legal);
+        }
+        self.advance();
+        Ok(())
+    }
+
+    /// Plays a move in UCI notation, such as `e2e4`, `e7e8q` or the drop `N@f3`.
+    pub fn play_uci(&mut self, uci: &str) -> Result<(), String> {
+        let mv = ChessMove::from_standard_notation(uci, &self.board)
+            .ok_or_else(|| format!("Invalid move: {}", uci))?;
+        self.play(mv)
+    }
+
+    /// Plays a move in standard algebraic notation, such as `Nf3` or `O-O`.
+    pub fn play_san(&mut self, san: &str) -> Result<(), String> {
+        let mv = parse_san(san, &mut self.board)?;
+        self.play(mv)
+    }
+
+    /// Makes the move of `history` at the current ply.
+    fn advance(&mut self) {
+        self.board.make_move(self.history[self.ply]);
+        self.ply += 1;
+        self.hashes.push(self.board.hash());
+    }
+
+    /// Takes back the last move. Returns false at the start of the game.
+    pub fn undo(&mut self) -> bool {
+        if self.ply == 0 {
+            return false;
+        }
+        self.decided = None;
+        self.board.unmake();
+        self.ply -= 1;
+        self.hashes.pop();
+        true
+    }
+
+    /// Plays the next of the moves taken back. Returns false if there is none.
+    pub fn redo(&mut self) -> bool {
+        if self.ply == self.history.len() {
+            return false;
+        }
+        self.advance();
+        true
+    }
+
+    /// Takes back or plays again moves until the game is at `ply`.
+    pub fn go_to(&mut self, ply: usize) -> Result<(), String> {
+        if ply > self.history.len() {
+            return Err(format!(
+                "Ply {} is beyond the {} moves of the game",
+                ply,
+                self.history.len()
+            ));
+        }
+        while self.ply > ply {
+            self.undo();
+        }
+        while self.ply < ply {
+            self.advance();
+        }
+        Ok(())
+    }
+
+    /// Ends the game with the resignation of `color`.
+    pub fn resign(&mut self, color: Color) {
+        self.decide(GameStatus::Resignation(color.opposite()));
+    }
+
+    /// Ends the game with `color` out of time.
+    pub fn flag(&mut self, color: Color) {
+        self.decide(GameStatus::Timeout(color.opposite()));
+    }
+
+    /// Ends the game at the current ply, forgetting the moves taken back.
+    fn decide(&mut self, status: GameStatus) {
+        if !self.status().is_over() {
+            self.history.truncate(self.ply);
+            self.decided = Some(status);
+        }
+    }
+
+    /// How many times the current position occurred, counting itself.
+    pub fn repetitions(&self) -> usize {
+        let current = self.hashes[self.ply];
+        // Positions before a capture or pawn move cannot come back
+        let reversible = (self.board.half_move_clock as usize).min(self.ply);
+        self.hashes[self.ply - reversible..]
+            .iter()
+            .filter(|&&hash| hash == current)
+            .count()
+    }
+
+    /// How the game stands at the current ply. A mate or stalemate on the move that
+    /// reaches a draw by rule takes precedence over it.
+    pub fn status(&self) -> GameStatus {
+        if let Some(status) = self.decided {
+            return status;
+        }
+        let mut board = self.board.clone();
+        if let Some(outcome) = board.variant_outcome() {
+            return GameStatus::VariantEnd(outcome.winner());
+        }
+        if MoveGenerator::generate_legal_moves(&mut board).is_empty() {
+            let side = board.side_to_move;
+            if board.variant == Variant::Antichess {
+                return GameStatus::VariantEnd(Some(side));
+            }
+            return match board.is_king_in_check(side) {
+                true => GameStatus::Checkmate(side.opposite()),
+                false => GameStatus::Stalemate,
+            };
+        }
+        let repetitions = self.repetitions();
+        let clock = self.board.half_move_clock;
+        if board.is_insufficient_material() {
+            GameStatus::InsufficientMaterial
+        } else if clock >= 150 {
+            GameStatus::SeventyFiveMove
+        } else if repetitions >= 5 {
+            GameStatus::FivefoldRepetition
+        } else if clock >= 100 {
+            GameStatus::FiftyMove
+        } else if repetitions >= 3 {
+            GameStatus::Repetition
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(game: &mut Game, moves: &str) {
+        for mv in moves.split_whitespace() {
+            game.play_uci(mv).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_checkmate_and_stalemate() {
+        let mut game = Game::default();
+        play(&mut game, "f2f3 e7e5 g2g4");
+        assert_eq!(game.status(), GameStatus::Ongoing);
+        game.play_san("Qh4#").unwrap();
+        assert_eq!(game.status(), GameStatus::Checkmate(Color::Black));
+        assert_eq!(game.status().result(), "0-1");
+        assert!(game.play_uci("a2a3").is_err());
+
+        let mut game = Game::from_fen("7k/8/6Q1/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert!(game.play_uci("g6g5").is_ok());
+        game.undo();
+        game.play_uci("g6f7").unwrap();
+        assert_eq!(game.status(), GameStatus::Stalemate);
+        assert_eq!(game.status().result(), "1/2-1/2");
+    }
+
+    #[test]
+    fn test_draws_by_rule() {
+        let mut game = Game::default();
+        let shuffle = "g1f3 g8f6 f3g1 f6g8";
+        play(&mut game, shuffle);
+        assert_eq!(game.repetitions(), 2);
+        play(&mut game, shuffle);
+        assert_eq!(game.status(), GameStatus::Repetition);
+        assert!(!game.status().is_over());
+        play(&mut game, shuffle);
+        play(&mut game, shuffle);
+        assert_eq!(game.status(), GameStatus::FivefoldRepetition);
+        assert!(game.play_uci("e2e4").is_err());
+
+        // The en passant square after e4 counts for nothing with no pawn to take on e3
+        let mut game = Game::default();
+        play(&mut game, "e2e4 g8f6 g1f3 f6g8 f3g1");
+        assert_eq!(game.repetitions(), 2);
+        play(&mut game, "g8f6 g1f3 f6g8 f3g1");
+        assert_eq!(game.status(), GameStatus::Repetition);
+        // With a pawn that could take it, the position after d5 is a different one
+        let mut game = Game::from_fen("4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1").unwrap();
+        play(&mut game, "d7d5 e1e2 e8e7 e2e1 e7e8 e1e2 e8e7 e2e1 e7e8");
+        assert_eq!(game.repetitions(), 2);
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/3RK3 w - - 100 80").unwrap();
+        assert_eq!(game.status(), GameStatus::FiftyMove);
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/3RK3 w - - 150 100").unwrap();
+        assert_eq!(game.status(), GameStatus::SeventyFiveMove);
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/3NK3 w - - 0 1").unwrap();
+        assert_eq!(game.status(), GameStatus::InsufficientMaterial);
+        // Mate on the move that reaches the limit still counts
+        let game = Game::from_fen("R3k3/8/4K3/8/8/8/8/8 b - - 150 100").unwrap();
+        assert_eq!(game.status(), GameStatus::Checkmate(Color::White));
+    }
+
+    #[test]
+    fn test_undo_redo_and_navigation() {
+        let mut game = Game::default();
+        play(&mut game, "e2e4 e7e5 g1f3 b8c6");
+        let fen = game.board().to_fen();
+        assert!(game.undo() && game.undo());
+        assert_eq!(
+            (game.ply(), game.moves().len(), game.history().len()),
+            (2, 2, 4)
+        );
+        assert!(game.redo());
+        game.go_to(4).unwrap();
+        assert_eq!(game.board().to_fen(), fen);
+        assert_eq!(game.board().moves().len(), 4);
+        assert_eq!(game.board().positions().len(), 4);
+        assert!(!game.redo());
+
+        game.go_to(0).unwrap();
+        assert_eq!(game.board().to_fen(), STARTING_FEN);
+        assert!(!game.undo());
+        assert!(game.go_to(5).is_err());
+
+        // Replaying the next move keeps the rest, another move forgets it
+        game.play_uci("e2e4").unwrap();
+        assert_eq!(game.history().len(), 4);
+        game.play_uci("c7c5").unwrap();
+        assert_eq!(game.history().len(), 2);
+        assert_eq!(game.moves()[1].to_standard_notation(), "c7c5");
+        assert!(game.play_uci("e4e6").is_err());
+        assert!(game.play_uci("e3e4").is_err());
+    }
+
+    #[test]
+    fn test_resignation_and_timeout() {
+        let mut game = Game::default();
+        play(&mut game, "e2e4 e7e5");
+        game.resign(Color::White);
+        assert_eq!(game.status(), GameStatus::Resignation(Color::Black));
+        assert_eq!(game.status().result(), "0-1");
+        assert!(game.play_uci("g1f3").is_err());
+        // Taking back the last move takes back the resignation too
+        game.undo();
+        assert_eq!(game.status(), GameStatus::Ongoing);
+        game.flag(Color::Black);
+        assert_eq!(game.status().winner(), Some(Color::White));
+    }
+
+    #[test]
+    fn test_variant_end() {
+        let fen = "4k3/8/8/8/8/8/8/3QK3 w - - 1+1 0 1";
+        let mut game = Game::new(Board::from_variant_fen(fen, Variant::ThreeCheck).unwrap());
+        play(&mut game, "d1d7");
+        assert_eq!(game.status(), GameStatus::VariantEnd(Some(Color::White)));
+        assert_eq!(game.status().result(), "1-0");
+        assert!(game.play_uci("e8d7").is_err());
+
+        // A stalemated side wins Antichess
+        let fen = "8/8/8/8/8/p7/P7/8 w - - 0 1";
+        let game = Game::new(Board::from_variant_fen(fen, Variant::Antichess).unwrap());
+        assert_eq!(game.status(), GameStatus::VariantEnd(Some(Color::White)));
+    }
+
+    #[test]
+    fn test_play_uci_drops() {
+        let fen = "4k3/8/8/8/8/8/8/4K3[Nn] w - - 0 1";
+        let mut game = Game::new(Board::from_fen(fen).unwrap());
+        play(&mut game, "N@f3 N@c6");
+        assert_eq!(game.board().to_fen(), "4k3/8/2n5/8/8/5N2/8/4K3[] w - - 2 2");
+        // Taken pieces go to the pocket, and only pieces in the pocket are dropped
+        assert!(game.play_uci("N@e2").is_err());
+        play(&mut game, "f3e5 c6e5 e1d1");
+        assert!(game.play_uci("Q@d4").is_err());
+        play(&mut game, "N@d3");
+
+        for invalid in [
+            "", "e2", "e2e", "e1e2qq", "e9e8", "d4d5", "N@", "N@i1", "é2e4",
+        ] {
+            assert!(game.play_uci(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
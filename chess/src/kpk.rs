@@ -0,0 +1,283 @@
+//! A bitbase of every king and pawn against king position, telling whether the side
+//! with the pawn wins. It is generated on first use by retrograde analysis: positions
+//! decided by a promotion, a stalemate or a capture of the pawn are classified first,
+//! then every other position takes the result of its best move until nothing changes.
+//! The positions left undecided are draws.
+//!
+//! The pawn is always White's. Positions with a black pawn are probed with the board
+//! flipped vertically and the colors swapped, see `probe`.
+
+use lazy_static::lazy_static;
+
+use crate::{move_generator::MoveGenerator, piece::Color};
+
+/// The pawn squares on files a to d and ranks 2 to 7; the other files mirror them.
+const PAWN_SQUARES: usize = 24;
+/// Positions by side to move, black king, white king and pawn square.
+const POSITIONS: usize = 2 * 64 * 64 * PAWN_SQUARES;
+
+lazy_static! {
+    static ref BITBASE: Vec<u64> = generate();
+}
+
+/// Returns true if White wins with its king on `wk`, its pawn on `wp` and the black
+/// king on `bk`, with `stm` to move. Illegal positions are not wins.
+///
+/// To probe a position where Black has the pawn, flip every square vertically with
+/// `square ^ 56` and swap the side to move.
+pub fn probe(wk: u8, wp: u8, bk: u8, stm: Color) -> bool {
+    let bit = index(stm, wk, wp, bk);
+    BITBASE[bit / 64] >> (bit % 64) & 1 == 1
+}
+
+/// The index of a position, with the pawn mirrored onto files a to d.
+fn index(stm: Color, wk: u8, wp: u8, bk: u8) -> usize {
+    let (wk, wp, bk) = if wp % 8 > 3 {
+        (wk ^ 7, wp ^ 7, bk ^ 7)
+    } else {
+        (wk, wp, bk)
+    };
+    let pawn = (wp / 8 - 1) as usize * 4 + (wp % 8) as usize;
+    ((stm as usize * 64 + bk as usize) * 64 + wk as usize) * PAWN_SQUARES + pawn
+}
+
+/// The squares of the position at `index`, with the pawn on files a to d.
+fn squares(index: usize) -> (Color, u8, u8, u8) {
+    let pawn = index % PAWN_SQUARES;
+    let wk = (index / PAWN_SQUARES % 64) as u8;
+    let bk = (index / PAWN_SQUARES / 64 % 64) as u8;
+    let stm = if index / PAWN_SQUARES / 64 / 64 == 0 {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let wp = ((pawn / 4 + 1) * 8 + pawn % 4) as u8;
+    (stm, wk, wp, bk)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+fn distance(a: u8, b: u8) -> u8 {
+    let files = (a % 8).abs_diff(b % 8);
+    let ranks = (a / 8).abs_diff(b / 8);
+    files.max(ranks)
+}
+
+/// Classifies the positions that are decided without looking at their moves.
+fn classify(stm: Color, wk: u8, wp: u8, bk: u8) -> Outcome {
+    let pawn_attacks = MoveGenerator::pawn_attacks(wp, Color::White).0;
+    if distance(wk, bk) <= 1
+        || wk == wp
+        || bk == wp
+        || (stm == Color::White && pawn_attacks >> bk & 1 == 1)
+    {
+        return Outcome::Invalid;
+    }
+
+    let white_king = MoveGenerator::king_attacks(wk);
+    match stm {
+        Color::White => {
+            // The pawn promotes and the queen cannot be taken at once
+            let queening = wp + 8;
+            let safe = distance(bk, queening) > 1 || white_king >> queening & 1 == 1;
+            if wp / 8 == 6 && wk != queening && bk != queening && safe {
+                return Outcome::Win;
+            }
+        }
+        Color::Black => {
+            let black_king = MoveGenerator::king_attacks(bk);
+            if black_king & !(white_king | pawn_attacks) == 0 {
+                return Outcome::Draw; // Stalemate
+            }
+            if (black_king & !white_king) >> wp & 1 == 1 {
+                return Outcome::Draw; // The pawn falls
+            }
+        }
+    }
+    Outcome::Unknown
+}
+
+/// Combines the outcomes of the moves of an undecided position.
+fn resolve(outcomes: &[Outcome], stm: Color, wk: u8, wp: u8, bk: u8) -> Outcome {
+    let mut children = Vec::with_capacity(10);
+    match stm {
+        Color::White => {
+            let mut targets = MoveGenerator::king_attacks(wk);
+            while targets != 0 {
+                let to = targets.trailing_zeros() as u8;
+                targets &= targets - 1;
+                children.push(index(Color::Black, to, wp, bk));
+            }
+            let push = wp + 8;
+            if wp / 8 < 6 && push != wk && push != bk {
+                children.push(index(Color::Black, wk, push, bk));
+                if wp / 8 == 1 && push + 8 != wk && push + 8 != bk {
+                    children.push(index(Color::Black, wk, push + 8, bk));
+                }
+            }
+        }
+        Color::Black => {
+            let mut targets = MoveGenerator::king_attacks(bk);
+            while targets != 0 {
+                let to = targets.trailing_zeros() as u8;
+                targets &= targets - 1;
+                children.push(index(Color::White, wk, wp, to));
+            }
+        }
+    }
+
+    let (good, bad) = match stm {
+        Color::White => (Outcome::Win, Outcome::Draw),
+        Color::Black => (Outcome::Draw, Outcome::Win),
+    };
+    let children = children.into_iter().map(|child| outcomes[child]);
+    let mut unknown = false;
+    for child in children {
+        if child == good {
+            return good;
+        }
+        unknown |= child == Outcome::Unknown;
+    }
+    if unknown {
+        Outcome::Unknown
+    } else {
+        bad
+    }
+}
+
+fn generate() -> Vec<u64> {
+    let mut outcomes: Vec<Outcome> = (0..POSITIONS)
+        .map(|index| {
+            let (stm, wk, wp, bk) = squares(index);
+            classify(stm, wk, wp, bk)
+        })
+        .collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..POSITIONS {
+            if outcomes[index] != Outcome::Unknown {
+                continue;
+            }
+            let (stm, wk, wp, bk) = squares(index);
+            let outcome = resolve(&outcomes, stm, wk, wp, bk);
+            if outcome != Outcome::Unknown {
+                outcomes[index] = outcome;
+                changed = true;
+            }
+        }
+    }
+
+    let mut bits = vec![0u64; POSITIONS / 64];
+    for (index, outcome) in outcomes.into_iter().enumerate() {
+        if outcome == Outcome::Win {
+            bits[index / 64] |= 1 << (index % 64);
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::Board, piece::PieceType};
+
+    fn probe_fen(fen: &str) -> bool {
+        let board = Board::from_fen(fen).unwrap();
+        let square = |color: Color, piece: PieceType| {
+            board.bitboards[color as usize][piece as usize].to_square()
+        };
+        probe(
+            square(Color::White, PieceType::King),
+            square(Color::White, PieceType::Pawn),
+            square(Color::Black, PieceType::King),
+            board.side_to_move,
+        )
+    }
+
+    #[test]
+    fn test_known_positions() {
+        // The king on the sixth rank in front of its pawn wins whoever is to move
+        assert!(probe_fen("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"));
+        assert!(probe_fen("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"));
+        // Behind the pawn the opposition decides
+        assert!(!probe_fen("8/8/8/8/8/4k3/4P3/4K3 w - - 0 1"));
+        assert!(probe_fen("8/8/8/4k3/8/4K3/4P3/8 b - - 0 1"));
+        assert!(!probe_fen("8/8/8/4k3/8/4K3/4P3/8 w - - 0 1"));
+        // A rook pawn is a draw once the king reaches the corner
+        assert!(!probe_fen("k7/8/8/8/8/8/P7/7K w - - 0 1"));
+        assert!(!probe_fen("7k/8/8/8/8/8/7P/K7 w - - 0 1"));
+        // Outside the square of the pawn, and inside with the pawn falling
+        assert!(probe_fen("8/8/8/8/P7/8/8/k6K w - - 0 1"));
+        assert!(!probe_fen("8/8/8/8/8/8/3kP3/7K b - - 0 1"));
+    }
+
+    #[test]
+    fn test_agrees_with_the_moves_of_the_board() {
+        // Every position is won exactly when the side to move has a winning move, or
+        // for Black, when it has none that draws
+        for index in (0..POSITIONS).step_by(61) {
+            let (stm, wk, wp, bk) = squares(index);
+            if classify(stm, wk, wp, bk) == Outcome::Invalid || wp / 8 == 6 {
+                continue;
+            }
+            let mut placement = ['.'; 64];
+            placement[wk as usize] = 'K';
+            placement[wp as usize] = 'P';
+            placement[bk as usize] = 'k';
+            let ranks: Vec<String> = (0..8)
+                .rev()
+                .map(|rank| {
+                    let row: String = placement[rank * 8..rank * 8 + 8].iter().collect();
+                    row.replace("........", "8")
+                        .replace(".......", "7")
+                        .replace("......", "6")
+                        .replace(".....", "5")
+                        .replace("....", "4")
+                        .replace("...", "3")
+                        .replace("..", "2")
+                        .replace('.', "1")
+                })
+                .collect();
+            let side = if stm == Color::White { "w" } else { "b" };
+            let fen = format!("{} {} - - 0 1", ranks.join("/"), side);
+            let mut board = Board::from_fen(&fen).unwrap();
+
+            let won_after = |board: &Board| {
+                let square = |color: Color, piece: PieceType| {
+                    board.bitboards[color as usize][piece as usize].to_square()
+                };
+                let pawns = board.bitboards[Color::White as usize][PieceType::Pawn as usize];
+                pawns.popcnt() == 1
+                    && probe(
+                        square(Color::White, PieceType::King),
+                        square(Color::White, PieceType::Pawn),
+                        square(Color::Black, PieceType::King),
+                        board.side_to_move,
+                    )
+            };
+            let moves = MoveGenerator::generate_legal_moves(&mut board);
+            let results: Vec<bool> = moves
+                .into_iter()
+                .map(|mv| {
+                    board.make_move(mv);
+                    let won = won_after(&board);
+                    board.unmake();
+                    won
+                })
+                .collect();
+            let expected = match stm {
+                Color::White => results.iter().any(|&won| won),
+                Color::Black => !results.is_empty() && results.iter().all(|&won| won),
+            };
+            assert_eq!(probe(wk, wp, bk, stm), expected, "{}", fen);
+        }
+    }
+}
@@ -2,8 +2,15 @@ extern crate lazy_static;
 mod bitboard;
 pub mod board;
 pub mod chess_move;
+pub mod clock;
+pub mod game;
+pub mod kpk;
 pub mod move_generator;
 pub mod perft;
+pub mod pgn;
 pub mod piece;
+pub mod polyglot;
+pub mod variant;
+mod zobrist;
 pub use board::{Board, STARTING_FEN};
 pub use piece::{Color, PieceType};
@@ -2,17 +2,49 @@ use crate::bitboard::BitBoard;
 use crate::board::Board;
 use crate::chess_move::{ChessMove, FLAG_CASTLE, FLAG_EN_PASSANT, FLAG_PROMOTION};
 use crate::piece::{Color, PieceType};
+use crate::variant::Variant;
 
 pub struct MoveGenerator;
 
+/// The pieces a pawn promotes to. The king only in Antichess.
+const PROMOTIONS: [PieceType; 5] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::King,
+];
+
 impl MoveGenerator {
     pub fn generate_legal_moves(board: &mut Board) -> Vec<ChessMove> {
+        // A variant game can end with moves left, such as with a king on the hill
+        if board.variant_outcome().is_some() {
+            return Vec::new();
+        }
         let moves = Self::generate_moves(board);
+        if board.variant == Variant::Antichess {
+            // No king to keep safe, but a capture has to be played when there is one
+            let captures: Vec<ChessMove> = moves
+                .iter()
+                .filter(|m| m.captured_piece.is_some())
+                .copied()
+                .collect();
+            return if captures.is_empty() { moves } else { captures };
+        }
         let mut legal_moves = Vec::new();
 
         for m in moves.into_iter() {
             board.make_move(m);
-            if !board.is_king_in_check(board.side_to_move.opposite()) {
+            let mover = board.side_to_move.opposite();
+            let legal = match board.variant {
+                Variant::Atomic => board.is_atomic_king_safe(mover),
+                // Giving check is not allowed in Racing Kings
+                Variant::RacingKings => {
+                    !board.is_king_in_check(mover) && !board.is_king_in_check(board.side_to_move)
+                }
+                _ => !board.is_king_in_check(mover),
+            };
+            if legal {
                 legal_moves.push(m);
             }
             board.unmake();
@@ -30,10 +62,32 @@ impl MoveGenerator {
         } else {
             Self::generate_moves_for_color(board, Color::Black, &mut moves);
         }
+        if board.variant == Variant::Crazyhouse {
+            Self::generate_drops(board, board.side_to_move, &mut moves);
+        }
 
         moves
     }
 
+    /// Generates the Crazyhouse drops of the pieces in the pocket of `color` on the
+    /// empty squares, pawns not on the first or eighth rank.
+    fn generate_drops(board: &Board, color: Color, moves: &mut Vec<ChessMove>) {
+        let back_ranks = 0xFF00_0000_0000_00FF;
+        for (piece, &count) in board.pockets[color as usize].iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let piece = PieceType::from(piece);
+            let mut targets = !board.combined.0;
+            if piece == PieceType::Pawn {
+                targets &= !back_ranks;
+            }
+            for square in BitBoard(targets) {
+                moves.push(ChessMove::drop(piece, square, board));
+            }
+        }
+    }
+
     /// Generates moves for a specific color.
     fn generate_moves_for_color(board: &Board, color: Color, moves: &mut Vec<ChessMove>) {
         // Iterate over all pieces of the given color and generate moves
@@ -195,6 +249,7 @@ impl MoveGenerator {
                         to,
                         captured_piece: Some(captured_piece),
                         promoted_piece,
+                        dropped_piece: None,
                         flags,
                         old_castling_rights: board.castling_rights,
                         old_en_passant_square: board.en_passant,
@@ -226,17 +281,17 @@ impl MoveGenerator {
             moves_bits &= moves_bits - 1; // Clear the least significant bit
 
             if (1u64 << to) & promotion_rank_mask != 0 {
-                // Handle promotions
-                for &promo_type in &[
-                    PieceType::Queen,
-                    PieceType::Rook,
-                    PieceType::Bishop,
-                    PieceType::Knight,
-                ] {
+                // Handle promotions, in Antichess to a king as well
+                let promotions = match board.variant {
+                    Variant::Antichess => &PROMOTIONS[..],
+                    _ => &PROMOTIONS[..4],
+                };
+                for &promo_type in promotions {
                     moves.push(ChessMove {
                         from: from as u8,
                         to,
                         promoted_piece: Some(promo_type),
+                        dropped_piece: None,
                         captured_piece: if is_capture {
                             Some(board.piece_at(to, color.opposite()).unwrap())
                         } else {
@@ -254,6 +309,7 @@ impl MoveGenerator {
                     from: from as u8,
                     to,
                     promoted_piece: None,
+                    dropped_piece: None,
                     captured_piece: if is_capture {
                         if is_en_passant {
                             Some(PieceType::Pawn)
@@ -332,6 +388,7 @@ impl MoveGenerator {
                 from,
                 to,
                 promoted_piece: None,
+                dropped_piece: None,
                 captured_piece: board.piece_at(to, color.opposite()),
                 flags: capture_flag.unwrap_or(0),
                 old_castling_rights: board.castling_rights,
@@ -540,86 +597,73 @@ impl MoveGenerator {
             let possible_moves = king_moves & !opponent_pieces; // Normal moves
             Self::generate_move_list(board, from, possible_moves, moves, color, None);
 
-            let possible_captures = king_moves & opponent_pieces; // Capture moves
-            Self::generate_move_list(board, from, possible_captures, moves, color, Some(0));
+            // Kings cannot capture in Atomic
+            if board.variant != Variant::Atomic {
+                let possible_captures = king_moves & opponent_pieces; // Capture moves
+                Self::generate_move_list(board, from, possible_captures, moves, color, Some(0));
+            }
 
             kings &= kings.0 - 1; // Remove this king from the set
 
             // Generate castling moves if applicable
-            if color == Color::White {
-                // White kingside castling
-                if board.castling_rights[0] && (board.combined.0 & 0x60) == 0 // Check if squares f1, g1 are clear
-                && !board.is_square_attacked(4, Color::Black)
-                && !board.is_square_attacked(5, Color::Black)
-                && !board.is_square_attacked(6, Color::Black)
-                {
-                    moves.push(ChessMove {
-                        from,
-                        to: 6,
-                        promoted_piece: None,
-                        captured_piece: None,
-                        flags: FLAG_CASTLE,
-                        old_castling_rights: board.castling_rights,
-                        old_en_passant_square: board.en_passant,
-                        old_halfmove_clock: board.half_move_clock,
-                    });
-                }
-                // White queenside castling
-                if board.castling_rights[1] && (board.combined.0 & 0xE) == 0 // Check if squares b1, c1, d1 are clear
-                && !board.is_square_attacked(4, Color::Black)
-                && !board.is_square_attacked(3, Color::Black)
-                && !board.is_square_attacked(2, Color::Black)
-                {
-                    moves.push(ChessMove {
-                        from,
-                        to: 2,
-                        promoted_piece: None,
-                        captured_piece: None,
-                        flags: FLAG_CASTLE,
-                        old_castling_rights: board.castling_rights,
-                        old_en_passant_square: board.en_passant,
-                        old_halfmove_clock: board.half_move_clock,
-                    });
-                }
-            } else {
-                // Black kingside castling
-                if board.castling_rights[2] && (board.combined.0 & 0x6000000000000000) == 0 // Check if squares f8, g8 are clear
-                && !board.is_square_attacked(60, Color::White)
-                && !board.is_square_attacked(61, Color::White)
-                && !board.is_square_attacked(62, Color::White)
-                {
-                    moves.push(ChessMove {
-                        from,
-                        to: 62,
-                        promoted_piece: None,
-                        captured_piece: None,
-                        flags: FLAG_CASTLE,
-                        old_castling_rights: board.castling_rights,
-                        old_en_passant_square: board.en_passant,
-                        old_halfmove_clock: board.half_move_clock,
-                    });
-                }
-                // Black queenside castling
-                if board.castling_rights[3] && (board.combined.0 & 0xE00000000000000) == 0 // Check if squares b8, c8, d8 are clear
-                && !board.is_square_attacked(60, Color::White)
-                && !board.is_square_attacked(59, Color::White)
-                && !board.is_square_attacked(58, Color::White)
-                {
-                    moves.push(ChessMove {
-                        from,
-                        to: 58,
-                        promoted_piece: None,
-                        captured_piece: None,
-                        flags: FLAG_CASTLE,
-                        old_castling_rights: board.castling_rights,
-                        old_en_passant_square: board.en_passant,
-                        old_halfmove_clock: board.half_move_clock,
-                    });
-                }
+            Self::generate_castling_moves(board, from, color, moves);
+        }
+    }
+
+    /// Generates the castling moves of the king on `from`, which go to the g or c file
+    /// with the rook next to it on the f or d file, wherever king and rook started as
+    /// in Chess960. The squares both pass over must be empty but for the two of them,
+    /// and the squares of the king's path must not be attacked. That the king is not
+    /// in check at the end is left to the legality check.
+    fn generate_castling_moves(board: &Board, from: u8, color: Color, moves: &mut Vec<ChessMove>) {
+        let back_rank = color as u8 * 56;
+        if from / 8 != back_rank / 8 {
+            return;
+        }
+        for (side, (king_file, rook_file)) in [(6, 5), (2, 3)].into_iter().enumerate() {
+            let right = 2 * color as usize + side;
+            let rook = board.castling_rook_square(right);
+            if !board.castling_rights[right]
+                || board.bitboards[color as usize][PieceType::Rook as usize] & (1 << rook) == 0
+            {
+                continue;
+            }
+            let to = back_rank + king_file;
+            let king_path = Self::span(from, to);
+            let rook_path = Self::span(rook, back_rank + rook_file);
+            let others = board.combined.0 & !(1 << from) & !(1 << rook);
+            if (king_path | rook_path) & others != 0 {
+                continue;
             }
+            // The king does not block attacks along its own path, and its destination
+            // is checked with the rook in place by the legality check
+            let without_king = BitBoard(board.combined.0 & !(1 << from));
+            let attacked = BitBoard(king_path & !(1 << to))
+                .into_iter()
+                .any(|square| board.is_king_attacked_on(square, color.opposite(), without_king));
+            if attacked {
+                continue;
+            }
+            moves.push(ChessMove {
+                from,
+                to,
+                promoted_piece: None,
+                dropped_piece: None,
+                captured_piece: None,
+                flags: FLAG_CASTLE,
+                old_castling_rights: board.castling_rights,
+                old_en_passant_square: board.en_passant,
+                old_halfmove_clock: board.half_move_clock,
+            });
         }
     }
 
+    /// The squares from `a` to `b` on a rank, both included.
+    fn span(a: u8, b: u8) -> u64 {
+        let (low, high) = (a.min(b), a.max(b));
+        (u64::MAX >> (63 - high)) & (u64::MAX << low)
+    }
+
     pub fn king_attacks(square: u8) -> u64 {
         let mut bit = 1u64 << square;
 
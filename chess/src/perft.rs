@@ -1,7 +1,4 @@
-use crate::{
-    board::Board,
-    move_generator::MoveGenerator,
-};
+use crate::{board::Board, move_generator::MoveGenerator};
 
 pub fn perft(depth: u32, board: &mut Board, is_top_level: bool) -> u64 {
     if depth == 0 {
@@ -32,3 +29,115 @@ pub fn perft(depth: u32, board: &mut Board, is_top_level: bool) -> u64 {
 
     nodes
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::STARTING_FEN, variant::Variant};
+
+    /// A position of a perft suite with its node counts by depth.
+    pub(crate) struct PerftCase {
+        pub id: String,
+        pub fen: String,
+        pub nodes: Vec<(u32, u64)>,
+    }
+
+    /// Reads a suite in the format of the `.perft` files, blocks of `id`, `epd` and
+    /// `perft <depth> <nodes>` lines. The EPD lacks the move counters, which are added.
+    pub(crate) fn read_suite(text: &str) -> Vec<PerftCase> {
+        let mut cases: Vec<PerftCase> = Vec::new();
+        for line in text.lines() {
+            let Some((keyword, rest)) = line.split_once(' ') else {
+                continue;
+            };
+            match keyword {
+                "id" => cases.push(PerftCase {
+                    id: rest.to_string(),
+                    fen: String::new(),
+                    nodes: Vec::new(),
+                }),
+                "epd" => {
+                    let last = rest.split_whitespace().last().unwrap_or_default();
+                    let counters = match last.parse::<u32>() {
+                        Ok(_) => "",
+                        Err(_) => " 0 1",
+                    };
+                    cases.last_mut().expect("An id before the epd").fen =
+                        format!("{}{}", rest, counters);
+                }
+                "perft" => {
+                    let (depth, nodes) = rest.split_once(' ').expect("A depth and a count");
+                    cases
+                        .last_mut()
+                        .expect("An id before the counts")
+                        .nodes
+                        .push((depth.parse().unwrap(), nodes.parse().unwrap()));
+                }
+                _ => {}
+            }
+        }
+        cases
+    }
+
+    /// Runs the depths of each case with at most `max_nodes` nodes.
+    pub(crate) fn run_suite(text: &str, variant: Variant, max_nodes: u64) {
+        for case in read_suite(text) {
+            let mut board = Board::from_variant_fen(&case.fen, variant).unwrap();
+            for &(depth, nodes) in case.nodes.iter().filter(|(_, nodes)| *nodes <= max_nodes) {
+                assert_eq!(
+                    perft(depth, &mut board, false),
+                    nodes,
+                    "{} at depth {}: {}",
+                    case.id,
+                    depth,
+                    case.fen
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_chess960_perft() {
+        run_suite(
+            include_str!("../perft/chess960.perft"),
+            Variant::Standard,
+            100_000,
+        );
+    }
+
+    #[test]
+    fn test_variant_perft() {
+        let suites = [
+            (include_str!("../perft/3check.perft"), Variant::ThreeCheck),
+            (
+                include_str!("../perft/racingkings.perft"),
+                Variant::RacingKings,
+            ),
+            (include_str!("../perft/antichess.perft"), Variant::Antichess),
+            (
+                include_str!("../perft/crazyhouse.perft"),
+                Variant::Crazyhouse,
+            ),
+            (include_str!("../perft/atomic.perft"), Variant::Atomic),
+        ];
+        for (text, variant) in suites {
+            run_suite(text, variant, 1_000_000);
+        }
+    }
+
+    #[test]
+    fn test_king_of_the_hill_perft() {
+        // No king reaches the hill within four plies of the start, so the counts
+        // are those of standard chess
+        let mut board = Board::from_variant_fen(STARTING_FEN, Variant::KingOfTheHill).unwrap();
+        for (depth, nodes) in [(1, 20), (2, 400), (3, 8_902), (4, 197_281)] {
+            assert_eq!(perft(depth, &mut board, false), nodes);
+        }
+        // Two of the eight king moves end the game on the hill, the other six
+        // leave the black king its three moves
+        let fen = "8/8/8/8/8/4K3/8/k7 w - - 0 1";
+        let mut board = Board::from_variant_fen(fen, Variant::KingOfTheHill).unwrap();
+        assert_eq!(perft(1, &mut board, false), 8);
+        assert_eq!(perft(2, &mut board, false), 18);
+    }
+}
@@ -0,0 +1,349 @@
+//! Reading games in Portable Game Notation and moves in standard algebraic notation.
+//!
+//! `PgnReader` goes through a collection game by game without loading it whole. It
+//! keeps the tag pairs and the moves of the main line as written, skipping comments,
+//! variations and annotation glyphs. `parse_san` turns the moves into `ChessMove`s.
+
+use std::io::{BufRead, Lines};
+
+use crate::{
+    board::{Board, STARTING_FEN},
+    chess_move::{ChessMove, FLAG_CASTLE},
+    move_generator::MoveGenerator,
+    piece::PieceType,
+};
+
+/// A game of a PGN file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PgnGame {
+    /// The tag pairs in the order of the file, such as `("White", "Carlsen")`.
+    pub tags: Vec<(String, String)>,
+    /// The moves of the main line in standard algebraic notation.
+    pub moves: Vec<String>,
+    /// The result that ends the movetext: `1-0`, `0-1`, `1/2-1/2` or `*`.
+    pub result: String,
+}
+
+impl PgnGame {
+    /// The value of the tag `name`, if the game has it.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The position the game starts from, given by its FEN tag or else the standard
+    /// starting position.
+    pub fn start(&self) -> Result<Board, String> {
+        Board::from_fen(self.tag("FEN").unwrap_or(STARTING_FEN))
+    }
+
+    /// Plays the moves of the game from its start and returns them, stopping with an
+    /// error at the first move that is not legal.
+    pub fn replay(&self) -> Result<Vec<ChessMove>, String> {
+        let mut board = self.start()?;
+        let mut moves = Vec::with_capacity(self.moves.len());
+        for san in &self.moves {
+            let mv = parse_san(san, &mut board)?;
+            board.make_move(mv);
+            moves.push(mv);
+        }
+        Ok(moves)
+    }
+}
+
+/// Reads the games of a PGN collection one at a time.
+pub struct PgnReader<R> {
+    lines: Lines<R>,
+    game: PgnGame,
+    /// Whether the movetext of `game` has begun.
+    in_movetext: bool,
+    /// Whether a `{` comment is still open at the end of the last line.
+    in_comment: bool,
+    /// How many variations are open.
+    variations: u32,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        PgnReader {
+            lines: reader.lines(),
+            game: PgnGame::default(),
+            in_movetext: false,
+            in_comment: false,
+            variations: 0,
+        }
+    }
+
+    fn take_game(&mut self) -> PgnGame {
+        self.in_movetext = false;
+        self.in_comment = false;
+        self.variations = 0;
+        std::mem::take(&mut self.game)
+    }
+
+    /// Reads the movetext tokens of `line`. Returns true when the game ended.
+    fn read_movetext(&mut self, line: &str) -> bool {
+        let mut token = String::new();
+        for c in line.chars().chain([' ']) {
+            if self.in_comment {
+                self.in_comment = c != '}';
+                continue;
+            }
+            let separator = c.is_whitespace() || "{}();".contains(c);
+            if !separator {
+                token.push(c);
+                continue;
+            }
+            if !token.is_empty() {
+                if self.read_token(&token) {
+                    return true;
+                }
+                token.clear();
+            }
+            match c {
+                '{' => self.in_comment = true,
+                '(' => self.variations += 1,
+                ')' => self.variations = self.variations.saturating_sub(1),
+                // The rest of the line is a comment
+                ';' => break,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Reads a token of the movetext. Returns true when it is the result.
+    fn read_token(&mut self, token: &str) -> bool {
+        if self.variations > 0 {
+            return false;
+        }
+        self.in_movetext = true;
+        if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") {
+            self.game.result = token.to_string();
+            return true;
+        }
+        // Move numbers may stick to the move, as in 12.e4 or 12...e5
+        let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        if !san.is_empty() && !san.starts_with('$') {
+            self.game.moves.push(san.to_string());
+        }
+        false
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(line) = self.lines.next() else {
+                // A last game without a result
+                let game = self.take_game();
+                let empty = game.tags.is_empty() && game.moves.is_empty();
+                return (!empty).then_some(Ok(game));
+            };
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.to_string())),
+            };
+            let trimmed = line.trim();
+            if trimmed.starts_with('%') {
+                continue;
+            }
+            if trimmed.starts_with('[') && !self.in_comment && self.variations == 0 {
+                // A game whose result is missing ends at the tags of the next one
+                let unfinished = self.in_movetext.then(|| self.take_game());
+                match parse_tag(trimmed) {
+                    Some(tag) => self.game.tags.push(tag),
+                    None => return Some(Err(format!("Invalid tag pair: {}", trimmed))),
+                }
+                if let Some(game) = unfinished {
+                    return Some(Ok(game));
+                }
+                continue;
+            }
+            if self.read_movetext(&line) {
+                return Some(Ok(self.take_game()));
+            }
+        }
+    }
+}
+
+/// Parses a tag pair such as `[Event "Casual game"]`.
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.trim_end().strip_suffix(']')?;
+    let (name, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let value = value.replace("\\\"", "\"").replace("\\\\", "\\");
+    Some((name.to_string(), value))
+}
+
+/// Returns the legal move of `board` written as `san` in standard algebraic notation,
+/// such as `Nbd7`, `exd6`, `e8=Q+`, `O-O` or the Crazyhouse drop `N@f3`, where a pawn
+/// drop may leave out the letter as in `@e4`. Check and annotation marks are ignored.
+pub fn parse_san(san: &str, board: &mut Board) -> Result<ChessMove, String> {
+    let invalid = || format!("Invalid move: {}", san);
+    let text = san.trim_end_matches(['+', '#', '!', '?']);
+    let moves = MoveGenerator::generate_legal_moves(board);
+
+    let castling_file = match text {
+        "O-O" | "0-0" => Some(6),
+        "O-O-O" | "0-0-0" => Some(2),
+        _ => None,
+    };
+    if let Some(file) = castling_file {
+        return moves
+            .into_iter()
+            .find(|mv| mv.flags & FLAG_CASTLE != 0 && mv.to % 8 == file)
+            .ok_or_else(|| format!("Illegal move: {}", san));
+    }
+
+    if let Some((letter, square)) = text.split_once('@') {
+        let letter = if letter.is_empty() { "P" } else { letter };
+        let drop = ChessMove::from_standard_notation(&format!("{}@{}", letter, square), board)
+            .ok_or_else(invalid)?;
+        return moves
+            .into_iter()
+            .find(|mv| mv.is_same_move(&drop))
+            .ok_or_else(|| format!("Illegal move: {}", san));
+    }
+
+    let piece_type = |letter: char| match letter {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    };
+    let mut text = text;
+    let piece = match text.chars().next().and_then(piece_type) {
+        Some(piece) => {
+            text = &text[1..];
+            piece
+        }
+        None => PieceType::Pawn,
+    };
+    // Promotions are written e8=Q, and sometimes e8Q
+    let mut promotion = None;
+    if let Some(letter) = text.chars().last().filter(|c| c.is_ascii_uppercase()) {
+        promotion = Some(piece_type(letter).ok_or_else(invalid)?);
+        text = text[..text.len() - 1].trim_end_matches('=');
+    }
+
+    if text.len() < 2 || !text.is_ascii() {
+        return Err(invalid());
+    }
+    let (origin, target) = text.split_at(text.len() - 2);
+    let target = target.as_bytes();
+    if !(b'a'..=b'h').contains(&target[0]) || !(b'1'..=b'8').contains(&target[1]) {
+        return Err(invalid());
+    }
+    let to = (target[1] - b'1') * 8 + (target[0] - b'a');
+    let mut from_file = None;
+    let mut from_rank = None;
+    for c in origin.bytes().filter(|&c| c != b'x') {
+        match c {
+            b'a'..=b'h' => from_file = Some(c - b'a'),
+            b'1'..=b'8' => from_rank = Some(c - b'1'),
+            _ => return Err(invalid()),
+        }
+    }
+
+    let side = board.side_to_move;
+    let mut candidates = moves.into_iter().filter(|mv| {
+        mv.to == to
+            && mv.flags & FLAG_CASTLE == 0
+            && board.piece_at(mv.from, side) == Some(piece)
+            && mv.promoted_piece == promotion
+            && from_file.is_none_or(|file| mv.from % 8 == file)
+            && from_rank.is_none_or(|rank| mv.from / 8 == rank)
+    });
+    match (candidates.next(), candidates.next()) {
+        (Some(mv), None) => Ok(mv),
+        (Some(_), Some(_)) => Err(format!("Ambiguous move: {}", san)),
+        (None, _) => Err(format!("Illegal move: {}", san)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAMES: &str = r#"[Event "Casual game"]
+[White "Anderssen, \"The Immortal\""]
+[Result "1-0"]
+
+1. e4 e5 2. f4 exf4 {King's Gambit Accepted} 3. Bc4 Qh4+ (3... d5 4. Bxd5)
+4. Kf1 b5?! $6 5.Bxb5 ; a line comment Nf6
+Nf6 1-0
+
+% an escaped line
+[Event "No result"]
+[FEN "4k3/P7/8/8/8/8/8/4K2R w K - 0 1"]
+
+1. a8=Q+ Kd7 2. O-O
+
+[Event "Last"]
+1.d4 d5 *
+"#;
+
+    #[test]
+    fn test_reads_games() {
+        let games: Vec<PgnGame> = PgnReader::new(GAMES.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(games.len(), 3);
+
+        assert_eq!(games[0].tag("White"), Some("Anderssen, \"The Immortal\""));
+        assert_eq!(
+            games[0].moves,
+            ["e4", "e5", "f4", "exf4", "Bc4", "Qh4+", "Kf1", "b5?!", "Bxb5", "Nf6"]
+        );
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[0].replay().unwrap().len(), 10);
+
+        assert_eq!(games[1].moves, ["a8=Q+", "Kd7", "O-O"]);
+        assert_eq!(games[1].result, "");
+        let moves = games[1].replay().unwrap();
+        assert_eq!(moves[0].promoted_piece, Some(PieceType::Queen));
+        assert_eq!(moves[2].to_standard_notation(), "e1g1");
+
+        assert_eq!((games[2].moves.len(), games[2].result.as_str()), (2, "*"));
+    }
+
+    #[test]
+    fn test_parse_san() {
+        let mut board =
+            Board::from_fen("r3k2r/1P3ppp/8/3pP3/8/2N3N1/5PPP/R3K2R w KQkq d6 0 1").unwrap();
+        let uci = |san: &str, board: &mut Board| {
+            parse_san(san, board).map(|mv| mv.to_standard_notation())
+        };
+        assert_eq!(uci("Nf5", &mut board).unwrap(), "g3f5");
+        assert_eq!(uci("Nge4", &mut board).unwrap(), "g3e4");
+        assert_eq!(uci("Nce4", &mut board).unwrap(), "c3e4");
+        assert_eq!(uci("Ncxd5", &mut board).unwrap(), "c3d5");
+        assert_eq!(uci("exd6", &mut board).unwrap(), "e5d6 e.p.");
+        assert_eq!(uci("bxa8=N", &mut board).unwrap(), "b7a8n");
+        assert_eq!(uci("b8Q", &mut board).unwrap(), "b7b8q");
+        assert_eq!(uci("O-O-O", &mut board).unwrap(), "e1c1");
+        assert_eq!(uci("Rxa8+", &mut board).unwrap(), "a1a8");
+        assert_eq!(
+            uci("Ne2", &mut board),
+            Err("Ambiguous move: Ne2".to_string())
+        );
+        assert_eq!(uci("Ke3", &mut board), Err("Illegal move: Ke3".to_string()));
+        assert!(uci("Zz9", &mut board).is_err());
+
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/4K3[Np] b - - 0 1").unwrap();
+        assert_eq!(uci("@e4", &mut board).unwrap(), "P@e4");
+        assert_eq!(uci("p@e4", &mut board).unwrap(), "P@e4");
+        assert_eq!(
+            uci("N@f3", &mut board),
+            Err("Illegal move: N@f3".to_string())
+        );
+        assert!(uci("@e9", &mut board).is_err());
+    }
+}
@@ -0,0 +1,349 @@
+//! Rule variants of chess.
+//!
+//! A `Board` plays by the rules of its `variant`, standard chess unless set otherwise.
+//! The variants change how a game ends, and some of them which moves are legal:
+//!
+//! * Three-check: the third check wins. The checks each side still has to give are
+//!   part of the FEN, as in `rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1`.
+//! * King of the Hill: a king reaching d4, e4, d5 or e5 wins.
+//! * Racing Kings: there are no pawns, no move may give check, and the first king to
+//!   reach the eighth rank wins. When White gets there first Black has one more move
+//!   to reach it as well and draw.
+//! * Antichess: captures are compulsory and the king is an ordinary piece, which can
+//!   be captured and promoted to. Losing every piece or being stalemated wins.
+//! * Crazyhouse: captured pieces go to the pocket of the capturing side, which can drop
+//!   them back on an empty square instead of moving. Promoted pieces go back as pawns.
+//!   Pockets are written after the placement, as in `.../RNBQKBNR[Qnp] w KQkq - 0 1`,
+//!   and promoted pieces are marked with a `~`.
+//! * Atomic: a capture explodes the capturing piece and every piece but the pawns next
+//!   to the capture square. Kings cannot capture, and a king next to the enemy king
+//!   cannot be in check. The side whose king explodes loses.
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+    bitboard::BitBoard,
+    board::{Board, STARTING_FEN},
+    move_generator::MoveGenerator,
+    piece::{Color, PieceType},
+};
+
+/// The squares of the hill in King of the Hill: d4, e4, d5 and e5.
+const CENTER: u64 = 0x0000_0018_1800_0000;
+/// The eighth rank, the goal of Racing Kings.
+const GOAL: u64 = 0xFF00_0000_0000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    ThreeCheck,
+    KingOfTheHill,
+    RacingKings,
+    Antichess,
+    Crazyhouse,
+    Atomic,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 7] = [
+        Variant::Standard,
+        Variant::ThreeCheck,
+        Variant::KingOfTheHill,
+        Variant::RacingKings,
+        Variant::Antichess,
+        Variant::Crazyhouse,
+        Variant::Atomic,
+    ];
+
+    /// The position games of the variant start from.
+    pub fn starting_fen(self) -> &'static str {
+        match self {
+            Variant::Standard
+            | Variant::ThreeCheck
+            | Variant::KingOfTheHill
+            | Variant::Crazyhouse
+            | Variant::Atomic => STARTING_FEN,
+            Variant::RacingKings => "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+            Variant::Antichess => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        }
+    }
+}
+
+/// Variants are named as in the `UCI_Variant` option: `chess`, `3check`,
+/// `kingofthehill`, `racingkings`, `antichess`, `crazyhouse` and `atomic`.
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Variant::Standard => "chess",
+            Variant::ThreeCheck => "3check",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::RacingKings => "racingkings",
+            Variant::Antichess => "antichess",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::Atomic => "atomic",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    /// Also takes the names other programs use, such as `standard` or `giveaway`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "chess" | "standard" | "normal" => Ok(Variant::Standard),
+            "3check" | "threecheck" | "three-check" => Ok(Variant::ThreeCheck),
+            "kingofthehill" | "koth" | "king of the hill" => Ok(Variant::KingOfTheHill),
+            "racingkings" | "racing kings" => Ok(Variant::RacingKings),
+            "antichess" | "giveaway" => Ok(Variant::Antichess),
+            "crazyhouse" | "zh" => Ok(Variant::Crazyhouse),
+            "atomic" => Ok(Variant::Atomic),
+            _ => Err(format!("Unknown variant: {}", name)),
+        }
+    }
+}
+
+/// How a game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Win(Color),
+    Draw,
+}
+
+impl Outcome {
+    pub fn winner(self) -> Option<Color> {
+        match self {
+            Outcome::Win(winner) => Some(winner),
+            Outcome::Draw => None,
+        }
+    }
+}
+
+// Game ends by the rules of the variant
+impl Board {
+    /// Returns how the variant's own rules ended the game, such as by a third check,
+    /// a king on the hill or an exploded king, or None while it goes on. Mates and
+    /// stalemates are not included since they need the legal moves, see
+    /// `outcome_without_moves`.
+    pub fn variant_outcome(&self) -> Option<Outcome> {
+        let kings = |color: Color| self.bitboards[color as usize][PieceType::King as usize];
+        match self.variant {
+            Variant::Standard | Variant::Crazyhouse => None,
+            Variant::ThreeCheck => [Color::White, Color::Black]
+                .into_iter()
+                .find(|&color| self.remaining_checks[color as usize] == 0)
+                .map(Outcome::Win),
+            Variant::KingOfTheHill => [Color::White, Color::Black]
+                .into_iter()
+                .find(|&color| kings(color) & CENTER != 0)
+                .map(Outcome::Win),
+            Variant::Atomic => [Color::White, Color::Black]
+                .into_iter()
+                .find(|&color| kings(color.opposite()) == 0)
+                .map(Outcome::Win),
+            Variant::RacingKings => {
+                let white = kings(Color::White) & GOAL != 0;
+                let black = kings(Color::Black) & GOAL != 0;
+                match (white, black) {
+                    (true, true) => Some(Outcome::Draw),
+                    (false, true) => Some(Outcome::Win(Color::Black)),
+                    (true, false) if self.side_to_move == Color::Black => {
+                        // Black still gets its move, and draws if its king gets there too
+                        let king = kings(Color::Black).to_square();
+                        let targets = MoveGenerator::king_attacks(king)
+                            & GOAL
+                            & !self.occupied[Color::Black as usize].0;
+                        let catches_up = BitBoard(targets)
+                            .into_iter()
+                            .any(|square| !self.is_square_attacked(square, Color::White));
+                        (!catches_up).then_some(Outcome::Win(Color::White))
+                    }
+                    (true, false) => Some(Outcome::Win(Color::White)),
+                    (false, false) => None,
+                }
+            }
+            Variant::Antichess => {
+                let side = self.side_to_move;
+                (self.occupied[side as usize] == 0).then_some(Outcome::Win(side))
+            }
+        }
+    }
+
+    /// Returns the result of a position where the side to move has no legal move:
+    /// mate, stalemate, a win for the stalemated side in Antichess, or the end the
+    /// variant made of the game, which leaves no moves either.
+    pub fn outcome_without_moves(&self) -> Outcome {
+        if let Some(outcome) = self.variant_outcome() {
+            return outcome;
+        }
+        let side = self.side_to_move;
+        match self.variant {
+            Variant::Antichess => Outcome::Win(side),
+            _ if self.is_king_in_check(side) => Outcome::Win(side.opposite()),
+            _ => Outcome::Draw,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_move::ChessMove;
+
+    fn board(fen: &str, variant: Variant) -> Board {
+        Board::from_variant_fen(fen, variant).unwrap()
+    }
+
+    #[test]
+    fn test_names() {
+        for variant in Variant::ALL {
+            assert_eq!(variant.to_string().parse::<Variant>(), Ok(variant));
+        }
+        assert_eq!("Giveaway".parse::<Variant>(), Ok(Variant::Antichess));
+        assert!("horde".parse::<Variant>().is_err());
+    }
+
+    #[test]
+    fn test_three_check_counts_checks() {
+        let fen = "4k3/8/8/8/8/8/8/3QK3 w - - 1+3 0 1";
+        let mut board = board(fen, Variant::ThreeCheck);
+        assert_eq!(board.remaining_checks, [1, 3]);
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.variant_outcome(), None);
+
+        let check = MoveGenerator::generate_legal_moves(&mut board)
+            .into_iter()
+            .find(|mv| mv.to_standard_notation() == "d1d7")
+            .unwrap();
+        let hash = board.hash();
+        board.make_move(check);
+        assert_eq!(board.remaining_checks, [0, 3]);
+        assert_eq!(board.variant_outcome(), Some(Outcome::Win(Color::White)));
+        assert!(MoveGenerator::generate_legal_moves(&mut board).is_empty());
+        board.unmake();
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.hash(), hash);
+    }
+
+    #[test]
+    fn test_king_of_the_hill() {
+        let mut board = board("4k3/8/8/8/8/4K3/8/8 w - - 0 1", Variant::KingOfTheHill);
+        let center = MoveGenerator::generate_legal_moves(&mut board)
+            .into_iter()
+            .find(|mv| mv.to_standard_notation() == "e3e4")
+            .unwrap();
+        board.make_move(center);
+        assert_eq!(board.outcome_without_moves(), Outcome::Win(Color::White));
+        assert!(!board.is_insufficient_material());
+    }
+
+    #[test]
+    fn test_racing_kings_goal() {
+        // Black can still reach the eighth rank with its last move
+        let racing = |fen| board(fen, Variant::RacingKings).variant_outcome();
+        assert_eq!(racing("4K3/k7/8/8/8/8/8/8 b - - 0 1"), None);
+        assert_eq!(
+            racing("4K3/8/8/k7/8/8/8/8 b - - 0 1"),
+            Some(Outcome::Win(Color::White))
+        );
+        assert_eq!(racing("K6k/8/8/8/8/8/8/8 w - - 0 1"), Some(Outcome::Draw));
+        assert_eq!(
+            racing("7k/8/8/8/8/8/8/K7 w - - 0 1"),
+            Some(Outcome::Win(Color::Black))
+        );
+
+        // Giving check is not allowed
+        let mut board = board("8/8/8/8/8/8/k7/6RK w - - 0 1", Variant::RacingKings);
+        let moves = MoveGenerator::generate_legal_moves(&mut board);
+        assert!(moves.iter().all(|mv| mv.to_standard_notation() != "g1g2"));
+    }
+
+    #[test]
+    fn test_antichess_captures_are_compulsory() {
+        let mut board = board("8/8/8/3p4/4P3/8/8/K7 w - - 0 1", Variant::Antichess);
+        let moves: Vec<String> = MoveGenerator::generate_legal_moves(&mut board)
+            .iter()
+            .map(|mv| mv.to_standard_notation())
+            .collect();
+        assert_eq!(moves, ["e4d5"]);
+
+        let mut lone = board_after(&mut board, "e4d5");
+        assert_eq!(lone.variant_outcome(), Some(Outcome::Win(Color::Black)));
+        assert!(MoveGenerator::generate_legal_moves(&mut lone).is_empty());
+        assert!(Board::from_variant_fen(STARTING_FEN, Variant::Antichess).is_err());
+    }
+
+    #[test]
+    fn test_crazyhouse_pockets() {
+        let fen = "4k3/1Q~6/8/8/4b3/8/K7/8[Nn] b - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        assert_eq!(board.variant, Variant::Crazyhouse);
+        assert_eq!(board.to_fen(), fen);
+        let ninth_rank = Board::from_fen("4k3/1Q~6/8/8/4b3/8/K7/8/Nn b - - 0 1").unwrap();
+        assert_eq!(ninth_rank.to_fen(), fen);
+        assert!(Board::from_variant_fen(fen, Variant::Standard).is_err());
+
+        // The promoted queen goes to the pocket as a pawn
+        let hash = board.hash();
+        let after = board_after(&mut board, "e4b7");
+        assert_eq!(after.to_fen(), "4k3/1b6/8/8/8/8/K7/8[Nnp] w - - 0 2");
+
+        let drop = ChessMove::from_standard_notation("N@c3", &board).unwrap();
+        assert_eq!(drop.to_standard_notation(), "N@c3");
+        let moves = MoveGenerator::generate_legal_moves(&mut board);
+        assert!(moves.iter().any(|mv| mv.is_same_move(&drop)));
+        board.make_move(drop);
+        assert_eq!(board.to_fen(), "4k3/1Q~6/8/8/4b3/2n5/K7/8[N] w - - 1 2");
+        board.unmake();
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.hash(), hash);
+
+        // Pawns are only dropped on the 48 squares of the second to seventh ranks
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/4K3[P] w - - 0 1").unwrap();
+        let drops = MoveGenerator::generate_legal_moves(&mut board)
+            .into_iter()
+            .filter(|mv| mv.dropped_piece.is_some())
+            .count();
+        assert_eq!(drops, 48);
+    }
+
+    #[test]
+    fn test_atomic_explosions() {
+        // The knight, the bishops and the black knight go up, the pawns next to d5 stay
+        let fen = "4k3/8/2n1p3/3b4/3PB3/2N5/8/4K3 w - - 0 1";
+        let mut blast = board(fen, Variant::Atomic);
+        let hash = blast.hash();
+        let capture = ChessMove::from_standard_notation("c3d5", &blast).unwrap();
+        blast.make_move(capture);
+        assert_eq!(blast.to_fen(), "4k3/8/4p3/8/3P4/8/8/4K3 b - - 0 1");
+        blast.unmake();
+        assert_eq!(blast.to_fen(), fen);
+        assert_eq!(blast.hash(), hash);
+
+        // Touching kings give no check, and a king cannot capture
+        let mut kings = board("8/8/8/8/8/8/4k3/r3K3 w - - 0 1", Variant::Atomic);
+        assert!(!kings.is_king_in_check(Color::White));
+        let moves = MoveGenerator::generate_legal_moves(&mut kings);
+        assert!(!moves.is_empty());
+        assert!(moves.iter().all(|mv| mv.captured_piece.is_none()));
+
+        // Blowing up the king wins
+        let mut board = board("4k3/4p3/8/8/8/8/8/4QK2 w - - 0 1", Variant::Atomic);
+        let mut exploded = board_after(&mut board, "e1e7");
+        assert_eq!(exploded.to_fen(), "8/8/8/8/8/8/8/5K2 b - - 0 1");
+        assert_eq!(exploded.variant_outcome(), Some(Outcome::Win(Color::White)));
+        assert!(MoveGenerator::generate_legal_moves(&mut exploded).is_empty());
+    }
+
+    fn board_after(board: &mut Board, uci: &str) -> Board {
+        let mv = MoveGenerator::generate_legal_moves(board)
+            .into_iter()
+            .find(|mv| mv.to_standard_notation() == uci)
+            .unwrap();
+        let mut after = board.clone();
+        after.make_move(mv);
+        after
+    }
+}
@@ -0,0 +1,64 @@
+use lazy_static::lazy_static;
+
+/// Random keys used to compute the Zobrist hash of a position.
+pub(crate) struct ZobristKeys {
+    pub pieces: [[[u64; 64]; 6]; 2], // Indexed by [Color][PieceType][Square]
+    pub castling: [u64; 4],
+    pub en_passant: [u64; 8], // Indexed by the file of the en passant square
+    pub side_to_move: u64,
+    pub remaining_checks: [[u64; 4]; 2], // Indexed by [Color][checks left], in Three-check
+    pub pockets: [[[u64; 17]; 5]; 2],    // Indexed by [Color][PieceType][count], in Crazyhouse
+}
+
+lazy_static! {
+    pub(crate) static ref KEYS: ZobristKeys = ZobristKeys::new();
+}
+
+impl ZobristKeys {
+    /// Generates the keys from a fixed seed so that hashes are stable between runs.
+    fn new() -> Self {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next = || {
+            // xorshift64*
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        };
+
+        let mut pieces = [[[0u64; 64]; 6]; 2];
+        for color in pieces.iter_mut() {
+            for piece in color.iter_mut() {
+                for square in piece.iter_mut() {
+                    *square = next();
+                }
+            }
+        }
+        let mut castling = [0u64; 4];
+        for key in castling.iter_mut() {
+            *key = next();
+        }
+        let mut en_passant = [0u64; 8];
+        for key in en_passant.iter_mut() {
+            *key = next();
+        }
+        let side_to_move = next();
+        let mut remaining_checks = [[0u64; 4]; 2];
+        for key in remaining_checks.iter_mut().flatten() {
+            *key = next();
+        }
+        let mut pockets = [[[0u64; 17]; 5]; 2];
+        for key in pockets.iter_mut().flatten().flatten() {
+            *key = next();
+        }
+
+        ZobristKeys {
+            pieces,
+            castling,
+            en_passant,
+            side_to_move,
+            remaining_checks,
+            pockets,
+        }
+    }
+}
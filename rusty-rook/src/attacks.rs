@@ -0,0 +1,116 @@
+//! The squares attacked by every piece on the board, computed once per evaluation
+//! with the attack functions of `MoveGenerator` and shared by the evaluation terms.
+
+use chess::{
+    board::Board,
+    move_generator::MoveGenerator,
+    piece::{Color, PieceType},
+};
+
+use crate::masks::pawn_attacks;
+
+/// The squares one piece attacks.
+#[derive(Debug, Clone, Copy)]
+pub struct PieceAttacks {
+    pub piece: PieceType,
+    pub square: u8,
+    pub attacks: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Attacks {
+    /// The knights, bishops, rooks and queens of each color with their attacks,
+    /// indexed by `Color`.
+    pub pieces: [Vec<PieceAttacks>; 2],
+    /// The squares attacked by each piece type, indexed by `Color` and `PieceType`.
+    pub by_piece: [[u64; 6]; 2],
+    /// The squares attacked by any piece, indexed by `Color`.
+    pub all: [u64; 2],
+    /// The squares attacked by at least two pieces, indexed by `Color`.
+    pub double: [u64; 2],
+}
+
+impl Attacks {
+    pub fn new(board: &Board) -> Self {
+        let mut attacks = Attacks {
+            pieces: [Vec::new(), Vec::new()],
+            by_piece: [[0; 6]; 2],
+            all: [0; 2],
+            double: [0; 2],
+        };
+        for color in [Color::White, Color::Black] {
+            let c = color as usize;
+            let pawns = board.bitboards[c][PieceType::Pawn as usize].0;
+            attacks.by_piece[c][PieceType::Pawn as usize] = pawn_attacks(pawns, color);
+            let king = board.bitboards[c][PieceType::King as usize].to_square();
+            attacks.by_piece[c][PieceType::King as usize] = MoveGenerator::king_attacks(king);
+
+            for piece in [
+                PieceType::Knight,
+                PieceType::Bishop,
+                PieceType::Rook,
+                PieceType::Queen,
+            ] {
+                for square in board.bitboards[c][piece as usize] {
+                    let piece_attacks = piece_attacks(board, piece, square);
+                    attacks.by_piece[c][piece as usize] |= piece_attacks;
+                    attacks.add(color, piece_attacks);
+                    attacks.pieces[c].push(PieceAttacks {
+                        piece,
+                        square,
+                        attacks: piece_attacks,
+                    });
+                }
+            }
+            for piece in [PieceType::Pawn, PieceType::King] {
+                attacks.add(color, attacks.by_piece[c][piece as usize]);
+            }
+        }
+        attacks
+    }
+
+    fn add(&mut self, color: Color, attacks: u64) {
+        let c = color as usize;
+        self.double[c] |= self.all[c] & attacks;
+        self.all[c] |= attacks;
+    }
+}
+
+/// The squares a knight, bishop, rook or queen on `square` attacks.
+pub fn piece_attacks(board: &Board, piece: PieceType, square: u8) -> u64 {
+    match piece {
+        PieceType::Knight => MoveGenerator::knight_attacks(square),
+        PieceType::Bishop => MoveGenerator::bishop_attacks(square, board.combined),
+        PieceType::Rook => MoveGenerator::rook_attacks(square, board.combined),
+        PieceType::Queen => {
+            MoveGenerator::bishop_attacks(square, board.combined)
+                | MoveGenerator::rook_attacks(square, board.combined)
+        }
+        PieceType::Pawn | PieceType::King => {
+            unreachable!("Pawn and king attacks are computed in bulk")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::board::STARTING_FEN;
+
+    #[test]
+    fn test_starting_position_attacks() {
+        let attacks = Attacks::new(&Board::from_fen(STARTING_FEN).unwrap());
+        let white = Color::White as usize;
+        // The whole third rank is covered by pawns, the knights reach a3, c3, f3, h3, d2 and e2
+        assert_eq!(attacks.by_piece[white][PieceType::Pawn as usize], 0xFF_0000);
+        assert_eq!(
+            attacks.by_piece[white][PieceType::Knight as usize].count_ones(),
+            6
+        );
+        assert_eq!(attacks.pieces[white].len(), 7);
+        assert_eq!(attacks.all[white] & 0xFF_0000, 0xFF_0000);
+        // c3 is covered by the b pawn, the d pawn and the knight
+        assert_ne!(attacks.double[white] & (1 << 18), 0);
+        assert_eq!(attacks.double[white] & (1 << 16), 1 << 16);
+    }
+}
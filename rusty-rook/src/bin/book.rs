@@ -0,0 +1,102 @@
+use std::{
+    env,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    process,
+};
+
+use chess::pgn::PgnReader;
+use rusty_rook::book::BookBuilder;
+
+const USAGE: &str = "Usage: book [options] <games.pgn>...
+
+Builds a Polyglot opening book from the games of PGN collections. Every move of the
+first plies of a game adds two points for a win and one for a draw of the side that
+played it, and the points are the weights of the book moves. Games without a result
+are skipped, and so are games with a move that is not legal.
+
+Options:
+  --max-ply N         the plies of each game that go into the book (default: 20)
+  --min-games N       leave out moves played in fewer games (default: 3)
+  --out FILE          where to write the book (default: book.bin)";
+
+struct Options {
+    builder: BookBuilder,
+    out: PathBuf,
+    pgn_files: Vec<PathBuf>,
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1).collect()).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        process::exit(2);
+    });
+    if let Err(err) = build(options) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn build(mut options: Options) -> Result<(), String> {
+    let (mut added, mut skipped) = (0, 0);
+    for path in &options.pgn_files {
+        let file = File::open(path)
+            .map_err(|err| format!("Could not open {}: {}", path.display(), err))?;
+        for (index, game) in PgnReader::new(BufReader::new(file)).enumerate() {
+            let game = game.map_err(|err| read_error(path, err))?;
+            match options.builder.add_game(&game) {
+                Ok(true) => added += 1,
+                Ok(false) => skipped += 1,
+                Err(err) => {
+                    eprintln!("Skipping game {} of {}: {}", index + 1, path.display(), err);
+                    skipped += 1;
+                }
+            }
+        }
+    }
+
+    let book = options.builder.build();
+    book.save(&options.out)?;
+    println!(
+        "Wrote {} entries from {} games to {} ({} games skipped)",
+        book.len(),
+        added,
+        options.out.display(),
+        skipped
+    );
+    Ok(())
+}
+
+fn read_error(path: &Path, err: String) -> String {
+    format!("Could not read {}: {}", path.display(), err)
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        builder: BookBuilder::default(),
+        out: PathBuf::from("book.bin"),
+        pgn_files: Vec::new(),
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            options.pgn_files.push(PathBuf::from(arg));
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("No value given for {}", arg))?;
+        let invalid = || format!("Invalid value for {}: {}", arg, value);
+        match arg.as_str() {
+            "--max-ply" => options.builder.max_ply = value.parse().map_err(|_| invalid())?,
+            "--min-games" => options.builder.min_games = value.parse().map_err(|_| invalid())?,
+            "--out" => options.out = PathBuf::from(&value),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+    if options.pgn_files.is_empty() {
+        return Err("No PGN file given".to_string());
+    }
+    Ok(options)
+}
@@ -0,0 +1,161 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+
+use chess::board::{Board, STARTING_FEN};
+use rusty_rook::{datagen::SelfPlay, rng::Rng, search::Search};
+
+const USAGE: &str = "Usage: datagen [options]
+
+Plays self-play games with a fixed number of nodes per move from randomized openings
+and writes their quiet positions with the search score and the game result, both
+from White's point of view. The text file has one `FEN | score | result` line per
+position, which the tuner reads. The binary file packs each position into 32 bytes.
+
+Options:
+  --games N           the number of games to play (default: 1000)
+  --threads N         the number of games played at once (default: all cores)
+  --nodes N           the nodes searched per move (default: 5000)
+  --random-plies N    the random moves played at the start of a game (default: 8)
+  --max-plies N       games still running after N plies are drawn (default: 400)
+  --seed N            the seed of the random openings (default: 1)
+  --text FILE         where to write the text format (default: data.txt)
+  --binary FILE       where to write the binary format (default: data.bin)";
+
+struct Options {
+    games: u64,
+    threads: usize,
+    self_play: SelfPlay,
+    seed: u64,
+    text: PathBuf,
+    binary: PathBuf,
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1).collect()).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        process::exit(2);
+    });
+    if let Err(err) = generate(&options) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn generate(options: &Options) -> Result<(), String> {
+    let create = |path: &Path| {
+        File::create(path)
+            .map(BufWriter::new)
+            .map_err(|err| format!("Could not create {}: {}", path.display(), err))
+    };
+    let mut text = create(&options.text)?;
+    let mut binary = create(&options.binary)?;
+
+    // Every game has its own seed, so the games don't depend on the thread count
+    let next_game = Arc::new(AtomicU64::new(0));
+    let (sender, receiver) = mpsc::channel();
+    let workers: Vec<_> = (0..options.threads)
+        .map(|_| {
+            let next_game = Arc::clone(&next_game);
+            let sender = sender.clone();
+            let self_play = options.self_play.clone();
+            let (games, seed) = (options.games, options.seed);
+            thread::spawn(move || {
+                let start = Board::from_fen(STARTING_FEN).unwrap();
+                let mut search = Search::default();
+                loop {
+                    let game = next_game.fetch_add(1, Ordering::Relaxed);
+                    if game >= games {
+                        break;
+                    }
+                    let mut rng = Rng::new(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ game);
+                    let records = self_play.play_game(&start, &mut search, &mut rng);
+                    if sender.send(records).is_err() {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let (mut games, mut positions) = (0, 0);
+    for records in receiver {
+        for record in &records {
+            writeln!(text, "{}", record.to_text())
+                .and_then(|_| binary.write_all(&record.pack()))
+                .map_err(|err| write_error(options, err))?;
+        }
+        games += 1;
+        positions += records.len();
+        if games % 100 == 0 || games == options.games {
+            println!("{} games, {} positions", games, positions);
+        }
+    }
+    for worker in workers {
+        worker.join().expect("Self-play thread panicked");
+    }
+    text.flush()
+        .and_then(|_| binary.flush())
+        .map_err(|err| write_error(options, err))?;
+    println!(
+        "Wrote {} positions to {} and {}",
+        positions,
+        options.text.display(),
+        options.binary.display()
+    );
+    Ok(())
+}
+
+fn write_error(options: &Options, err: io::Error) -> String {
+    format!(
+        "Could not write {} or {}: {}",
+        options.text.display(),
+        options.binary.display(),
+        err
+    )
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        games: 1000,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        self_play: SelfPlay::default(),
+        seed: 1,
+        text: PathBuf::from("data.txt"),
+        binary: PathBuf::from("data.bin"),
+    };
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("No value given for {}", flag))?;
+        let invalid = || format!("Invalid value for {}: {}", flag, value);
+        match flag.as_str() {
+            "--games" => options.games = value.parse().map_err(|_| invalid())?,
+            "--threads" => options.threads = value.parse().map_err(|_| invalid())?,
+            "--nodes" => options.self_play.nodes = value.parse().map_err(|_| invalid())?,
+            "--random-plies" => {
+                options.self_play.random_plies = value.parse().map_err(|_| invalid())?
+            }
+            "--max-plies" => options.self_play.max_plies = value.parse().map_err(|_| invalid())?,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            "--text" => options.text = PathBuf::from(&value),
+            "--binary" => options.binary = PathBuf::from(&value),
+            _ => return Err(format!("Unknown option {}", flag)),
+        }
+    }
+    if options.threads == 0 {
+        return Err("At least one thread is needed".to_string());
+    }
+    Ok(options)
+}
@@ -1,14 +1,38 @@
-use std::io;
+use std::{env, io, path::Path, process};
 
 use chess::{
     board::{Board, STARTING_FEN},
+    clock::{Clock, TimeControl},
     move_generator::MoveGenerator,
     piece::Color,
 };
-use rusty_rook::score::minimax_ab;
+use rusty_rook::{eval::ClassicalEvaluator, params::EvalParams, search::Search, time::Limits};
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("eval") => return print_eval(&args[1..]),
+        Some("params") => return save_params(&args[1..]),
+        _ => {}
+    }
+
+    // `engine TIMECONTROL` plays a timed game, as in `engine 300+2`
+    let mut clock = match args.first() {
+        Some(control) => match control.parse::<TimeControl>() {
+            Ok(control) => Some(Clock::new(control)),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    if let Some(clock) = &mut clock {
+        clock.start(Color::White);
+    }
+
     let mut board = Board::from_fen(STARTING_FEN).unwrap();
+    let mut search = Search::default();
     loop {
         let turn = if board.side_to_move == Color::White {
             "White"
@@ -17,11 +41,34 @@ fn main() {
         };
         println!("Turn: {}", turn);
         board.print_board();
+        if let Some(clock) = &clock {
+            if let Some(flagged) = clock.flagged() {
+                println!("{:?} lost on time", flagged);
+                return;
+            }
+            println!(
+                "White: {:.1}s, Black: {:.1}s",
+                clock.remaining(Color::White).as_secs_f64(),
+                clock.remaining(Color::Black).as_secs_f64()
+            );
+        }
         // engine to move
         if board.side_to_move == Color::Black {
-            let (_, mv) = minimax_ab(&mut board, 6, 0, i32::MIN, i32::MAX);
+            let limits = match &clock {
+                Some(clock) => Limits {
+                    time_left: Some(clock.remaining(Color::Black)),
+                    increment: clock.increment(Color::Black),
+                    moves_to_go: clock.moves_to_go(Color::Black),
+                    ..Limits::default()
+                },
+                None => Limits::depth(6),
+            };
+            let mv = search
+                .search_with_limits(&mut board, &limits, |_| {})
+                .best_move();
             if let Some(mv) = mv {
                 board.make_move(mv);
+                press(&mut clock);
             }
             continue;
         }
@@ -65,6 +112,42 @@ fn main() {
             continue;
         }
         board.make_move(mv.unwrap());
+        press(&mut clock);
+    }
+}
+
+fn press(clock: &mut Option<Clock>) {
+    if let Some(clock) = clock {
+        clock.press();
+    }
+}
+
+/// `engine eval [FEN]` prints the evaluation of a position term by term, the
+/// starting position when no FEN is given.
+fn print_eval(fen: &[String]) {
+    let fen = if fen.is_empty() {
+        STARTING_FEN.to_string()
+    } else {
+        fen.join(" ")
+    };
+    let Ok(board) = Board::from_fen(&fen) else {
+        eprintln!("Invalid FEN: {}", fen);
+        process::exit(1);
+    };
+    board.print_board();
+    print!("{}", ClassicalEvaluator::default().trace(&board));
+}
+
+/// `engine params FILE` writes the compiled-in evaluation parameters to a `.toml` or
+/// `.json` file, as a starting point for tuning.
+fn save_params(path: &[String]) {
+    let [path] = path else {
+        eprintln!("Usage: engine params FILE");
+        process::exit(1);
+    };
+    if let Err(err) = EvalParams::default().save(Path::new(path)) {
+        eprintln!("{}", err);
+        process::exit(1);
     }
 }
 
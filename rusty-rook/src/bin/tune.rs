@@ -0,0 +1,147 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use rusty_rook::{
+    params::EvalParams,
+    tuner::{fit_k, load_samples, split, Tuner},
+};
+
+const USAGE: &str = "Usage: tune DATASET [options]
+
+Tunes the evaluation weights on a dataset of quiet positions, one EPD or FEN line
+per position labeled with its result as `c9 \"1-0\";` or `[1.0]`.
+
+Options:
+  --params FILE       start from the parameters in FILE instead of the defaults
+  --output FILE       where to save the tuned parameters after every epoch
+                      (default: tuned.toml)
+  --curve FILE        where to write the loss curve as CSV (default: loss.csv)
+  --epochs N          the most passes over all weights (default: 100)
+  --step N            how far to move a weight at once (default: 1)
+  --validation F      the fraction of positions kept out of training (default: 0.1)
+  --k K               the sigmoid scaling constant, fitted when not given
+  --tune PREFIX,...   only tune the weights whose path starts with a prefix, as in
+                      pawns,pieces.bishop_pair";
+
+struct Options {
+    dataset: PathBuf,
+    params: Option<PathBuf>,
+    output: PathBuf,
+    curve: PathBuf,
+    epochs: usize,
+    step: i32,
+    validation: f64,
+    k: Option<f64>,
+    prefixes: Vec<String>,
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1).collect()).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        process::exit(2);
+    });
+    if let Err(err) = tune(&options) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn tune(options: &Options) -> Result<(), String> {
+    let params = match &options.params {
+        Some(path) => EvalParams::load(path)?,
+        None => EvalParams::default(),
+    };
+    let samples = load_samples(&options.dataset)?;
+    let (training, validation) = split(samples, options.validation);
+    println!(
+        "{} training and {} validation positions",
+        training.len(),
+        validation.len()
+    );
+
+    let k = options.k.unwrap_or_else(|| fit_k(&training, &params));
+    println!("K = {:.4}", k);
+
+    let mut tuner = Tuner::new(params, k, &options.prefixes, training, validation);
+    println!("Tuning {} weights", tuner.weights.len());
+    let mut curve = File::create(&options.curve).map_err(|err| io_error(&options.curve, err))?;
+    writeln!(curve, "epoch,training,validation,changed")
+        .map_err(|err| io_error(&options.curve, err))?;
+    let initial_training = tuner.training_error(&tuner.params);
+    let initial_validation = tuner.validation_error();
+    writeln!(curve, "0,{:.8},{:.8},0", initial_training, initial_validation)
+        .map_err(|err| io_error(&options.curve, err))?;
+    println!(
+        "epoch 0: training {:.6} validation {:.6}",
+        initial_training, initial_validation
+    );
+
+    let mut result = Ok(());
+    tuner.run(options.epochs, options.step, |epoch, params| {
+        println!(
+            "epoch {}: training {:.6} validation {:.6}, {} weights changed",
+            epoch.epoch, epoch.training_error, epoch.validation_error, epoch.changed
+        );
+        let written = writeln!(
+            curve,
+            "{},{:.8},{:.8},{}",
+            epoch.epoch, epoch.training_error, epoch.validation_error, epoch.changed
+        )
+        .and_then(|_| curve.flush())
+        .map_err(|err| io_error(&options.curve, err))
+        .and_then(|_| params.save(&options.output));
+        if written.is_err() && result.is_ok() {
+            result = written;
+        }
+    })?;
+    result?;
+    tuner.params.save(&options.output)?;
+    println!("Saved the parameters to {}", options.output.display());
+    Ok(())
+}
+
+fn io_error(path: &Path, err: io::Error) -> String {
+    format!("Could not write {}: {}", path.display(), err)
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut args = args.into_iter();
+    let dataset = match args.next() {
+        Some(dataset) if !dataset.starts_with("--") => PathBuf::from(dataset),
+        _ => return Err("No dataset given".to_string()),
+    };
+    let mut options = Options {
+        dataset,
+        params: None,
+        output: PathBuf::from("tuned.toml"),
+        curve: PathBuf::from("loss.csv"),
+        epochs: 100,
+        step: 1,
+        validation: 0.1,
+        k: None,
+        prefixes: Vec::new(),
+    };
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("No value given for {}", flag))?;
+        let invalid = || format!("Invalid value for {}: {}", flag, value);
+        match flag.as_str() {
+            "--params" => options.params = Some(PathBuf::from(&value)),
+            "--output" => options.output = PathBuf::from(&value),
+            "--curve" => options.curve = PathBuf::from(&value),
+            "--epochs" => options.epochs = value.parse().map_err(|_| invalid())?,
+            "--step" => options.step = value.parse().map_err(|_| invalid())?,
+            "--validation" => options.validation = value.parse().map_err(|_| invalid())?,
+            "--k" => options.k = Some(value.parse().map_err(|_| invalid())?),
+            "--tune" => options.prefixes = value.split(',').map(str::to_string).collect(),
+            _ => return Err(format!("Unknown option {}", flag)),
+        }
+    }
+    Ok(options)
+}
@@ -1,10 +1,144 @@
-use std::io::{self, BufRead, Write};
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use chess::{
     board::{Board, STARTING_FEN},
     chess_move::ChessMove,
+    piece::Color,
+    variant::Variant,
+};
+use rusty_rook::{
+    book::{Book, Selection},
+    eval::{evaluate_antichess, ClassicalEvaluator, Evaluator},
+    nnue::{Network, NnueEvaluator},
+    params::EvalParams,
+    rng::Rng,
+    score::Score,
+    search::{Search, SearchControl, SearchResult},
+    syzygy::{SyzygyTablebase, Tablebase},
+    time::Limits,
 };
-use rusty_rook::score::minimax_ab;
+
+/// The search runs on its own thread so that the main loop keeps reading commands
+/// such as `stop` and `ponderhit` while it runs.
+struct SearchThread {
+    /// The search while no `go` command is running, otherwise owned by the thread.
+    idle: Option<Search>,
+    running: Option<JoinHandle<Search>>,
+    control: SearchControl,
+    /// The value of the Ponder option.
+    ponder: bool,
+}
+
+impl SearchThread {
+    fn new(search: Search) -> Self {
+        SearchThread {
+            control: search.control(),
+            idle: Some(search),
+            running: None,
+            ponder: false,
+        }
+    }
+
+    /// Waits for the running search to finish and returns the search.
+    fn search(&mut self) -> &mut Search {
+        if let Some(handle) = self.running.take() {
+            self.idle = Some(handle.join().expect("Search thread panicked"));
+        }
+        self.idle
+            .as_mut()
+            .expect("Search is neither idle nor running")
+    }
+
+    /// Starts searching `board`, the thread prints the info lines and the best move.
+    fn start(&mut self, mut board: Board, limits: Limits) {
+        self.search();
+        let mut search = self.idle.take().expect("Search is not idle");
+        let ponder = self.ponder;
+        self.running = Some(thread::spawn(move || {
+            let mut output = io::stdout();
+            let root = board.clone();
+            let result = search.search_with_limits(&mut board, &limits, |result| {
+                write_info(&mut io::stdout(), result, &root);
+            });
+            board.print_board();
+            if let Some(moves) = limits.mate {
+                if !matches!(result.score, Score::Mate(_)) {
+                    writeln!(output, "info string no mate in {} found", moves)
+                        .expect("Error writing output");
+                }
+            }
+            write_bestmove(&mut output, &result, &board, ponder);
+            search
+        }));
+    }
+}
+
+/// The options that select and configure the evaluation.
+#[derive(Default)]
+struct EvalOptions {
+    params: EvalParams,
+    network: Option<Arc<Network>>,
+    /// The value of the UseNNUE option, only effective once a network is loaded.
+    use_nnue: bool,
+}
+
+impl EvalOptions {
+    fn evaluator(&self) -> Arc<dyn Evaluator> {
+        match &self.network {
+            Some(network) if self.use_nnue => Arc::new(NnueEvaluator::new(Arc::clone(network))),
+            _ => Arc::new(ClassicalEvaluator::new(self.params.clone())),
+        }
+    }
+}
+
+/// The options that set the rules positions are played by.
+#[derive(Default)]
+struct RuleOptions {
+    /// The value of the UCI_Chess960 option
+    chess960: bool,
+    /// The value of the UCI_Variant option
+    variant: Variant,
+}
+
+/// The opening book options.
+struct BookOptions {
+    book: Option<Book>,
+    /// The value of the OwnBook option, only effective once a book is loaded.
+    own_book: bool,
+    selection: Selection,
+    rng: Rng,
+}
+
+impl BookOptions {
+    fn new() -> Self {
+        // A new seed every run, so that the engine varies its openings
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |time| time.as_nanos() as u64);
+        BookOptions {
+            book: None,
+            own_book: false,
+            selection: Selection::default(),
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// A book move for `board` if the engine plays from its book. Books are for
+    /// standard chess only.
+    fn pick(&mut self, board: &Board) -> Option<ChessMove> {
+        let book = self
+            .book
+            .as_ref()
+            .filter(|_| self.own_book && board.variant == Variant::Standard)?;
+        book.pick(&mut board.clone(), self.selection, &mut self.rng)
+    }
+}
 
 fn main() {
     let stdin = io::stdin();
@@ -12,6 +146,10 @@ fn main() {
     let mut output = io::stdout();
 
     let mut board = Board::from_fen(STARTING_FEN).unwrap();
+    let mut search = SearchThread::new(Search::default());
+    let mut eval = EvalOptions::default();
+    let mut book = BookOptions::new();
+    let mut rules = RuleOptions::default();
 
     for line in input.lines() {
         let line = line.expect("Could not read line from standard input");
@@ -19,12 +157,25 @@ fn main() {
         if line == "uci" {
             handle_uci(&mut output);
         } else if line.starts_with("position") {
-            handle_position(&mut board, &line);
+            search.search();
+            handle_position(&mut board, &rules, &line);
         } else if line.starts_with("go") {
-            handle_go(&mut board, &mut output);
+            handle_go(&board, &mut search, &mut book, &line, &mut output);
+        } else if line.starts_with("setoption") {
+            handle_setoption(&mut search, &mut eval, &mut book, &mut rules, &line);
+        } else if line == "ucinewgame" {
+            search.search().new_game();
+        } else if line == "stop" {
+            search.control.stop();
+        } else if line == "ponderhit" {
+            search.control.ponderhit();
+        } else if line == "eval" {
+            handle_eval(&board, &eval, &mut output);
         } else if line == "isready" {
             handle_isready(&mut output)
         } else if line == "quit" {
+            search.control.stop();
+            search.search();
             break;
         }
     }
@@ -33,46 +184,315 @@ fn main() {
 fn handle_uci(output: &mut impl Write) {
     writeln!(output, "id name MyChessEngine").expect("Error writing output");
     writeln!(output, "id author Your Name").expect("Error writing output");
+    writeln!(
+        output,
+        "option name Threads type spin default 1 min 1 max 256"
+    )
+    .expect("Error writing output");
+    writeln!(output, "option name Ponder type check default false").expect("Error writing output");
+    writeln!(
+        output,
+        "option name MultiPV type spin default 1 min 1 max 256"
+    )
+    .expect("Error writing output");
+    writeln!(output, "option name EvalFile type string default <empty>")
+        .expect("Error writing output");
+    writeln!(output, "option name UseNNUE type check default false").expect("Error writing output");
+    writeln!(output, "option name NnueFile type string default <empty>")
+        .expect("Error writing output");
+    writeln!(output, "option name SyzygyPath type string default <empty>")
+        .expect("Error writing output");
+    writeln!(output, "option name OwnBook type check default false").expect("Error writing output");
+    writeln!(output, "option name BookFile type string default <empty>")
+        .expect("Error writing output");
+    writeln!(
+        output,
+        "option name Book type combo default Random var Random var Best"
+    )
+    .expect("Error writing output");
+    writeln!(output, "option name UCI_Chess960 type check default false")
+        .expect("Error writing output");
+    let variants: String = Variant::ALL
+        .iter()
+        .map(|variant| format!(" var {}", variant))
+        .collect();
+    writeln!(
+        output,
+        "option name UCI_Variant type combo default {}{}",
+        Variant::Standard,
+        variants
+    )
+    .expect("Error writing output");
     writeln!(output, "uciok").expect("Error writing output");
 }
 
-fn handle_position(board: &mut Board, line: &str) {
-    // Example: position startpos moves e2e4 e7e5
+fn handle_setoption(
+    search: &mut SearchThread,
+    eval: &mut EvalOptions,
+    book: &mut BookOptions,
+    rules: &mut RuleOptions,
+    line: &str,
+) {
+    // Example: setoption name Threads value 4
+    // Single evaluation weights are set by their path in the parameter file, as in
+    // setoption name Eval.pawns.doubled.mg value -12
     let parts: Vec<&str> = line.split_whitespace().collect();
-    if parts[1] == "startpos" {
-        *board = Board::from_fen(STARTING_FEN).unwrap();
-        if parts.len() > 3 && parts[2] == "moves" {
-            for move_notation in parts[3..].iter() {
-                let mv = parse_move(move_notation, board);
-                board.make_move(mv);
+    let (Some(name), Some(value)) = (
+        parts.iter().position(|part| *part == "name"),
+        parts.iter().position(|part| *part == "value"),
+    ) else {
+        return;
+    };
+    let name = parts[name + 1..value].join(" ");
+    let value = parts[value + 1..].join(" ");
+    if name.eq_ignore_ascii_case("Threads") {
+        if let Ok(threads) = value.parse() {
+            search.search().set_threads(threads);
+        }
+    } else if name.eq_ignore_ascii_case("MultiPV") {
+        if let Ok(lines) = value.parse() {
+            search.search().set_multi_pv(lines);
+        }
+    } else if name.eq_ignore_ascii_case("Ponder") {
+        if let Ok(ponder) = value.parse() {
+            search.ponder = ponder;
+        }
+    } else if name.eq_ignore_ascii_case("EvalFile") {
+        let loaded = if value.is_empty() || value == "<empty>" {
+            Ok(EvalParams::default())
+        } else {
+            EvalParams::load(Path::new(&value))
+        };
+        match loaded {
+            Ok(loaded) => {
+                eval.params = loaded;
+                update_evaluator(search, eval);
+            }
+            Err(err) => println!("info string {}", err),
+        }
+    } else if name.eq_ignore_ascii_case("UseNNUE") {
+        if let Ok(use_nnue) = value.parse() {
+            eval.use_nnue = use_nnue;
+            update_evaluator(search, eval);
+        }
+    } else if name.eq_ignore_ascii_case("NnueFile") {
+        if value.is_empty() || value == "<empty>" {
+            eval.network = None;
+        } else {
+            match Network::load(Path::new(&value)) {
+                Ok(network) => eval.network = Some(Arc::new(network)),
+                Err(err) => println!("info string {}", err),
+            }
+        }
+        update_evaluator(search, eval);
+    } else if name.eq_ignore_ascii_case("SyzygyPath") {
+        if value.is_empty() || value == "<empty>" {
+            search.search().set_tablebase(None);
+            return;
+        }
+        match SyzygyTablebase::open(&value) {
+            Ok(tablebase) => {
+                println!(
+                    "info string Found {} Syzygy table files up to {} pieces",
+                    tablebase.files(),
+                    tablebase.max_pieces()
+                );
+                search.search().set_tablebase(Some(Arc::new(tablebase)));
+            }
+            Err(err) => println!("info string {}", err),
+        }
+    } else if name.eq_ignore_ascii_case("OwnBook") {
+        if let Ok(own_book) = value.parse() {
+            book.own_book = own_book;
+        }
+    } else if name.eq_ignore_ascii_case("BookFile") {
+        if value.is_empty() || value == "<empty>" {
+            book.book = None;
+            return;
+        }
+        match Book::load(Path::new(&value)) {
+            Ok(loaded) => {
+                println!("info string Loaded {} book entries", loaded.len());
+                book.book = Some(loaded);
             }
+            Err(err) => println!("info string {}", err),
         }
-    } else if parts[1] == "fen" {
-        let fen: String = parts[2..=7].join(" ");
-        *board = Board::from_fen(&fen).unwrap();
-        if parts.len() > 8 && parts[8] == "moves" {
-            for move_notation in parts[9..].iter() {
-                let mv = parse_move(move_notation, board);
-                board.make_move(mv);
+    } else if name.eq_ignore_ascii_case("Book") {
+        match value.to_ascii_lowercase().as_str() {
+            "random" => book.selection = Selection::WeightedRandom,
+            "best" => book.selection = Selection::BestMove,
+            _ => println!("info string Unknown book selection: {}", value),
+        }
+    } else if name.eq_ignore_ascii_case("UCI_Chess960") {
+        if let Ok(value) = value.parse() {
+            rules.chess960 = value;
+        }
+    } else if name.eq_ignore_ascii_case("UCI_Variant") {
+        match value.parse() {
+            Ok(variant) => rules.variant = variant,
+            Err(err) => println!("info string {}", err),
+        }
+    } else if let Some(weight) = name.strip_prefix("Eval.") {
+        let mut changed = eval.params.clone();
+        let result = value
+            .parse()
+            .map_err(|_| format!("Invalid weight: {}", value))
+            .and_then(|value| changed.set(weight, value));
+        match result {
+            Ok(()) => {
+                eval.params = changed;
+                update_evaluator(search, eval);
             }
+            Err(err) => println!("info string {}", err),
         }
     }
 }
 
-fn handle_go(board: &mut Board, output: &mut impl Write) {
-    let (_, mv) = minimax_ab(board, 7, 0, i32::MIN, i32::MAX);
-    if let Some(mv) = mv {
-        board.print_board();
-        writeln!(output, "bestmove {}", mv.to_standard_notation()).expect("Error writing output");
-    } else {
-        writeln!(output, "bestmove none").expect("Error writing output");
+fn update_evaluator(search: &mut SearchThread, eval: &EvalOptions) {
+    if eval.use_nnue && eval.network.is_none() {
+        println!("info string UseNNUE needs a network, set NnueFile");
     }
+    search.search().set_evaluator(eval.evaluator());
+}
+
+fn handle_position(board: &mut Board, rules: &RuleOptions, line: &str) {
+    // Example: position startpos moves e2e4 e7e5
+    // With UCI_Chess960 castling moves are written as the king taking its rook, e1h1
+    // Three-check FENs have one more field, the checks each side still has to give
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let moves = parts
+        .iter()
+        .position(|part| *part == "moves")
+        .unwrap_or(parts.len());
+    let fen = match parts[1] {
+        "startpos" => rules.variant.starting_fen().to_string(),
+        "fen" => parts[2..moves].join(" "),
+        _ => return,
+    };
+    *board = Board::from_variant_fen(&fen, rules.variant).unwrap();
+    board.chess960 |= rules.chess960;
+    for move_notation in parts.iter().skip(moves + 1) {
+        let mv = parse_move(move_notation, board);
+        board.make_move(mv);
+    }
+}
+
+fn handle_go(
+    board: &Board,
+    search: &mut SearchThread,
+    book: &mut BookOptions,
+    line: &str,
+    output: &mut impl Write,
+) {
+    // Example: go depth 6, go mate 3, go ponder wtime 60000 btime 60000 winc 1000 binc 1000
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    // The GUI expects no move before it ends a ponder or infinite search
+    let waits = parts.contains(&"ponder") || parts.contains(&"infinite");
+    if let Some(mv) = book.pick(board).filter(|_| !waits) {
+        search.search();
+        writeln!(output, "info string book move").expect("Error writing output");
+        writeln!(output, "bestmove {}", mv.to_uci(board)).expect("Error writing output");
+        return;
+    }
+
+    let (time, increment) = match board.side_to_move {
+        Color::White => ("wtime", "winc"),
+        Color::Black => ("btime", "binc"),
+    };
+    let milliseconds = |name| go_parameter(&parts, name).map(|ms| Duration::from_millis(ms.into()));
+    let mut limits = Limits {
+        depth: go_parameter(&parts, "depth"),
+        move_time: milliseconds("movetime"),
+        time_left: milliseconds(time),
+        increment: milliseconds(increment).unwrap_or_default(),
+        moves_to_go: go_parameter(&parts, "movestogo"),
+        nodes: go_parameter(&parts, "nodes").map(u64::from),
+        infinite: parts.contains(&"infinite"),
+        mate: go_parameter(&parts, "mate"),
+        // Pondered on even without the Ponder option, which only decides whether
+        // `bestmove` names a move to ponder on
+        ponder: parts.contains(&"ponder"),
+    };
+    let unlimited = limits.move_time.is_none() && limits.time_left.is_none();
+    if unlimited && limits.nodes.is_none() && !limits.infinite {
+        limits.depth = limits.depth.or(Some(7));
+    }
+    search.start(board.clone(), limits);
+}
+
+/// Prints the result of one iteration as UCI info lines, one per MultiPV line.
+fn write_info(output: &mut impl Write, result: &SearchResult, board: &Board) {
+    for (index, line) in result.lines.iter().enumerate() {
+        let pv: Vec<String> = line.pv.iter().map(|mv| mv.to_uci(board)).collect();
+        writeln!(
+            output,
+            "info depth {} multipv {} score {} nodes {} time {} pv {}",
+            result.depth,
+            index + 1,
+            line.score,
+            result.nodes,
+            result.time.as_millis(),
+            pv.join(" ")
+        )
+        .expect("Error writing output");
+    }
+}
+
+/// Prints the best move, followed by the expected reply when pondering is enabled.
+fn write_bestmove(output: &mut impl Write, result: &SearchResult, board: &Board, ponder: bool) {
+    match (result.best_move(), result.ponder_move()) {
+        (Some(mv), Some(reply)) if ponder => writeln!(
+            output,
+            "bestmove {} ponder {}",
+            mv.to_uci(board),
+            reply.to_uci(board)
+        ),
+        (Some(mv), _) => writeln!(output, "bestmove {}", mv.to_uci(board)),
+        (None, _) => writeln!(output, "bestmove none"),
+    }
+    .expect("Error writing output");
+}
+
+/// Returns the number following `name` in a `go` command, e.g. 3 for `go mate 3`.
+fn go_parameter(parts: &[&str], name: &str) -> Option<u32> {
+    let index = parts.iter().position(|part| *part == name)?;
+    parts.get(index + 1)?.parse().ok()
 }
 
 fn handle_isready(output: &mut impl Write) {
     writeln!(output, "readyok").expect("Error writing output");
 }
 
+/// Prints the evaluation of the current position term by term. Not part of the UCI
+/// protocol, but understood by other engines as well.
+fn handle_eval(board: &Board, eval: &EvalOptions, output: &mut impl Write) {
+    if board.variant == Variant::Antichess {
+        let score = evaluate_antichess(board);
+        let white_score = match board.side_to_move {
+            Color::White => score,
+            Color::Black => -score,
+        };
+        writeln!(
+            output,
+            "Antichess evaluation: {} (White's view)",
+            white_score
+        )
+        .expect("Error writing output");
+        return;
+    }
+    let trace = ClassicalEvaluator::new(eval.params.clone()).trace(board);
+    write!(output, "{}", trace).expect("Error writing output");
+    if let Some(network) = eval.network.as_ref().filter(|_| eval.use_nnue) {
+        let score = NnueEvaluator::new(Arc::clone(network)).evaluate(board);
+        let white_score = match board.side_to_move {
+            Color::White => score,
+            Color::Black => -score,
+        };
+        writeln!(output, "NNUE evaluation: {} (White's view)", white_score)
+            .expect("Error writing output");
+    }
+}
+
 fn parse_move(move_notation: &str, board: &Board) -> ChessMove {
     ChessMove::from_standard_notation(move_notation, board).unwrap()
 }
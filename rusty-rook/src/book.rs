@@ -0,0 +1,345 @@
+//! Opening books in the Polyglot format.
+//!
+//! A book is a file of 16 byte entries sorted by position key, each a move with a
+//! weight telling how often to play it. `Book` looks up the moves of a position and
+//! picks one, `BookBuilder` makes a book from the games of a PGN collection.
+
+use std::{cmp::Reverse, collections::HashMap, fs, path::Path};
+
+use chess::{
+    board::Board,
+    chess_move::ChessMove,
+    pgn::{parse_san, PgnGame},
+    piece::Color,
+    polyglot,
+};
+
+use crate::rng::Rng;
+
+/// The size of an entry in a book file.
+pub const ENTRY_SIZE: usize = 16;
+
+/// An entry of a book: a move of the positions with Polyglot key `key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    /// The move as encoded by `polyglot::encode_move`.
+    pub raw_move: u16,
+    pub weight: u16,
+    /// Left for learning, which this engine does not do.
+    pub learn: u32,
+}
+
+impl BookEntry {
+    /// Reads an entry, all fields big endian.
+    pub fn from_bytes(bytes: &[u8; ENTRY_SIZE]) -> Self {
+        BookEntry {
+            key: u64::from_be_bytes(bytes[..8].try_into().expect("8 bytes")),
+            raw_move: u16::from_be_bytes([bytes[8], bytes[9]]),
+            weight: u16::from_be_bytes([bytes[10], bytes[11]]),
+            learn: u32::from_be_bytes(bytes[12..].try_into().expect("4 bytes")),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.raw_move.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+/// How a move is chosen among the book moves of a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Selection {
+    /// Every move with a chance proportional to its weight.
+    #[default]
+    WeightedRandom,
+    /// Always the move with the highest weight.
+    BestMove,
+}
+
+/// A Polyglot opening book held in memory.
+#[derive(Debug, Clone, Default)]
+pub struct Book {
+    /// Sorted by key, and the moves of a position by decreasing weight.
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn new(mut entries: Vec<BookEntry>) -> Self {
+        entries.sort_by_key(|entry| (entry.key, Reverse(entry.weight), entry.raw_move));
+        Book { entries }
+    }
+
+    /// Reads a book from the bytes of a `.bin` file.
+    pub fn read(bytes: &[u8]) -> Result<Self, String> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(format!(
+                "Invalid book: {} bytes is not a whole number of entries",
+                bytes.len()
+            ));
+        }
+        let entries = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| BookEntry::from_bytes(chunk.try_into().expect("A whole entry")))
+            .collect();
+        Ok(Book::new(entries))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes =
+            fs::read(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        Book::read(&bytes)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries
+            .iter()
+            .flat_map(|entry| entry.to_bytes())
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_bytes())
+            .map_err(|err| format!("Could not write {}: {}", path.display(), err))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries of the positions with Polyglot key `key`.
+    pub fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    /// The legal book moves of `board` with their weights, heaviest first.
+    pub fn moves(&self, board: &mut Board) -> Vec<(ChessMove, u16)> {
+        self.entries(polyglot::key(board))
+            .iter()
+            .filter_map(|entry| {
+                let mv = polyglot::decode_move(entry.raw_move, board)?;
+                Some((mv, entry.weight))
+            })
+            .collect()
+    }
+
+    /// Picks a book move for `board`, or None when the position is not in the book.
+    /// Moves of weight 0 are never picked.
+    pub fn pick(
+        &self,
+        board: &mut Board,
+        selection: Selection,
+        rng: &mut Rng,
+    ) -> Option<ChessMove> {
+        let moves: Vec<_> = self
+            .moves(board)
+            .into_iter()
+            .filter(|&(_, weight)| weight > 0)
+            .collect();
+        match selection {
+            Selection::BestMove => moves.first().map(|&(mv, _)| mv),
+            Selection::WeightedRandom => {
+                let total: usize = moves.iter().map(|&(_, weight)| weight as usize).sum();
+                if total == 0 {
+                    return None;
+                }
+                let mut choice = rng.below(total);
+                for (mv, weight) in moves {
+                    if choice < weight as usize {
+                        return Some(mv);
+                    }
+                    choice -= weight as usize;
+                }
+                unreachable!("The choice is below the total weight")
+            }
+        }
+    }
+}
+
+/// How a move did in the games of a `BookBuilder`.
+#[derive(Debug, Clone, Copy, Default)]
+struct MoveStats {
+    games: u32,
+    /// Two points for a win and one for a draw of the side that played the move.
+    points: u64,
+}
+
+/// Makes a book from games. The weight of a move is two points for every game won
+/// by the side that played it and one for every draw, as Polyglot weighs its books,
+/// so moves that never scored are left out.
+#[derive(Debug, Clone)]
+pub struct BookBuilder {
+    /// Only the first moves of a game go into the book.
+    pub max_ply: usize,
+    /// Moves played in fewer games are left out.
+    pub min_games: u32,
+    stats: HashMap<(u64, u16), MoveStats>,
+}
+
+impl Default for BookBuilder {
+    fn default() -> Self {
+        BookBuilder {
+            max_ply: 20,
+            min_games: 3,
+            stats: HashMap::new(),
+        }
+    }
+}
+
+impl BookBuilder {
+    pub fn new(max_ply: usize, min_games: u32) -> Self {
+        BookBuilder {
+            max_ply,
+            min_games,
+            ..BookBuilder::default()
+        }
+    }
+
+    /// Adds the opening of `game`. Returns false for a game without a result, which
+    /// is skipped, and an error for one with a move that is not legal, which adds
+    /// nothing.
+    pub fn add_game(&mut self, game: &PgnGame) -> Result<bool, String> {
+        let winner = match game.result.as_str() {
+            "1-0" => Some(Color::White),
+            "0-1" => Some(Color::Black),
+            "1/2-1/2" => None,
+            _ => return Ok(false),
+        };
+        let mut board = game.start()?;
+        let mut played = Vec::new();
+        for san in game.moves.iter().take(self.max_ply) {
+            let mv = parse_san(san, &mut board)?;
+            let points = match winner {
+                Some(winner) if winner == board.side_to_move => 2,
+                Some(_) => 0,
+                None => 1,
+            };
+            played.push((
+                (polyglot::key(&board), polyglot::encode_move(&mv, &board)),
+                points,
+            ));
+            board.make_move(mv);
+        }
+        for (entry, points) in played {
+            let stats = self.stats.entry(entry).or_default();
+            stats.games += 1;
+            stats.points += points;
+        }
+        Ok(true)
+    }
+
+    /// The book of the games added so far. Weights are scaled down together when the
+    /// points of a move do not fit in 16 bits.
+    pub fn build(&self) -> Book {
+        let kept: Vec<_> = self
+            .stats
+            .iter()
+            .filter(|(_, stats)| stats.games >= self.min_games && stats.points > 0)
+            .collect();
+        let most_points = kept
+            .iter()
+            .map(|(_, stats)| stats.points)
+            .max()
+            .unwrap_or(0);
+        let scale = |points: u64| match most_points {
+            0..=0xffff => points,
+            _ => (points * 0xffff / most_points).max(1),
+        };
+        let entries = kept
+            .into_iter()
+            .map(|(&(key, raw_move), stats)| BookEntry {
+                key,
+                raw_move,
+                weight: scale(stats.points) as u16,
+                learn: 0,
+            })
+            .collect();
+        Book::new(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::{board::STARTING_FEN, pgn::PgnReader};
+
+    const GAMES: &str = "1. e4 e5 2. Nf3 1-0\n\n1. e4 c5 1/2-1/2\n\n1. d4 d5 0-1\n\n1. e4 e5 *\n";
+
+    fn build(min_games: u32) -> Book {
+        let mut builder = BookBuilder::new(2, min_games);
+        for game in PgnReader::new(GAMES.as_bytes()) {
+            builder.add_game(&game.unwrap()).unwrap();
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn test_builder_weighs_moves_by_result() {
+        let book = build(1);
+        let mut board = Board::from_fen(STARTING_FEN).unwrap();
+        let moves: Vec<(String, u16)> = book
+            .moves(&mut board)
+            .iter()
+            .map(|(mv, weight)| (mv.to_standard_notation(), *weight))
+            .collect();
+        // d4 lost its only game and is left out
+        assert_eq!(moves, [("e2e4".to_string(), 3)]);
+
+        board.make_move(ChessMove::from_standard_notation("e2e4", &board).unwrap());
+        let replies = book.moves(&mut board);
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].0.to_standard_notation(), "c7c5");
+        // e4 c5 and d4 d5, while Nf3 is beyond the ply limit
+        assert_eq!(book.len(), 3);
+
+        assert_eq!(build(2).len(), 1);
+    }
+
+    #[test]
+    fn test_file_round_trip_and_selection() {
+        let entry = |raw_move, weight| BookEntry {
+            key: 0x463b96181691fc9c,
+            raw_move,
+            weight,
+            learn: 7,
+        };
+        // e2e4, d2d4 and g1f3 of the starting position
+        let e4 = 12 << 6 | 28;
+        let d4 = 11 << 6 | 27;
+        let nf3 = 6 << 6 | 21;
+        let book = Book::new(vec![entry(d4, 1), entry(e4, 3), entry(nf3, 0)]);
+        assert_eq!(Book::read(&book.to_bytes()).unwrap().entries, book.entries);
+        assert!(Book::read(&[0; 17]).is_err());
+
+        let mut board = Board::from_fen(STARTING_FEN).unwrap();
+        let pick = |selection, rng: &mut Rng, board: &mut Board| {
+            book.pick(board, selection, rng)
+                .map(|mv| mv.to_standard_notation())
+        };
+        let mut rng = Rng::new(1);
+        assert_eq!(
+            pick(Selection::BestMove, &mut rng, &mut board).as_deref(),
+            Some("e2e4")
+        );
+        let mut counts = HashMap::new();
+        for _ in 0..400 {
+            let mv = pick(Selection::WeightedRandom, &mut rng, &mut board).unwrap();
+            *counts.entry(mv).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 2, "{:?}", counts);
+        assert!(counts["e2e4"] > 2 * counts["d2d4"], "{:?}", counts);
+
+        board.make_move(ChessMove::from_standard_notation("e2e4", &board).unwrap());
+        assert_eq!(pick(Selection::BestMove, &mut rng, &mut board), None);
+    }
+}
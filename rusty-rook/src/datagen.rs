@@ -0,0 +1,319 @@
+//! Training data from self-play. Each game starts with a few random moves and then
+//! plays the best move of a search with a fixed number of nodes for both sides.
+//! Quiet positions are recorded with the score of their search and, once the game is
+//! over, its result.
+
+use chess::{board::Board, move_generator::MoveGenerator, piece::Color};
+
+use crate::{rng::Rng, score::Score, search::Search, time::Limits};
+
+/// The size of a position in the binary format, see `Record::pack`.
+pub const PACKED_SIZE: usize = 32;
+
+/// A position with its search score and the result of its game, both from White's
+/// point of view. The result is 1 for a win, 0.5 for a draw and 0 for a loss, as
+/// for the tuner's samples.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub board: Board,
+    pub score: i32,
+    pub result: f64,
+}
+
+impl Record {
+    /// The position as a line of text, `FEN | score | result`.
+    pub fn to_text(&self) -> String {
+        format!(
+            "{} | {} | {:.1}",
+            self.board.to_fen(),
+            self.score,
+            self.result
+        )
+    }
+
+    /// Packs the position into 32 bytes:
+    ///
+    /// - the occupied squares as a little endian `u64`,
+    /// - a nibble per occupied square from a1 to h8, the piece type with the color in
+    ///   the high bit, low nibble first,
+    /// - a byte with the side to move in bit 0 and the castling rights in bits 1 to 4,
+    /// - the en passant square, or 64 without one,
+    /// - the half move clock, saturated to 255,
+    /// - the full move number as a little endian `u16`,
+    /// - the score as a little endian `i16`, clamped to its range,
+    /// - the result as 0, 1 or 2 for a loss, draw or win for White.
+    pub fn pack(&self) -> [u8; PACKED_SIZE] {
+        let board = &self.board;
+        let mut bytes = [0; PACKED_SIZE];
+        bytes[..8].copy_from_slice(&board.combined.0.to_le_bytes());
+        let mut occupied = board.combined.0;
+        let mut index = 0;
+        while occupied != 0 {
+            let square = occupied.trailing_zeros() as u8;
+            occupied &= occupied - 1;
+            let (color, piece) = board.piece(square).expect("The square is occupied");
+            let nibble = (color as u8) << 3 | piece as u8;
+            bytes[8 + index / 2] |= nibble << (4 * (index % 2));
+            index += 1;
+        }
+
+        let mut flags = (board.side_to_move == Color::Black) as u8;
+        for (i, &right) in board.castling_rights.iter().enumerate() {
+            flags |= (right as u8) << (i + 1);
+        }
+        bytes[24] = flags;
+        bytes[25] = board.en_passant.unwrap_or(64);
+        bytes[26] = board.half_move_clock.min(255) as u8;
+        bytes[27..29].copy_from_slice(&(board.full_move_number.min(65535) as u16).to_le_bytes());
+        let score = self.score.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        bytes[29..31].copy_from_slice(&score.to_le_bytes());
+        bytes[31] = (self.result * 2.0).round() as u8;
+        bytes
+    }
+
+    /// Reads a position packed by `pack`.
+    pub fn unpack(bytes: &[u8; PACKED_SIZE]) -> Result<Self, String> {
+        let occupied = u64::from_le_bytes(bytes[..8].try_into().expect("8 bytes"));
+        if occupied.count_ones() > 32 {
+            return Err("More than 32 pieces in a packed position".to_string());
+        }
+        let mut squares = [None; 64];
+        let mut remaining = occupied;
+        let mut index = 0;
+        while remaining != 0 {
+            let square = remaining.trailing_zeros() as usize;
+            remaining &= remaining - 1;
+            let nibble = bytes[8 + index / 2] >> (4 * (index % 2)) & 0xf;
+            let piece = match nibble & 7 {
+                0 => 'p',
+                1 => 'n',
+                2 => 'b',
+                3 => 'r',
+                4 => 'q',
+                5 => 'k',
+                _ => return Err(format!("Invalid piece {} in a packed position", nibble)),
+            };
+            squares[square] = Some(match nibble >> 3 {
+                0 => piece.to_ascii_uppercase(),
+                _ => piece,
+            });
+            index += 1;
+        }
+
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match squares[rank * 8 + file] {
+                    Some(piece) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(piece);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+
+        let flags = bytes[24];
+        let side = if flags & 1 == 0 { "w" } else { "b" };
+        let castling: String = "KQkq"
+            .chars()
+            .enumerate()
+            .filter(|(i, _)| flags >> (i + 1) & 1 == 1)
+            .map(|(_, right)| right)
+            .collect();
+        let castling = if castling.is_empty() {
+            "-".to_string()
+        } else {
+            castling
+        };
+        let en_passant = match bytes[25] {
+            64 => "-".to_string(),
+            square if square < 64 => {
+                format!("{}{}", (b'a' + square % 8) as char, square / 8 + 1)
+            }
+            square => return Err(format!("Invalid en passant square {}", square)),
+        };
+        let full_move_number = u16::from_le_bytes([bytes[27], bytes[28]]);
+        let fen = format!(
+            "{} {} {} {} {} {}",
+            placement, side, castling, en_passant, bytes[26], full_move_number
+        );
+        let result = match bytes[31] {
+            result @ 0..=2 => result as f64 / 2.0,
+            result => return Err(format!("Invalid result {} in a packed position", result)),
+        };
+        Ok(Record {
+            board: Board::from_fen(&fen)?,
+            score: i16::from_le_bytes([bytes[29], bytes[30]]) as i32,
+            result,
+        })
+    }
+}
+
+/// The settings of the self-play games.
+#[derive(Debug, Clone)]
+pub struct SelfPlay {
+    /// The nodes searched per move.
+    pub nodes: u64,
+    /// The random moves played from the starting position.
+    pub random_plies: u32,
+    /// Games still running after this many plies are drawn.
+    pub max_plies: u32,
+}
+
+impl Default for SelfPlay {
+    fn default() -> Self {
+        SelfPlay {
+            nodes: 5000,
+            random_plies: 8,
+            max_plies: 400,
+        }
+    }
+}
+
+impl SelfPlay {
+    /// Plays one game from `start` and returns its recorded positions. Returns no
+    /// positions when the random opening already ended the game.
+    pub fn play_game(&self, start: &Board, search: &mut Search, rng: &mut Rng) -> Vec<Record> {
+        let mut board = start.clone();
+        for _ in 0..self.random_plies {
+            let moves = MoveGenerator::generate_legal_moves(&mut board);
+            if moves.is_empty() {
+                return Vec::new();
+            }
+            board.make_move(moves[rng.below(moves.len())]);
+        }
+        if MoveGenerator::generate_legal_moves(&mut board).is_empty() || board.is_draw() {
+            return Vec::new();
+        }
+
+        search.new_game();
+        let limits = Limits {
+            nodes: Some(self.nodes),
+            ..Limits::default()
+        };
+        let mut records = Vec::new();
+        let mut result = 0.5;
+        for _ in 0..self.max_plies {
+            if board.is_draw() {
+                break;
+            }
+            let moves = MoveGenerator::generate_legal_moves(&mut board);
+            if moves.is_empty() {
+                if board.is_king_in_check(board.side_to_move) {
+                    result = white_result(board.side_to_move.opposite());
+                }
+                break;
+            }
+
+            let found = search.search_with_limits(&mut board, &limits, |_| {});
+            let Some(best_move) = found.best_move() else {
+                break;
+            };
+            let score = match found.score {
+                Score::Centipawns(score) => score,
+                // The search has seen the end of the game, no need to play it out
+                Score::Mate(moves) => {
+                    let winner = if moves > 0 {
+                        board.side_to_move
+                    } else {
+                        board.side_to_move.opposite()
+                    };
+                    result = white_result(winner);
+                    break;
+                }
+            };
+
+            let noisy = best_move.captured_piece.is_some()
+                || best_move.promoted_piece.is_some()
+                || board.is_king_in_check(board.side_to_move);
+            if !noisy {
+                let score = match board.side_to_move {
+                    Color::White => score,
+                    Color::Black => -score,
+                };
+                records.push(Record {
+                    board: board.clone(),
+                    score,
+                    result: 0.0,
+                });
+            }
+            board.make_move(best_move);
+        }
+
+        for record in &mut records {
+            record.result = result;
+        }
+        records
+    }
+}
+
+fn white_result(winner: Color) -> f64 {
+    match winner {
+        Color::White => 1.0,
+        Color::Black => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuner::Sample;
+    use chess::board::STARTING_FEN;
+
+    #[test]
+    fn test_pack_round_trip() {
+        for fen in [
+            STARTING_FEN,
+            "r3k2r/pPp2ppp/8/3pP3/8/8/PPP2PPP/R3K2R w Kq d6 7 23",
+            "8/8/4k3/8/8/8/8/4K3 b - - 99 300",
+        ] {
+            let record = Record {
+                board: Board::from_fen(fen).unwrap(),
+                score: -123,
+                result: 0.5,
+            };
+            let unpacked = Record::unpack(&record.pack()).unwrap();
+            assert_eq!(unpacked.board.to_fen(), fen);
+            assert_eq!((unpacked.score, unpacked.result), (-123, 0.5));
+        }
+    }
+
+    #[test]
+    fn test_text_format_is_read_by_the_tuner() {
+        let record = Record {
+            board: Board::from_fen(STARTING_FEN).unwrap(),
+            score: 25,
+            result: 1.0,
+        };
+        let sample = Sample::parse(&record.to_text()).unwrap().unwrap();
+        assert_eq!(sample.result, 1.0);
+        assert_eq!(sample.board.to_fen(), STARTING_FEN);
+    }
+
+    #[test]
+    fn test_self_play_records_quiet_positions() {
+        let self_play = SelfPlay {
+            nodes: 500,
+            random_plies: 4,
+            max_plies: 30,
+        };
+        let start = Board::from_fen(STARTING_FEN).unwrap();
+        let records = self_play.play_game(&start, &mut Search::default(), &mut Rng::new(1));
+        assert!(!records.is_empty());
+        for record in &records {
+            assert!(!record.board.is_king_in_check(record.board.side_to_move));
+            assert_eq!(record.result, records[0].result);
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! Specialized evaluations of endgames that the classical terms misjudge, looked up by
+//! the material of both sides. A matching endgame replaces the classical evaluation,
+//! see `EvalTrace::endgame`.
+//!
+//! Every endgame is evaluated for its strong side, the one with the pieces named first
+//! in its code such as `KRKP`. To share code between the colors the squares are
+//! normalized so that the strong side plays up the board.
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use chess::{
+    board::Board,
+    kpk,
+    piece::{Color, PieceType},
+    variant::Variant,
+};
+
+use crate::eval::PIECE_VALUES;
+
+/// A score that the strong side wins for sure, but not one the search counts as a
+/// mate. Wins of different endgames are ordered by what is added to it.
+pub const KNOWN_WIN: i32 = 10_000;
+
+const LIGHT_SQUARES: u64 = 0x55AA_55AA_55AA_55AA;
+
+/// Evaluates an endgame for the strong side, or returns None where it has nothing
+/// better to say than the classical evaluation.
+type Endgame = fn(&Board, Color) -> Option<i32>;
+
+/// The endgames with exactly this material, by the code of their material.
+const ENDGAMES: [(&str, Endgame); 5] = [
+    ("KBNK", kbnk),
+    ("KPK", kpk),
+    ("KRKP", krkp),
+    ("KQKP", kqkp),
+    ("KBPK", kbpk),
+];
+
+/// The endgames of `ENDGAMES` by material key, for both choices of the strong side.
+static TABLE: LazyLock<HashMap<u64, (Endgame, Color)>> = LazyLock::new(|| {
+    let mut table = HashMap::new();
+    for (code, endgame) in ENDGAMES {
+        let split = code[1..].find('K').expect("Both sides have a king") + 1;
+        let (strong, weak) = code.split_at(split);
+        for strong_color in [Color::White, Color::Black] {
+            let mut counts = [[0; 5]; 2];
+            counts[strong_color as usize] = piece_counts(strong);
+            counts[strong_color.opposite() as usize] = piece_counts(weak);
+            table.insert(key(&counts), (endgame, strong_color));
+        }
+    }
+    table
+});
+
+fn piece_counts(code: &str) -> [u32; 5] {
+    let mut counts = [0; 5];
+    for piece in code.chars().filter(|&c| c != 'K') {
+        counts["PNBRQ".find(piece).expect("A piece letter")] += 1;
+    }
+    counts
+}
+
+/// Packs the number of pieces of every type but the king into a key, four bits each.
+fn key(counts: &[[u32; 5]; 2]) -> u64 {
+    counts
+        .iter()
+        .flatten()
+        .fold(0, |key, &count| key << 4 | count.min(15) as u64)
+}
+
+fn material_counts(board: &Board) -> [[u32; 5]; 2] {
+    [Color::White, Color::Black]
+        .map(|color| std::array::from_fn(|piece| board.bitboards[color as usize][piece].popcnt()))
+}
+
+/// The specialized evaluation of `board` from White's point of view, if its material
+/// is a known endgame. The endgames are those of standard chess.
+pub fn evaluate_endgame(board: &Board) -> Option<i32> {
+    if board.variant != Variant::Standard {
+        return None;
+    }
+    let counts = material_counts(board);
+    let (endgame, strong) = match TABLE.get(&key(&counts)) {
+        Some(&entry) => entry,
+        None => {
+            // Any mating material against a lone king
+            let strong = [Color::White, Color::Black]
+                .into_iter()
+                .find(|&color| can_mate(&counts[color as usize]))?;
+            if counts[strong.opposite() as usize] != [0; 5] {
+                return None;
+            }
+            (kxk as Endgame, strong)
+        }
+    };
+    let score = endgame(board, strong)?;
+    Some(match strong {
+        Color::White => score,
+        Color::Black => -score,
+    })
+}
+
+/// Returns true if the pieces can force mate on their own.
+fn can_mate(counts: &[u32; 5]) -> bool {
+    let [_, knights, bishops, rooks, queens] = *counts;
+    queens > 0 || rooks > 0 || bishops >= 2 || (bishops > 0 && knights > 0)
+}
+
+fn square(board: &Board, color: Color, piece: PieceType) -> u8 {
+    board.bitboards[color as usize][piece as usize].to_square()
+}
+
+/// Flips `square` so that `strong` plays up the board.
+fn normalize(square: u8, strong: Color) -> u8 {
+    match strong {
+        Color::White => square,
+        Color::Black => square ^ 56,
+    }
+}
+
+fn file(square: u8) -> i32 {
+    (square % 8) as i32
+}
+
+fn rank(square: u8) -> i32 {
+    (square / 8) as i32
+}
+
+/// The number of king moves between two squares.
+fn distance(a: u8, b: u8) -> i32 {
+    (file(a) - file(b)).abs().max((rank(a) - rank(b)).abs())
+}
+
+/// Rewards driving the king on `square` towards the edge of the board.
+fn push_to_edge(square: u8) -> i32 {
+    10 * ((2 * file(square) - 7).abs() + (2 * rank(square) - 7).abs())
+}
+
+/// Rewards bringing the two kings together.
+fn push_close(a: u8, b: u8) -> i32 {
+    140 - 20 * distance(a, b)
+}
+
+/// Rewards driving the king on `square` into a corner of the color of `bishop`.
+fn push_to_corner(square: u8, bishop: u8) -> i32 {
+    let corners: [u8; 2] = if LIGHT_SQUARES >> bishop & 1 == 1 {
+        [7, 56]
+    } else {
+        [0, 63]
+    };
+    let manhattan =
+        |corner: u8| (file(square) - file(corner)).abs() + (rank(square) - rank(corner)).abs();
+    let nearest = corners
+        .map(manhattan)
+        .into_iter()
+        .min()
+        .expect("Two corners");
+    30 * (14 - nearest)
+}
+
+fn piece_material(board: &Board, color: Color) -> i32 {
+    (0..5)
+        .map(|piece| {
+            PIECE_VALUES[piece].eg * board.bitboards[color as usize][piece].popcnt() as i32
+        })
+        .sum()
+}
+
+/// Mating material against a lone king: drive it to the edge with the own king close.
+fn kxk(board: &Board, strong: Color) -> Option<i32> {
+    let weak = strong.opposite();
+    let strong_king = square(board, strong, PieceType::King);
+    let weak_king = square(board, weak, PieceType::King);
+    Some(
+        KNOWN_WIN
+            + piece_material(board, strong)
+            + push_to_edge(weak_king)
+            + push_close(strong_king, weak_king),
+    )
+}
+
+/// Bishop and knight against a lone king can only mate in a corner of the bishop's
+/// color, with the knight close enough to take part.
+fn kbnk(board: &Board, strong: Color) -> Option<i32> {
+    let weak = strong.opposite();
+    let strong_king = square(board, strong, PieceType::King);
+    let weak_king = square(board, weak, PieceType::King);
+    let bishop = square(board, strong, PieceType::Bishop);
+    let knight = square(board, strong, PieceType::Knight);
+    Some(
+        KNOWN_WIN
+            + push_close(strong_king, weak_king)
+            + push_to_corner(weak_king, bishop)
+            + push_close(knight, weak_king) / 4,
+    )
+}
+
+/// King and pawn against king, looked up in the KPK bitbase. Drawn positions score
+/// zero, won ones more the further the pawn has advanced.
+fn kpk(board: &Board, strong: Color) -> Option<i32> {
+    let weak = strong.opposite();
+    let strong_king = normalize(square(board, strong, PieceType::King), strong);
+    let weak_king = normalize(square(board, weak, PieceType::King), strong);
+    let pawn = normalize(square(board, strong, PieceType::Pawn), strong);
+    let side_to_move = if board.side_to_move == strong {
+        Color::White
+    } else {
+        Color::Black
+    };
+
+    if kpk::probe(strong_king, pawn, weak_king, side_to_move) {
+        Some(KNOWN_WIN + PIECE_VALUES[PieceType::Pawn as usize].eg + 10 * rank(pawn))
+    } else {
+        Some(0)
+    }
+}
+
+/// Rook against pawn, after the evaluation of the same endgame in Stockfish: won when
+/// the attacking king stops the pawn or the defending king is too far to support it,
+/// and otherwise closer the further the pawn has to go.
+fn krkp(board: &Board, strong: Color) -> Option<i32> {
+    let weak = strong.opposite();
+    let strong_king = normalize(square(board, strong, PieceType::King), strong);
+    let weak_king = normalize(square(board, weak, PieceType::King), strong);
+    let rook = normalize(square(board, strong, PieceType::Rook), strong);
+    let pawn = normalize(square(board, weak, PieceType::Pawn), strong);
+    let queening = file(pawn) as u8;
+    let rook_value = PIECE_VALUES[PieceType::Rook as usize].eg;
+
+    let in_front = strong_king < pawn && file(strong_king) == file(pawn);
+    let too_far = distance(weak_king, pawn) >= 3 + (board.side_to_move == weak) as i32
+        && distance(weak_king, rook) >= 3;
+    let score = if in_front || too_far {
+        rook_value - distance(strong_king, pawn)
+    } else if rank(weak_king) <= 2
+        && distance(weak_king, pawn) == 1
+        && rank(strong_king) >= 3
+        && distance(strong_king, pawn) > 2 + (board.side_to_move == strong) as i32
+    {
+        80 - 8 * distance(strong_king, pawn)
+    } else {
+        let next = pawn - 8;
+        200 - 8
+            * (distance(strong_king, next) - distance(weak_king, next) - distance(pawn, queening))
+    };
+    Some(score)
+}
+
+/// Queen against pawn is won, unless a bishop or rook pawn on the seventh rank is
+/// supported by its king, which can draw by stalemate.
+fn kqkp(board: &Board, strong: Color) -> Option<i32> {
+    let weak = strong.opposite();
+    let strong_king = normalize(square(board, strong, PieceType::King), strong);
+    let weak_king = normalize(square(board, weak, PieceType::King), strong);
+    let pawn = normalize(square(board, weak, PieceType::Pawn), strong);
+
+    let mut score = push_close(strong_king, weak_king);
+    let drawish_file = matches!(file(pawn), 0 | 2 | 5 | 7);
+    if rank(pawn) != 1 || distance(weak_king, pawn) != 1 || !drawish_file {
+        score +=
+            PIECE_VALUES[PieceType::Queen as usize].eg - PIECE_VALUES[PieceType::Pawn as usize].eg;
+    }
+    Some(score)
+}
+
+/// A rook pawn with a bishop that does not control the queening square is a draw once
+/// the defending king reaches the corner.
+fn kbpk(board: &Board, strong: Color) -> Option<i32> {
+    let weak = strong.opposite();
+    let pawn = square(board, strong, PieceType::Pawn);
+    let bishop = square(board, strong, PieceType::Bishop);
+    let weak_king = square(board, weak, PieceType::King);
+    let queening = match strong {
+        Color::White => pawn % 8 + 56,
+        Color::Black => pawn % 8,
+    };
+
+    let rook_pawn = file(pawn) == 0 || file(pawn) == 7;
+    let wrong_bishop = (LIGHT_SQUARES >> bishop & 1) != (LIGHT_SQUARES >> queening & 1);
+    if rook_pawn && wrong_bishop && distance(weak_king, queening) <= 1 {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::Search;
+    use chess::move_generator::MoveGenerator;
+
+    fn endgame(fen: &str) -> Option<i32> {
+        evaluate_endgame(&Board::from_fen(fen).unwrap())
+    }
+
+    /// Plays the position out with a search of `depth` plies for both sides and
+    /// returns the number of moves of the side to move until it mates, if it does.
+    fn moves_to_mate(fen: &str, depth: u32, budget: u32) -> Option<u32> {
+        let mut board = Board::from_fen(fen).unwrap();
+        let attacker = board.side_to_move;
+        let mut search = Search::default();
+        for ply in 0..2 * budget {
+            if MoveGenerator::generate_legal_moves(&mut board).is_empty() {
+                let mated = board.is_king_in_check(board.side_to_move);
+                return (mated && board.side_to_move != attacker).then_some(ply.div_ceil(2));
+            }
+            if board.is_draw() {
+                return None;
+            }
+            let best_move = search.search(&mut board, depth).best_move()?;
+            board.make_move(best_move);
+        }
+        None
+    }
+
+    #[test]
+    fn test_material_signatures() {
+        let kbnk = endgame("8/8/8/4k3/8/8/8/1NB1K3 w - - 0 1").unwrap();
+        assert!(kbnk > KNOWN_WIN);
+        assert_eq!(
+            endgame("1nb1k3/8/8/8/4K3/8/8/8 b - - 0 1"),
+            Some(-kbnk),
+            "White's view for either strong side"
+        );
+        assert!(endgame("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap() > KNOWN_WIN);
+        assert_eq!(endgame("4k3/8/8/8/8/8/8/NN2K3 w - - 0 1"), None);
+        assert_eq!(
+            endgame("4k3/8/8/8/8/8/p7/R3K3 w - - 0 1").map(|s| s > 0),
+            Some(true)
+        );
+        assert_eq!(endgame("4k3/pp6/8/8/8/8/8/R3K3 w - - 0 1"), None);
+    }
+
+    #[test]
+    fn test_lone_king_is_driven_to_the_corner() {
+        let center = endgame("8/8/8/3k4/8/3K4/8/R7 w - - 0 1").unwrap();
+        let edge = endgame("3k4/8/3K4/8/8/8/8/R7 w - - 0 1").unwrap();
+        assert!(edge > center);
+
+        // With bishop and knight only the corners of the bishop's color count
+        let right_corner = endgame("7k/8/5K2/8/8/8/8/2B1N3 w - - 0 1").unwrap();
+        let wrong_corner = endgame("k7/8/2K5/8/8/8/8/2B1N3 w - - 0 1").unwrap();
+        assert!(right_corner > wrong_corner);
+    }
+
+    #[test]
+    fn test_drawn_endings() {
+        // The wrong bishop for the h-pawn, against the right one
+        assert_eq!(endgame("7k/8/8/8/8/8/7P/3BK3 w - - 0 1"), Some(0));
+        assert_eq!(endgame("7k/8/8/8/8/8/7P/2B1K3 w - - 0 1"), None);
+        // A bishop pawn on the seventh supported by its king holds the queen off
+        let drawish = endgame("8/8/8/8/6Q1/8/2p5/1k2K3 w - - 0 1").unwrap();
+        let lost = endgame("8/8/8/8/6Q1/8/3p4/1k2K3 w - - 0 1").unwrap();
+        assert!(drawish < 200 && lost > 500, "{} {}", drawish, lost);
+        // A pawn is only won with the king ahead of it
+        assert!(endgame("8/8/8/4k3/8/4K3/4P3/8 b - - 0 1").unwrap() > KNOWN_WIN);
+        assert_eq!(endgame("8/8/8/4k3/8/4K3/4P3/8 w - - 0 1"), Some(0));
+        assert!(endgame("8/4p3/8/4k3/8/4K3/8/8 w - - 0 1").unwrap() < -KNOWN_WIN);
+        // The rook wins when its king is in front of the pawn
+        let won = endgame("8/8/8/8/3k4/8/3p4/3K3R w - - 0 1").unwrap();
+        let close = endgame("8/8/8/8/8/2k5/R2p4/6K1 w - - 0 1").unwrap();
+        assert!(won > close, "{} {}", won, close);
+    }
+
+    #[test]
+    fn test_engine_mates_within_budget() {
+        assert!(moves_to_mate("8/8/8/2k5/8/8/8/3QK3 w - - 0 1", 3, 12).is_some());
+        assert!(moves_to_mate("8/8/8/3k4/8/8/8/R3K3 w - - 0 1", 3, 20).is_some());
+        assert!(moves_to_mate("8/8/8/8/8/2k5/8/KBN5 w - - 0 1", 5, 40).is_some());
+    }
+}
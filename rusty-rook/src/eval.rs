@@ -0,0 +1,788 @@
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign},
+};
+
+use chess::{
+    board::Board,
+    piece::{Color, PieceType},
+    variant::Variant,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    attacks::Attacks,
+    endgame::evaluate_endgame,
+    king_safety::evaluate_king_safety,
+    params::{long_array, EvalParams},
+    pawns::{evaluate_pawns, PawnTable},
+    pieces::{evaluate_mobility, evaluate_pieces, evaluate_threats},
+};
+
+/// A static evaluation of a position.
+///
+/// Scores are in centipawns from the point of view of the side to move, so that
+/// the search can negate them between plies. Evaluators are shared by all search
+/// threads.
+pub trait Evaluator: Send + Sync {
+    fn evaluate(&self, board: &Board) -> i32;
+
+    /// Creates the state of an evaluator that is updated incrementally as moves are
+    /// made and unmade, one per search thread. None for evaluators without one.
+    fn new_state(&self) -> Option<Box<dyn EvalState>> {
+        None
+    }
+}
+
+/// The per-thread state of an incrementally updated evaluation.
+///
+/// The search calls `make_move` after every `Board::make_move` and `unmake` after
+/// every `Board::unmake` of the nodes it evaluates below, with the board as it is
+/// afterwards. A state must still evaluate correctly if moves were made without
+/// it, at the cost of recomputing from scratch.
+pub trait EvalState: Send {
+    fn make_move(&mut self, board: &Board);
+    fn unmake(&mut self, board: &Board);
+    fn evaluate(&mut self, board: &Board) -> i32;
+}
+
+/// An evaluation term with separate midgame and endgame values.
+/// The two are blended by `taper` according to the game phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct TaperedScore {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl TaperedScore {
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        TaperedScore { mg, eg }
+    }
+
+    /// Interpolates between the midgame and endgame values, `phase` running from
+    /// 0 in a pawn endgame to `MAX_PHASE` with all pieces on the board.
+    pub fn taper(self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for TaperedScore {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        TaperedScore::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl AddAssign for TaperedScore {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for TaperedScore {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        TaperedScore::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl SubAssign for TaperedScore {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for TaperedScore {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        TaperedScore::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for TaperedScore {
+    type Output = Self;
+
+    fn mul(self, factor: i32) -> Self {
+        TaperedScore::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+/// The game phase with all minor and major pieces on the board.
+pub const MAX_PHASE: i32 = 24;
+/// How much each piece type contributes to the game phase, indexed by `PieceType`.
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+
+/// The bonus for having the move.
+pub(crate) const TEMPO: TaperedScore = TaperedScore::new(20, 10);
+
+/// Scale factors are fractions of `SCALE_NORMAL`.
+const SCALE_NORMAL: i32 = 64;
+/// Bishops of opposite colors with no other pieces, which are very hard to win
+/// even a pawn or two up.
+const SCALE_OPPOSITE_BISHOPS: i32 = 24;
+/// Bishops of opposite colors with other pieces still on the board.
+const SCALE_OPPOSITE_BISHOPS_WITH_PIECES: i32 = 46;
+/// Without pawns an advantage of a minor piece or less is rarely enough to win,
+/// as in KRvKB or KRvKN.
+const SCALE_NO_PAWNS: i32 = 8;
+/// The evaluation shrinks linearly to zero over the fifty move rule, where the game
+/// is a draw.
+const FIFTY_MOVE_PLIES: i32 = 100;
+
+/// The light squares of the board, b1, d1, ... h7.
+const LIGHT_SQUARES: u64 = 0x55AA_55AA_55AA_55AA;
+
+/// Material values, indexed by `PieceType`. Both kings are always on the board so
+/// the king has no value of its own. The scale factors measure material with these
+/// values whatever the parameters say.
+pub(crate) const PIECE_VALUES: [TaperedScore; 6] = [
+    TaperedScore::new(100, 120),
+    TaperedScore::new(320, 300),
+    TaperedScore::new(330, 330),
+    TaperedScore::new(500, 540),
+    TaperedScore::new(900, 960),
+    TaperedScore::new(0, 0),
+];
+
+/// Returns the game phase of `board`, from 0 when only kings and pawns are left to
+/// `MAX_PHASE` in the opening. Promotions can push the count above the maximum.
+pub fn game_phase(board: &Board) -> i32 {
+    let mut phase = 0;
+    for color in [Color::White, Color::Black] {
+        for (piece, weight) in PHASE_WEIGHTS.iter().enumerate() {
+            phase += weight * board.bitboards[color as usize][piece].popcnt() as i32;
+        }
+    }
+    phase.min(MAX_PHASE)
+}
+
+/// The value of a piece in Antichess, where the king is an ordinary piece.
+const ANTICHESS_VALUES: [i32; 6] = [100, 200, 150, 300, 400, 200];
+
+/// The evaluation of an Antichess position for the side to move. Material is a
+/// burden there, so the side with less of it left stands better. The evaluators
+/// assume both kings are on the board and are not used for Antichess.
+pub fn evaluate_antichess(board: &Board) -> i32 {
+    let material = |color: Color| -> i32 {
+        ANTICHESS_VALUES
+            .iter()
+            .enumerate()
+            .map(|(piece, value)| value * board.bitboards[color as usize][piece].popcnt() as i32)
+            .sum()
+    };
+    let side = board.side_to_move;
+    material(side.opposite()) - material(side)
+}
+
+/// The hand-crafted evaluation: material, piece-square tables, pawn structure, king
+/// safety and piece activity tapered between the midgame and endgame, scaled down in
+/// drawish endgames.
+#[derive(Default)]
+pub struct ClassicalEvaluator {
+    params: EvalParams,
+    pawn_table: PawnTable,
+}
+
+impl Evaluator for ClassicalEvaluator {
+    fn evaluate(&self, board: &Board) -> i32 {
+        self.trace(board).score()
+    }
+}
+
+impl ClassicalEvaluator {
+    pub fn new(params: EvalParams) -> Self {
+        ClassicalEvaluator {
+            params,
+            pawn_table: PawnTable::default(),
+        }
+    }
+
+    pub fn params(&self) -> &EvalParams {
+        &self.params
+    }
+
+    /// Evaluates `board` and keeps every term apart, see `EvalTrace`.
+    pub fn trace(&self, board: &Board) -> EvalTrace {
+        let params = &self.params;
+        let attacks = Attacks::new(board);
+        let mut terms = [[TaperedScore::default(); 2]; Term::ALL.len()];
+        for term in Term::ALL {
+            terms[term as usize] = match term {
+                Term::Material => [Color::White, Color::Black]
+                    .map(|color| material(board, color, &params.piece_values)),
+                Term::PieceSquares => [Color::White, Color::Black]
+                    .map(|color| piece_squares(board, color, &params.piece_squares)),
+                Term::Pawns => evaluate_pawns(board, &self.pawn_table, &params.pawns),
+                Term::KingSafety => evaluate_king_safety(board, &attacks, &params.king_safety),
+                Term::Mobility => evaluate_mobility(board, &attacks, &params.pieces),
+                Term::Pieces => evaluate_pieces(board, &attacks, &params.pieces),
+                Term::Threats => evaluate_threats(board, &attacks, &params.pieces),
+                Term::Tempo => {
+                    let mut tempo = [TaperedScore::default(); 2];
+                    tempo[board.side_to_move as usize] = params.tempo;
+                    tempo
+                }
+            };
+        }
+
+        let eg = terms.iter().map(|[white, black]| white.eg - black.eg).sum();
+        EvalTrace {
+            terms,
+            phase: game_phase(board),
+            scale: scale_factor(board, eg),
+            half_move_clock: (board.half_move_clock as i32).min(FIFTY_MOVE_PLIES),
+            side_to_move: board.side_to_move,
+            endgame: evaluate_endgame(board),
+        }
+    }
+}
+
+/// The terms of the classical evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    Material,
+    PieceSquares,
+    Pawns,
+    KingSafety,
+    Mobility,
+    Pieces,
+    Threats,
+    Tempo,
+}
+
+impl Term {
+    pub const ALL: [Term; 8] = [
+        Term::Material,
+        Term::PieceSquares,
+        Term::Pawns,
+        Term::KingSafety,
+        Term::Mobility,
+        Term::Pieces,
+        Term::Threats,
+        Term::Tempo,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::PieceSquares => "Piece squares",
+            Term::Pawns => "Pawns",
+            Term::KingSafety => "King safety",
+            Term::Mobility => "Mobility",
+            Term::Pieces => "Pieces",
+            Term::Threats => "Threats",
+            Term::Tempo => "Tempo",
+        }
+    }
+}
+
+/// The classical evaluation of a position broken down by term and color, before the
+/// terms are tapered and scaled into the final score. Displays as a table.
+#[derive(Debug, Clone)]
+pub struct EvalTrace {
+    /// The score of every term for each color, indexed by `Term` and `Color`.
+    pub terms: [[TaperedScore; 2]; Term::ALL.len()],
+    /// The game phase, from 0 in the endgame to `MAX_PHASE` in the opening.
+    pub phase: i32,
+    /// The drawish endgame scale factor out of `SCALE_NORMAL`.
+    pub scale: i32,
+    /// The half moves counted towards the fifty-move decay.
+    pub half_move_clock: i32,
+    pub side_to_move: Color,
+    /// The specialized evaluation of a known endgame from White's point of view, which
+    /// replaces the tapered terms, see `endgame`.
+    pub endgame: Option<i32>,
+}
+
+impl EvalTrace {
+    pub fn term(&self, term: Term) -> [TaperedScore; 2] {
+        self.terms[term as usize]
+    }
+
+    /// The sum of all terms from White's point of view.
+    pub fn total(&self) -> TaperedScore {
+        self.terms
+            .iter()
+            .fold(TaperedScore::default(), |total, [white, black]| {
+                total + *white - *black
+            })
+    }
+
+    /// The final score from White's point of view. It shrinks to zero over the fifty
+    /// move rule, but for known endgames only to half: those are wins the search
+    /// converts by following their progress terms, which a full decay of the large
+    /// score of a win would outweigh.
+    pub fn white_score(&self) -> i32 {
+        match self.endgame {
+            Some(endgame) => {
+                endgame * (2 * FIFTY_MOVE_PLIES - self.half_move_clock) / (2 * FIFTY_MOVE_PLIES)
+            }
+            None => {
+                let white = self.total().taper(self.phase) * self.scale / SCALE_NORMAL;
+                white * (FIFTY_MOVE_PLIES - self.half_move_clock) / FIFTY_MOVE_PLIES
+            }
+        }
+    }
+
+    /// The final score from the point of view of the side to move, as returned by
+    /// `Evaluator::evaluate`.
+    pub fn score(&self) -> i32 {
+        match self.side_to_move {
+            Color::White => self.white_score(),
+            Color::Black => -self.white_score(),
+        }
+    }
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let separator = "--------------+-------------+-------------+-------------";
+        writeln!(f, "         Term |    White    |    Black    |    Total")?;
+        writeln!(f, "              |   MG    EG  |   MG    EG  |   MG    EG")?;
+        writeln!(f, "{separator}")?;
+        let row = |f: &mut fmt::Formatter, name: &str, white: TaperedScore, black: TaperedScore| {
+            let total = white - black;
+            writeln!(
+                f,
+                "{name:>13} | {:>5} {:>5} | {:>5} {:>5} | {:>5} {:>5}",
+                white.mg, white.eg, black.mg, black.eg, total.mg, total.eg
+            )
+        };
+        for term in Term::ALL {
+            let [white, black] = self.term(term);
+            row(f, term.name(), white, black)?;
+        }
+        writeln!(f, "{separator}")?;
+        let total = self.total();
+        writeln!(
+            f,
+            "{:>13} |             |             | {:>5} {:>5}",
+            "Total", total.mg, total.eg
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {}/{MAX_PHASE}", self.phase)?;
+        writeln!(f, "Scale: {}/{SCALE_NORMAL}", self.scale)?;
+        writeln!(f, "Fifty-move clock: {}", self.half_move_clock)?;
+        if let Some(endgame) = self.endgame {
+            writeln!(f, "Known endgame: {} (White's view)", endgame)?;
+        }
+        writeln!(f, "Final evaluation: {} (White's view)", self.white_score())
+    }
+}
+
+/// Returns how much of the evaluation the side that is ahead in the endgame,
+/// according to `eg`, can expect to convert, out of `SCALE_NORMAL`.
+fn scale_factor(board: &Board, eg: i32) -> i32 {
+    // Pieces in the Crazyhouse pockets can come back anywhere
+    if board.variant == Variant::Crazyhouse {
+        return SCALE_NORMAL;
+    }
+    let strong = if eg >= 0 { Color::White } else { Color::Black };
+    let weak = strong.opposite();
+    let pieces = |color: Color, piece: PieceType| board.bitboards[color as usize][piece as usize];
+
+    if pieces(strong, PieceType::Pawn).popcnt() == 0 {
+        let advantage = non_pawn_material(board, strong) - non_pawn_material(board, weak);
+        if advantage <= PIECE_VALUES[PieceType::Bishop as usize].mg {
+            return if non_pawn_material(board, strong) < PIECE_VALUES[PieceType::Rook as usize].mg {
+                0
+            } else {
+                SCALE_NO_PAWNS
+            };
+        }
+    }
+
+    let white_bishops = pieces(Color::White, PieceType::Bishop);
+    let black_bishops = pieces(Color::Black, PieceType::Bishop);
+    if white_bishops.popcnt() == 1
+        && black_bishops.popcnt() == 1
+        && is_light(white_bishops.0) != is_light(black_bishops.0)
+    {
+        let only_bishops = [Color::White, Color::Black].iter().all(|&color| {
+            [PieceType::Knight, PieceType::Rook, PieceType::Queen]
+                .iter()
+                .all(|&piece| pieces(color, piece).popcnt() == 0)
+        });
+        return if only_bishops {
+            SCALE_OPPOSITE_BISHOPS
+        } else {
+            SCALE_OPPOSITE_BISHOPS_WITH_PIECES
+        };
+    }
+    SCALE_NORMAL
+}
+
+fn is_light(squares: u64) -> bool {
+    squares & LIGHT_SQUARES != 0
+}
+
+/// The midgame value of the knights, bishops, rooks and queens of `color`.
+fn non_pawn_material(board: &Board, color: Color) -> i32 {
+    [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ]
+    .iter()
+    .map(|&piece| {
+        PIECE_VALUES[piece as usize].mg
+            * board.bitboards[color as usize][piece as usize].popcnt() as i32
+    })
+    .sum()
+}
+
+/// The material of `color` on the board and in its Crazyhouse pocket.
+fn material(board: &Board, color: Color, values: &[TaperedScore; 6]) -> TaperedScore {
+    let mut score = TaperedScore::default();
+    for (piece, value) in values.iter().enumerate() {
+        score += *value * board.bitboards[color as usize][piece].popcnt() as i32;
+    }
+    for (piece, &count) in board.pockets[color as usize].iter().enumerate() {
+        score += values[piece] * count as i32;
+    }
+    score
+}
+
+/// The midgame value of the pieces in the Crazyhouse pockets for the side to move.
+/// The network only sees the board, so the NNUE evaluation adds this.
+pub(crate) fn pocket_material(board: &Board) -> i32 {
+    let value = |color: Color| -> i32 {
+        board.pockets[color as usize]
+            .iter()
+            .enumerate()
+            .map(|(piece, &count)| PIECE_VALUES[piece].mg * count as i32)
+            .sum()
+    };
+    value(board.side_to_move) - value(board.side_to_move.opposite())
+}
+
+fn piece_squares(board: &Board, color: Color, tables: &PieceSquareTables) -> TaperedScore {
+    let mut score = TaperedScore::default();
+    for piece in 0..6 {
+        for square in board.bitboards[color as usize][piece] {
+            score += tables.value(PieceType::from(piece), color, square);
+        }
+    }
+    score
+}
+
+/// The piece-square tables, written from White's point of view with the eighth rank
+/// first. Knights, bishops, rooks and queens use the same table in the midgame and
+/// the endgame.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PieceSquareTables {
+    #[serde(with = "long_array")]
+    pub pawn: [i32; 64],
+    #[serde(with = "long_array")]
+    pub pawn_endgame: [i32; 64],
+    #[serde(with = "long_array")]
+    pub knight: [i32; 64],
+    #[serde(with = "long_array")]
+    pub bishop: [i32; 64],
+    #[serde(with = "long_array")]
+    pub rook: [i32; 64],
+    #[serde(with = "long_array")]
+    pub queen: [i32; 64],
+    #[serde(with = "long_array")]
+    pub king: [i32; 64],
+    #[serde(with = "long_array")]
+    pub king_endgame: [i32; 64],
+}
+
+impl PieceSquareTables {
+    /// The piece-square bonus of a piece of `color` on `square`. White's squares are
+    /// flipped vertically and Black's are used as they are.
+    pub fn value(&self, piece: PieceType, color: Color, square: u8) -> TaperedScore {
+        let index = match color {
+            Color::White => square ^ 56,
+            Color::Black => square,
+        } as usize;
+        let (mg_table, eg_table) = match piece {
+            PieceType::Pawn => (&self.pawn, &self.pawn_endgame),
+            PieceType::Knight => (&self.knight, &self.knight),
+            PieceType::Bishop => (&self.bishop, &self.bishop),
+            PieceType::Rook => (&self.rook, &self.rook),
+            PieceType::Queen => (&self.queen, &self.queen),
+            PieceType::King => (&self.king, &self.king_endgame),
+        };
+        TaperedScore::new(mg_table[index], eg_table[index])
+    }
+}
+
+impl Default for PieceSquareTables {
+    fn default() -> Self {
+        PieceSquareTables {
+            pawn: PAWN_PIECE_TABLE,
+            pawn_endgame: PAWN_ENDGAME_TABLE,
+            knight: KNIGHT_PIECE_TABLE,
+            bishop: BISHOP_PIECE_TABLE,
+            rook: ROOK_PIECE_TABLE,
+            queen: QUEEN_PIECE_TABLE,
+            king: KING_PIECE_TABLE,
+            king_endgame: KING_ENDGAME_TABLE,
+        }
+    }
+}
+
+#[rustfmt::skip]
+const PAWN_PIECE_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+/// In the endgame pawns are worth more the closer they are to promoting,
+/// wherever they stand on the rank.
+#[rustfmt::skip]
+const PAWN_ENDGAME_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    90, 90, 90, 90, 90, 90, 90, 90,
+    60, 60, 60, 60, 60, 60, 60, 60,
+    40, 40, 40, 40, 40, 40, 40, 40,
+    25, 25, 25, 25, 25, 25, 25, 25,
+    10, 10, 10, 10, 10, 10, 10, 10,
+     0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_PIECE_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_PIECE_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_PIECE_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_PIECE_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+/// In the midgame the king hides behind its pawns.
+#[rustfmt::skip]
+const KING_PIECE_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+/// In the endgame the king is an attacking piece and belongs in the centre.
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{score::MATE_SCORE, search::Search};
+    use chess::board::STARTING_FEN;
+
+    fn evaluate(fen: &str) -> i32 {
+        ClassicalEvaluator::default().evaluate(&Board::from_fen(fen).unwrap())
+    }
+
+    /// Flips the board vertically and swaps the colors of all pieces and rights.
+    fn mirror_fen(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |c: char| {
+            if c.is_ascii_uppercase() {
+                c.to_ascii_lowercase()
+            } else {
+                c.to_ascii_uppercase()
+            }
+        };
+        let ranks: Vec<String> = fields[0]
+            .split('/')
+            .rev()
+            .map(|rank| rank.chars().map(swap_case).collect())
+            .collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let mut castling: Vec<char> = fields[2].chars().map(swap_case).collect();
+        castling.sort_by_key(|c| (c.is_ascii_lowercase(), "KQkq".find(c.to_ascii_uppercase())));
+        let castling: String = castling.into_iter().collect();
+        let en_passant = match fields[3] {
+            "-" => "-".to_string(),
+            square => {
+                let rank = square.chars().nth(1).unwrap().to_digit(10).unwrap();
+                format!("{}{}", &square[..1], 9 - rank)
+            }
+        };
+        format!(
+            "{} {} {} {} {} {}",
+            ranks.join("/"),
+            side,
+            castling,
+            en_passant,
+            fields[4],
+            fields[5]
+        )
+    }
+
+    #[test]
+    fn test_evaluation_is_color_symmetric() {
+        let fens = [
+            STARTING_FEN,
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "2b3k1/4pp1p/5np1/Q7/3qP3/5P2/P1PBK1PP/1r3B1R w - - 5 25",
+            "r1b1k2r/1ppp1pp1/p1n2q1p/3Pp3/2Bb4/P2PBN2/1PP2PPP/R2Q1RK1 b kq - 0 10",
+            "8/5k2/3p4/1p1Pp2p/pP2Pp1P/P4P1K/8/8 b - - 99 50",
+            "8/8/4k3/8/2P5/8/5K2/8 w - - 0 1",
+        ];
+        for fen in fens {
+            // Scores are relative to the side to move, which the mirror swaps
+            let mirrored = mirror_fen(fen);
+            assert_eq!(evaluate(fen), evaluate(&mirrored), "{}", fen);
+            assert_eq!(
+                game_phase(&Board::from_fen(fen).unwrap()),
+                game_phase(&Board::from_fen(&mirrored).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn test_starting_position_is_worth_the_tempo() {
+        assert_eq!(evaluate(STARTING_FEN), TEMPO.mg);
+        let black_to_move = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(evaluate(black_to_move), TEMPO.mg);
+        assert_eq!(
+            game_phase(&Board::from_fen(STARTING_FEN).unwrap()),
+            MAX_PHASE
+        );
+    }
+
+    #[test]
+    fn test_drawish_material_is_scaled_down() {
+        // A rook up is winning, a rook against a bishop is not
+        let rook_up = evaluate("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        let rook_against_bishop = evaluate("4k3/8/8/8/8/2b5/8/R3K3 w - - 0 1");
+        assert!(rook_up > 400);
+        assert!(rook_against_bishop.abs() < 50, "{}", rook_against_bishop);
+        assert_eq!(evaluate("4k3/8/8/8/8/8/8/N3K3 w - - 0 1"), 0);
+        assert_eq!(evaluate("4k3/8/8/8/8/8/8/4K3 b - - 0 1"), 0);
+
+        // A pawn up with bishops of opposite colors against the same colors
+        let opposite = evaluate("4k3/8/3b4/8/3P4/2PB4/8/4K3 w - - 0 1");
+        let same = evaluate("4k3/8/4b4/8/3P4/2PB4/8/4K3 w - - 0 1");
+        assert!(opposite > 0 && opposite < same / 2, "{} {}", opposite, same);
+    }
+
+    #[test]
+    fn test_fifty_move_counter_scales_towards_zero() {
+        let at_clock = |clock| evaluate(&format!("4k3/4p3/8/8/8/8/3P4/R3K3 w - - {} 60", clock));
+        let fresh = at_clock(0);
+        assert!(fresh > 0);
+        assert_eq!(at_clock(50), fresh / 2);
+        let stale = at_clock(80);
+        assert!(stale > 0 && stale < fresh / 4, "{} {}", stale, fresh);
+        assert_eq!(at_clock(100), 0);
+
+        // A known endgame only shrinks to half
+        let fresh = evaluate("4k3/8/8/8/8/8/3P4/R3K3 w - - 0 60");
+        let stale = evaluate("4k3/8/8/8/8/8/3P4/R3K3 w - - 80 60");
+        assert!(stale > 0 && stale < fresh * 3 / 4, "{} {}", stale, fresh);
+        let drawn = "4k3/8/8/8/8/8/3P4/R3K3 w - - 100 60";
+        assert_eq!(evaluate(drawn), fresh / 2);
+
+        // but the search scores a leaf the rule has drawn as a draw
+        let mut board = Board::from_fen(drawn).unwrap();
+        let (score, _) = Search::default().negamax(&mut board, 0, 1, -MATE_SCORE, MATE_SCORE, 0);
+        assert_eq!(score, 0);
+    }
+
+    #[test]
+    fn test_game_phase_tapers_king_placement() {
+        assert_eq!(TaperedScore::new(100, 20).taper(MAX_PHASE), 100);
+        assert_eq!(TaperedScore::new(100, 20).taper(0), 20);
+        assert_eq!(TaperedScore::new(100, 20).taper(MAX_PHASE / 2), 60);
+
+        // With only pawns left the central king is better than the cornered one
+        let central = "8/8/4k3/8/8/3K4/3P4/8 w - - 0 1";
+        let cornered = "8/8/4k3/8/8/8/3P4/K7 w - - 0 1";
+        assert_eq!(game_phase(&Board::from_fen(central).unwrap()), 0);
+        assert!(evaluate(central) > evaluate(cornered));
+
+        // With all pieces on the board the castled king is better
+        let castled = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1RK1 w kq - 0 1";
+        let central = "rnbqkbnr/pppppppp/8/8/8/4K3/PPPPPPPP/RNBQ1R2 w kq - 0 1";
+        assert!(evaluate(castled) > evaluate(central));
+    }
+
+    #[test]
+    fn test_trace_adds_up_to_the_evaluation() {
+        let evaluator = ClassicalEvaluator::default();
+        let fen = "r1b1k2r/1ppp1pp1/p1n2q1p/3Pp3/2Bb4/P2PBN2/1PP2PPP/R2Q1RK1 b kq - 0 10";
+        let board = Board::from_fen(fen).unwrap();
+        let trace = evaluator.trace(&board);
+        assert_eq!(trace.score(), evaluator.evaluate(&board));
+        assert_eq!(trace.term(Term::Tempo), [TaperedScore::default(), TEMPO]);
+        assert_eq!(
+            trace.term(Term::Material)[Color::White as usize],
+            trace.term(Term::Material)[Color::Black as usize]
+        );
+
+        let table = trace.to_string();
+        for term in Term::ALL {
+            assert!(table.contains(term.name()));
+        }
+        assert!(table.contains(&format!("Final evaluation: {}", -trace.score())));
+    }
+}
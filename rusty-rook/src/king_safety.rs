@@ -0,0 +1,253 @@
+//! King safety evaluation. The pieces attacking the squares around a king and the
+//! checks the enemy can give safely add up to a number of attack units, which are
+//! turned into a penalty through a nonlinear table: a single attacker is rarely
+//! dangerous, several together quickly become decisive. The pawns in front of the
+//! king and the files around it are scored directly.
+
+use chess::{
+    board::Board,
+    move_generator::MoveGenerator,
+    piece::{Color, PieceType},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    attacks::Attacks,
+    eval::TaperedScore,
+    masks::{file_mask, file_of, forward_ranks, relative_rank},
+    params::long_array,
+};
+
+/// Attack units for every square of the king zone a piece attacks, indexed by
+/// `PieceType`.
+const ATTACK_WEIGHTS: [i32; 6] = [0, 2, 2, 3, 5, 0];
+/// Attack units for every square a piece can safely give check from, indexed by
+/// `PieceType`.
+const SAFE_CHECK_WEIGHTS: [i32; 6] = [0, 12, 6, 10, 8, 0];
+/// Attack units for every square of the king zone the enemy attacks and only the
+/// king defends.
+const WEAK_SQUARE_WEIGHT: i32 = 3;
+/// Attacks on the king zone only count when at least this many pieces take part.
+const MIN_ATTACKERS: u32 = 2;
+
+pub const DANGER_UNITS: usize = 80;
+/// The penalty for a number of attack units, growing quadratically up to a cap.
+const DANGER: [i32; DANGER_UNITS] = {
+    let mut table = [0; DANGER_UNITS];
+    let mut units = 0;
+    while units < DANGER_UNITS {
+        let danger = (units * units / 8) as i32;
+        table[units] = if danger < 500 { danger } else { 500 };
+        units += 1;
+    }
+    table
+};
+
+/// Bonus for the own pawn closest to the king on each file of the shelter, by its
+/// relative rank. Index 0 stands for a file without such a pawn.
+const SHIELD: [i32; 8] = [-36, 24, 14, 4, 0, 0, 0, 0];
+/// Penalty for the enemy pawn closest to the king on each file of the shelter, by its
+/// relative rank. Index 0 stands for a file without such a pawn.
+const STORM: [i32; 8] = [0, -10, -30, -20, -10, -4, 0, 0];
+/// A storming pawn that runs into an own pawn cannot open the file.
+const BLOCKED_STORM_DIVISOR: i32 = 3;
+
+/// A file next to the king without any pawns, and one with only enemy pawns.
+const OPEN_FILE: TaperedScore = TaperedScore::new(-20, 0);
+const SEMI_OPEN_FILE: TaperedScore = TaperedScore::new(-10, 0);
+
+/// The weights of the king safety terms, the constants above by default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KingSafetyParams {
+    pub attack_weights: [i32; 6],
+    pub safe_check_weights: [i32; 6],
+    pub weak_square_weight: i32,
+    #[serde(with = "long_array")]
+    pub danger: [i32; DANGER_UNITS],
+    pub shield: [i32; 8],
+    pub storm: [i32; 8],
+    pub open_file: TaperedScore,
+    pub semi_open_file: TaperedScore,
+}
+
+impl Default for KingSafetyParams {
+    fn default() -> Self {
+        KingSafetyParams {
+            attack_weights: ATTACK_WEIGHTS,
+            safe_check_weights: SAFE_CHECK_WEIGHTS,
+            weak_square_weight: WEAK_SQUARE_WEIGHT,
+            danger: DANGER,
+            shield: SHIELD,
+            storm: STORM,
+            open_file: OPEN_FILE,
+            semi_open_file: SEMI_OPEN_FILE,
+        }
+    }
+}
+
+/// Evaluates the safety of both kings, indexed by `Color`.
+pub fn evaluate_king_safety(
+    board: &Board,
+    attacks: &Attacks,
+    params: &KingSafetyParams,
+) -> [TaperedScore; 2] {
+    [Color::White, Color::Black].map(|color| king_safety(board, attacks, color, params))
+}
+
+fn king_safety(
+    board: &Board,
+    attacks: &Attacks,
+    color: Color,
+    params: &KingSafetyParams,
+) -> TaperedScore {
+    let king = board.bitboards[color as usize][PieceType::King as usize].to_square();
+    let units = attack_units(board, attacks, color, king, params) as usize;
+    let danger = params.danger[units.min(DANGER_UNITS - 1)];
+    TaperedScore::new(-danger, -danger / 8) + shelter(board, color, king, params)
+}
+
+/// Counts the attack units against the king of `color` on `king`.
+fn attack_units(
+    board: &Board,
+    attacks: &Attacks,
+    color: Color,
+    king: u8,
+    params: &KingSafetyParams,
+) -> i32 {
+    let own = color as usize;
+    let enemy = color.opposite() as usize;
+    let king_attacks = MoveGenerator::king_attacks(king);
+    let zone = king_attacks | (1 << king);
+    let weak = zone & attacks.all[enemy] & !attacks.double[own];
+
+    let mut attackers = 0;
+    let mut units = 0;
+    for piece in &attacks.pieces[enemy] {
+        let hits = piece.attacks & zone;
+        if hits != 0 {
+            attackers += 1;
+            units += params.attack_weights[piece.piece as usize] * hits.count_ones() as i32;
+        }
+    }
+    units += params.weak_square_weight * weak.count_ones() as i32;
+    if attackers < MIN_ATTACKERS {
+        units = 0;
+    }
+
+    // Squares the enemy gives check from that are not occupied by its own pieces and
+    // either not defended, or defended by the king alone but attacked twice
+    let safe_squares = !board.occupied[enemy].0
+        & (!attacks.all[own] | (weak & king_attacks & attacks.double[enemy]));
+    let diagonal = MoveGenerator::bishop_attacks(king, board.combined);
+    let straight = MoveGenerator::rook_attacks(king, board.combined);
+    let checks = [
+        (PieceType::Knight, MoveGenerator::knight_attacks(king)),
+        (PieceType::Bishop, diagonal),
+        (PieceType::Rook, straight),
+        (PieceType::Queen, diagonal | straight),
+    ];
+    for (piece, squares) in checks {
+        let safe = squares & attacks.by_piece[enemy][piece as usize] & safe_squares;
+        units += params.safe_check_weights[piece as usize] * safe.count_ones() as i32;
+    }
+    units
+}
+
+/// Scores the pawn shield, the pawn storm and the open files on the king's file and
+/// the files next to it.
+fn shelter(board: &Board, color: Color, king: u8, params: &KingSafetyParams) -> TaperedScore {
+    let own_pawns = board.bitboards[color as usize][PieceType::Pawn as usize].0;
+    let enemy_pawns = board.bitboards[color.opposite() as usize][PieceType::Pawn as usize].0;
+    let in_front = forward_ranks(king, color) | (0xFF << (8 * (king / 8)));
+
+    let mut score = TaperedScore::default();
+    let center = file_of(king).clamp(1, 6);
+    for file in center - 1..=center + 1 {
+        let own = own_pawns & file_mask(file);
+        let enemy = enemy_pawns & file_mask(file);
+
+        let shield = closest(own & in_front, color);
+        score.mg += params.shield[shield.map_or(0, |sq| relative_rank(sq, color)) as usize];
+
+        if let Some(storm) = closest(enemy & in_front, color) {
+            let rank = relative_rank(storm, color);
+            let blocked = shield.is_some_and(|sq| relative_rank(sq, color) + 1 == rank);
+            let penalty = params.storm[rank as usize];
+            score.mg += if blocked {
+                penalty / BLOCKED_STORM_DIVISOR
+            } else {
+                penalty
+            };
+        }
+
+        if own == 0 {
+            score += if enemy == 0 {
+                params.open_file
+            } else {
+                params.semi_open_file
+            };
+        }
+    }
+    score
+}
+
+/// The square in `squares` closest to the back rank of `color`.
+fn closest(squares: u64, color: Color) -> Option<u8> {
+    if squares == 0 {
+        return None;
+    }
+    Some(match color {
+        Color::White => squares.trailing_zeros() as u8,
+        Color::Black => 63 - squares.leading_zeros() as u8,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn safety(fen: &str, color: Color) -> TaperedScore {
+        let board = Board::from_fen(fen).unwrap();
+        let params = KingSafetyParams::default();
+        evaluate_king_safety(&board, &Attacks::new(&board), &params)[color as usize]
+    }
+
+    #[test]
+    fn test_pawn_shelter() {
+        let sheltered = safety("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1", Color::White);
+        let advanced = safety("6k1/8/8/8/8/5PPP/8/6K1 w - - 0 1", Color::White);
+        let exposed = safety("6k1/8/8/8/8/8/8/6K1 w - - 0 1", Color::White);
+        let stormed = safety("6k1/8/8/8/8/6pp/5P2/6K1 w - - 0 1", Color::White);
+        assert!(sheltered.mg > advanced.mg);
+        assert!(advanced.mg > exposed.mg);
+        assert!(stormed.mg < advanced.mg);
+
+        // The same shelter seen from Black
+        let black = safety("6k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", Color::Black);
+        assert_eq!(black, sheltered);
+    }
+
+    #[test]
+    fn test_attackers_and_safe_checks() {
+        let quiet = safety("6k1/5ppp/8/8/8/8/5PPP/1Q1R2K1 w - - 0 1", Color::Black);
+        // Queen and knight both hit the squares around the king, Qxh7 is a safe check
+        let attacked = safety("6k1/5ppp/7Q/6N1/8/8/5PPP/6K1 w - - 0 1", Color::Black);
+        assert!(attacked.mg < quiet.mg - 50);
+
+        // A lone attacker only counts through its safe checks
+        let lone_rook = safety("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", Color::Black);
+        let shelter = safety("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1", Color::Black);
+        assert_eq!(
+            lone_rook,
+            shelter + TaperedScore::new(-DANGER[10], -DANGER[10] / 8)
+        );
+    }
+
+    #[test]
+    fn test_danger_grows_nonlinearly() {
+        assert_eq!(DANGER[0], 0);
+        assert!(DANGER[20] > 2 * DANGER[10]);
+        assert_eq!(DANGER[DANGER_UNITS - 1], 500);
+    }
+}
@@ -1 +1,19 @@
+pub mod attacks;
+pub mod book;
+pub mod datagen;
+pub mod endgame;
+pub mod eval;
+pub mod king_safety;
+pub mod masks;
+pub mod nnue;
+pub mod params;
+pub mod pawns;
+pub mod pieces;
+pub mod rng;
 pub mod score;
+pub mod search;
+mod smp;
+pub mod syzygy;
+pub mod time;
+pub mod tt;
+pub mod tuner;
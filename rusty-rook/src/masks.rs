@@ -0,0 +1,120 @@
+//! Bitboard masks of files, ranks and the squares in front of a square, used by the
+//! evaluation. Squares are numbered from a1 = 0 to h8 = 63.
+
+use chess::piece::Color;
+
+pub const FILE_A: u64 = 0x0101_0101_0101_0101;
+pub const FILE_H: u64 = FILE_A << 7;
+pub const RANK_1: u64 = 0xFF;
+
+pub fn file_of(square: u8) -> u8 {
+    square % 8
+}
+
+pub fn rank_of(square: u8) -> u8 {
+    square / 8
+}
+
+/// The rank of `square` counted from the side of `color`, 0 being its back rank.
+pub fn relative_rank(square: u8, color: Color) -> u8 {
+    match color {
+        Color::White => rank_of(square),
+        Color::Black => 7 - rank_of(square),
+    }
+}
+
+pub fn file_mask(file: u8) -> u64 {
+    FILE_A << file
+}
+
+pub fn rank_mask(rank: u8) -> u64 {
+    RANK_1 << (8 * rank)
+}
+
+/// The files next to `file`, not including `file` itself.
+pub fn adjacent_files(file: u8) -> u64 {
+    let left = if file > 0 { file_mask(file - 1) } else { 0 };
+    let right = if file < 7 { file_mask(file + 1) } else { 0 };
+    left | right
+}
+
+/// Every square on the ranks in front of `square`, seen from `color`.
+pub fn forward_ranks(square: u8, color: Color) -> u64 {
+    let rank = rank_of(square);
+    match color {
+        Color::White if rank == 7 => 0,
+        Color::White => !0 << (8 * (rank + 1)),
+        Color::Black => !(!0 << (8 * rank)),
+    }
+}
+
+/// The squares in front of `square` on its own file.
+pub fn front_span(square: u8, color: Color) -> u64 {
+    forward_ranks(square, color) & file_mask(file_of(square))
+}
+
+/// The squares in front of `square` on its own and the adjacent files. A pawn is
+/// passed when no enemy pawn stands on them.
+pub fn passed_span(square: u8, color: Color) -> u64 {
+    let file = file_of(square);
+    forward_ranks(square, color) & (file_mask(file) | adjacent_files(file))
+}
+
+/// The squares in front of `square` on the adjacent files, which a pawn on
+/// `square` could attack as it advances.
+pub fn attack_span(square: u8, color: Color) -> u64 {
+    forward_ranks(square, color) & adjacent_files(file_of(square))
+}
+
+/// The square directly in front of `square`, seen from `color`.
+pub fn forward_square(square: u8, color: Color) -> Option<u8> {
+    match color {
+        Color::White if square < 56 => Some(square + 8),
+        Color::Black if square >= 8 => Some(square - 8),
+        _ => None,
+    }
+}
+
+/// Every square attacked by the pawns in `pawns` of `color`.
+pub fn pawn_attacks(pawns: u64, color: Color) -> u64 {
+    match color {
+        Color::White => ((pawns << 7) & !FILE_H) | ((pawns << 9) & !FILE_A),
+        Color::Black => ((pawns >> 9) & !FILE_H) | ((pawns >> 7) & !FILE_A),
+    }
+}
+
+/// The number of king moves between two squares.
+pub fn distance(a: u8, b: u8) -> u8 {
+    let files = file_of(a).abs_diff(file_of(b));
+    let ranks = rank_of(a).abs_diff(rank_of(b));
+    files.max(ranks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans() {
+        // e4 for White: e5-e8 in front, d5-d8 and f5-f8 beside
+        let e4 = 28;
+        assert_eq!(front_span(e4, Color::White).count_ones(), 4);
+        assert_eq!(passed_span(e4, Color::White).count_ones(), 12);
+        assert_eq!(attack_span(e4, Color::White).count_ones(), 8);
+        assert_eq!(front_span(e4, Color::Black).count_ones(), 3);
+        assert_eq!(passed_span(0, Color::White).count_ones(), 14);
+        assert_eq!(forward_ranks(63, Color::White), 0);
+        assert_eq!(forward_ranks(0, Color::Black), 0);
+        assert_eq!(forward_square(28, Color::Black), Some(20));
+    }
+
+    #[test]
+    fn test_pawn_attacks_do_not_wrap() {
+        let a2_h2 = (1 << 8) | (1 << 15);
+        assert_eq!(pawn_attacks(a2_h2, Color::White), (1 << 17) | (1 << 22));
+        let a7_h7 = (1 << 48) | (1 << 55);
+        assert_eq!(pawn_attacks(a7_h7, Color::Black), (1 << 41) | (1 << 46));
+        assert_eq!(distance(0, 63), 7);
+        assert_eq!(distance(12, 28), 2);
+    }
+}
@@ -0,0 +1,590 @@
+//! An efficiently updatable neural network evaluation (NNUE).
+//!
+//! The network has a HalfKP feature set: for each side, one input per combination of
+//! that side's king square and the square, type and color of every other piece but
+//! the kings, seen from that side so that both use the same weights. The inputs feed
+//! a hidden layer of int16 accumulators per side. They are concatenated, side to move
+//! first, clipped to `0..=QA` and multiplied with int8 output weights.
+//!
+//! A move changes only a handful of features, so during the search the accumulators
+//! are updated incrementally by `NnueState`, which the search keeps in step with the
+//! board. Only a move of a side's own king recomputes that side's accumulator.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use chess::{
+    board::Board,
+    piece::{Color, PieceType},
+};
+
+use crate::eval::{pocket_material, EvalState, Evaluator};
+
+/// The first bytes of every network file.
+pub const MAGIC: [u8; 4] = *b"RRNN";
+/// The network file version this build reads and writes.
+pub const VERSION: u32 = 1;
+/// The feature set id of HalfKP in the file header.
+pub const HALF_KP: u32 = 0;
+
+/// King squares times non-king pieces of both colors times squares.
+pub const FEATURES: usize = 64 * 10 * 64;
+
+/// The clipping ceiling of the hidden layer, which is the scale of its activations.
+pub const QA: i32 = 127;
+/// The scale of the output weights.
+pub const QB: i32 = 64;
+/// Converts the output of the network to centipawns.
+pub const OUTPUT_SCALE: i32 = 400;
+
+/// The weights of a network, quantized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    hidden: usize,
+    /// The bias of each hidden neuron.
+    feature_bias: Vec<i16>,
+    /// The weights of each feature to the hidden layer, `hidden` per feature.
+    feature_weights: Vec<i16>,
+    /// The weights of the side to move's hidden layer followed by the other side's.
+    output_weights: Vec<i8>,
+    output_bias: i32,
+}
+
+impl Network {
+    /// Reads a network from its file format: the magic bytes, the version, the feature
+    /// set and the hidden layer size as little endian `u32`s, followed by the feature
+    /// biases and weights as `i16`, the output weights as `i8` and the output bias as
+    /// `i32`.
+    pub fn read(reader: &mut impl Read) -> Result<Self, String> {
+        let mut magic = [0; 4];
+        read_exact(reader, &mut magic)?;
+        if magic != MAGIC {
+            return Err("Not a network file".to_string());
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(format!(
+                "Unsupported network version {}, expected {}",
+                version, VERSION
+            ));
+        }
+        let features = read_u32(reader)?;
+        if features != HALF_KP {
+            return Err(format!("Unsupported feature set {}", features));
+        }
+        let hidden = read_u32(reader)? as usize;
+        if hidden == 0 || hidden > 4096 {
+            return Err(format!("Invalid hidden layer size {}", hidden));
+        }
+
+        let feature_bias = read_i16s(reader, hidden)?;
+        let feature_weights = read_i16s(reader, FEATURES * hidden)?;
+        let mut output_weights = vec![0; 2 * hidden];
+        let mut bytes = vec![0; 2 * hidden];
+        read_exact(reader, &mut bytes)?;
+        for (weight, byte) in output_weights.iter_mut().zip(bytes) {
+            *weight = byte as i8;
+        }
+        let output_bias = read_u32(reader)? as i32;
+        Ok(Network {
+            hidden,
+            feature_bias,
+            feature_weights,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> Result<(), String> {
+        let mut bytes = Vec::with_capacity(16 + 2 * self.feature_weights.len());
+        bytes.extend_from_slice(&MAGIC);
+        for value in [VERSION, HALF_KP, self.hidden as u32] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in self.feature_bias.iter().chain(&self.feature_weights) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend(self.output_weights.iter().map(|&weight| weight as u8));
+        bytes.extend_from_slice(&self.output_bias.to_le_bytes());
+        writer.write_all(&bytes).map_err(|err| err.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path)
+            .map_err(|err| format!("Could not open {}: {}", path.display(), err))?;
+        Self::read(&mut BufReader::new(file))
+            .map_err(|err| format!("Could not load {}: {}", path.display(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path)
+            .map_err(|err| format!("Could not create {}: {}", path.display(), err))?;
+        let mut writer = BufWriter::new(file);
+        self.write(&mut writer)?;
+        writer.flush().map_err(|err| err.to_string())
+    }
+
+    pub fn hidden(&self) -> usize {
+        self.hidden
+    }
+
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    /// Computes the accumulator of `perspective` from scratch.
+    fn refresh(&self, board: &Board, perspective: Color, accumulator: &mut [i16]) {
+        accumulator.copy_from_slice(&self.feature_bias);
+        let king = king_square(&pieces(board), perspective);
+        for color in [Color::White, Color::Black] {
+            for piece in 0..5 {
+                for square in board.bitboards[color as usize][piece] {
+                    let feature = feature(perspective, king, color, piece, square);
+                    simd::add(accumulator, self.weights(feature));
+                }
+            }
+        }
+    }
+
+    /// The score of the position in centipawns for the side to move.
+    fn output(&self, to_move: &[i16], other: &[i16]) -> i32 {
+        let (to_move_weights, other_weights) = self.output_weights.split_at(self.hidden);
+        let sum = simd::clipped_dot(to_move, to_move_weights)
+            + simd::clipped_dot(other, other_weights)
+            + self.output_bias;
+        // The scaled sum of a large hidden layer does not fit in 32 bits
+        (sum as i64 * OUTPUT_SCALE as i64 / (QA * QB) as i64) as i32
+    }
+
+    /// Evaluates `board` with freshly computed accumulators.
+    fn evaluate_from_scratch(&self, board: &Board) -> i32 {
+        let mut white = vec![0; self.hidden];
+        let mut black = vec![0; self.hidden];
+        self.refresh(board, Color::White, &mut white);
+        self.refresh(board, Color::Black, &mut black);
+        match board.side_to_move {
+            Color::White => self.output(&white, &black),
+            Color::Black => self.output(&black, &white),
+        }
+    }
+
+    /// A network with small pseudo-random weights, for tests.
+    #[cfg(test)]
+    pub(crate) fn random(hidden: usize, seed: u64) -> Self {
+        let mut state = seed | 1;
+        let mut next = move |range: i64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % (2 * range as u64 + 1)) as i64 - range
+        };
+        Network {
+            hidden,
+            feature_bias: (0..hidden).map(|_| next(32) as i16).collect(),
+            feature_weights: (0..FEATURES * hidden).map(|_| next(16) as i16).collect(),
+            output_weights: (0..2 * hidden).map(|_| next(64) as i8).collect(),
+            output_bias: next(1000) as i32,
+        }
+    }
+}
+
+fn read_exact(reader: &mut impl Read, buffer: &mut [u8]) -> Result<(), String> {
+    reader
+        .read_exact(buffer)
+        .map_err(|_| "Truncated network file".to_string())
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, String> {
+    let mut bytes = [0; 4];
+    read_exact(reader, &mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_i16s(reader: &mut impl Read, count: usize) -> Result<Vec<i16>, String> {
+    let mut bytes = vec![0; 2 * count];
+    read_exact(reader, &mut bytes)?;
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+        .collect())
+}
+
+/// The piece bitboards of a board, indexed by `Color` and `PieceType`.
+type Pieces = [[u64; 6]; 2];
+
+fn pieces(board: &Board) -> Pieces {
+    [Color::White, Color::Black].map(|color| {
+        let bitboards = &board.bitboards[color as usize];
+        std::array::from_fn(|piece| bitboards[piece].0)
+    })
+}
+
+fn king_square(pieces: &Pieces, color: Color) -> u8 {
+    pieces[color as usize][PieceType::King as usize].trailing_zeros() as u8
+}
+
+/// The input index of a non-king `piece` of `color` on `square`, seen by
+/// `perspective` whose king stands on `king`. Black sees the board flipped.
+fn feature(perspective: Color, king: u8, color: Color, piece: usize, square: u8) -> usize {
+    let orient = |square: u8| match perspective {
+        Color::White => square as usize,
+        Color::Black => (square ^ 56) as usize,
+    };
+    let relative_color = (color != perspective) as usize;
+    (orient(king) * 10 + piece * 2 + relative_color) * 64 + orient(square)
+}
+
+/// The accumulators of both sides after one move, with the pieces they were computed
+/// for.
+#[derive(Clone)]
+struct Entry {
+    /// Indexed by `Color`.
+    accumulators: [Vec<i16>; 2],
+    pieces: Pieces,
+}
+
+/// A stack of accumulators that follows the moves made and unmade during a search.
+pub struct NnueState {
+    network: Arc<Network>,
+    entries: Vec<Entry>,
+    /// The number of entries in use, the rest are kept to avoid allocations.
+    len: usize,
+}
+
+impl NnueState {
+    pub fn new(network: Arc<Network>) -> Self {
+        NnueState {
+            network,
+            entries: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Makes room for one more entry and returns the previous and the new one.
+    fn push(&mut self) -> (Option<&Entry>, &mut Entry) {
+        if self.len == self.entries.len() {
+            let hidden = self.network.hidden;
+            self.entries.push(Entry {
+                accumulators: [vec![0; hidden], vec![0; hidden]],
+                pieces: [[0; 6]; 2],
+            });
+        }
+        self.len += 1;
+        let (previous, current) = self.entries[..self.len].split_at_mut(self.len - 1);
+        (previous.last(), &mut current[0])
+    }
+
+    fn refresh(&mut self, board: &Board) {
+        self.len = 0;
+        let network = Arc::clone(&self.network);
+        let (_, entry) = self.push();
+        for color in [Color::White, Color::Black] {
+            network.refresh(board, color, &mut entry.accumulators[color as usize]);
+        }
+        entry.pieces = pieces(board);
+    }
+}
+
+impl EvalState for NnueState {
+    fn make_move(&mut self, board: &Board) {
+        if self.len == 0 {
+            return self.refresh(board);
+        }
+        let network = Arc::clone(&self.network);
+        let (previous, entry) = self.push();
+        let previous = previous.expect("The stack is not empty");
+        let after = pieces(board);
+
+        for perspective in [Color::White, Color::Black] {
+            let accumulator = &mut entry.accumulators[perspective as usize];
+            let king = king_square(&after, perspective);
+            if king != king_square(&previous.pieces, perspective) {
+                network.refresh(board, perspective, accumulator);
+                continue;
+            }
+            accumulator.copy_from_slice(&previous.accumulators[perspective as usize]);
+            for color in [Color::White, Color::Black] {
+                let pieces = previous.pieces[color as usize]
+                    .iter()
+                    .zip(&after[color as usize]);
+                for (piece, (&before, &now)) in pieces.enumerate().take(5) {
+                    for (changed, update) in [
+                        (before & !now, simd::sub as fn(&mut [i16], &[i16])),
+                        (now & !before, simd::add),
+                    ] {
+                        let mut squares = changed;
+                        while squares != 0 {
+                            let square = squares.trailing_zeros() as u8;
+                            squares &= squares - 1;
+                            let feature = feature(perspective, king, color, piece, square);
+                            update(accumulator, network.weights(feature));
+                        }
+                    }
+                }
+            }
+        }
+        entry.pieces = after;
+    }
+
+    fn unmake(&mut self, _board: &Board) {
+        self.len = self.len.saturating_sub(1);
+    }
+
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        if self.len == 0 || self.entries[self.len - 1].pieces != pieces(board) {
+            self.refresh(board);
+        }
+        let [white, black] = &self.entries[self.len - 1].accumulators;
+        let score = match board.side_to_move {
+            Color::White => self.network.output(white, black),
+            Color::Black => self.network.output(black, white),
+        };
+        score + pocket_material(board)
+    }
+}
+
+/// Evaluates positions with a network. On its own every evaluation computes the
+/// accumulators from scratch; the search uses the incremental `NnueState` instead.
+pub struct NnueEvaluator {
+    network: Arc<Network>,
+}
+
+impl NnueEvaluator {
+    pub fn new(network: Arc<Network>) -> Self {
+        NnueEvaluator { network }
+    }
+}
+
+impl Evaluator for NnueEvaluator {
+    fn evaluate(&self, board: &Board) -> i32 {
+        self.network.evaluate_from_scratch(board) + pocket_material(board)
+    }
+
+    fn new_state(&self) -> Option<Box<dyn EvalState>> {
+        Some(Box::new(NnueState::new(Arc::clone(&self.network))))
+    }
+}
+
+/// The vector operations of the network, with AVX2 versions used when the CPU
+/// supports them.
+mod simd {
+    use super::QA;
+
+    pub fn add(accumulator: &mut [i16], weights: &[i16]) {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // Safety: the CPU supports AVX2
+            return unsafe { avx2::add(accumulator, weights) };
+        }
+        scalar::add(accumulator, weights)
+    }
+
+    pub fn sub(accumulator: &mut [i16], weights: &[i16]) {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // Safety: the CPU supports AVX2
+            return unsafe { avx2::sub(accumulator, weights) };
+        }
+        scalar::sub(accumulator, weights)
+    }
+
+    /// The dot product of the accumulator, clipped to `0..=QA`, with the weights.
+    pub fn clipped_dot(accumulator: &[i16], weights: &[i8]) -> i32 {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // Safety: the CPU supports AVX2
+            return unsafe { avx2::clipped_dot(accumulator, weights) };
+        }
+        scalar::clipped_dot(accumulator, weights)
+    }
+
+    pub mod scalar {
+        use super::QA;
+
+        pub fn add(accumulator: &mut [i16], weights: &[i16]) {
+            for (value, &weight) in accumulator.iter_mut().zip(weights) {
+                *value = value.wrapping_add(weight);
+            }
+        }
+
+        pub fn sub(accumulator: &mut [i16], weights: &[i16]) {
+            for (value, &weight) in accumulator.iter_mut().zip(weights) {
+                *value = value.wrapping_sub(weight);
+            }
+        }
+
+        pub fn clipped_dot(accumulator: &[i16], weights: &[i8]) -> i32 {
+            accumulator
+                .iter()
+                .zip(weights)
+                .map(|(&value, &weight)| (value as i32).clamp(0, QA) * weight as i32)
+                .sum()
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    mod avx2 {
+        use std::arch::x86_64::*;
+
+        use super::{scalar, QA};
+
+        const LANES: usize = 16;
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn add(accumulator: &mut [i16], weights: &[i16]) {
+            let chunks = accumulator.len().min(weights.len()) / LANES * LANES;
+            for i in (0..chunks).step_by(LANES) {
+                let a = _mm256_loadu_si256(accumulator.as_ptr().add(i) as *const __m256i);
+                let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+                let sum = _mm256_add_epi16(a, w);
+                _mm256_storeu_si256(accumulator.as_mut_ptr().add(i) as *mut __m256i, sum);
+            }
+            scalar::add(&mut accumulator[chunks..], &weights[chunks..]);
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn sub(accumulator: &mut [i16], weights: &[i16]) {
+            let chunks = accumulator.len().min(weights.len()) / LANES * LANES;
+            for i in (0..chunks).step_by(LANES) {
+                let a = _mm256_loadu_si256(accumulator.as_ptr().add(i) as *const __m256i);
+                let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+                let difference = _mm256_sub_epi16(a, w);
+                _mm256_storeu_si256(accumulator.as_mut_ptr().add(i) as *mut __m256i, difference);
+            }
+            scalar::sub(&mut accumulator[chunks..], &weights[chunks..]);
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn clipped_dot(accumulator: &[i16], weights: &[i8]) -> i32 {
+            let chunks = accumulator.len().min(weights.len()) / LANES * LANES;
+            let zero = _mm256_setzero_si256();
+            let ceiling = _mm256_set1_epi16(QA as i16);
+            let mut sum = _mm256_setzero_si256();
+            for i in (0..chunks).step_by(LANES) {
+                let a = _mm256_loadu_si256(accumulator.as_ptr().add(i) as *const __m256i);
+                let clipped = _mm256_min_epi16(_mm256_max_epi16(a, zero), ceiling);
+                let w = _mm_loadu_si128(weights.as_ptr().add(i) as *const __m128i);
+                let products = _mm256_madd_epi16(clipped, _mm256_cvtepi8_epi16(w));
+                sum = _mm256_add_epi32(sum, products);
+            }
+            let mut lanes = [0i32; 8];
+            _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+            lanes.iter().sum::<i32>()
+                + scalar::clipped_dot(&accumulator[chunks..], &weights[chunks..])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::Search;
+    use chess::{board::STARTING_FEN, chess_move::ChessMove};
+
+    fn make(board: &mut Board, state: &mut NnueState, moves: &[&str]) {
+        for notation in moves {
+            let mv = ChessMove::from_standard_notation(notation, board).unwrap();
+            board.make_move(mv);
+            state.make_move(board);
+            assert_eq!(
+                state.evaluate(board),
+                state.network.evaluate_from_scratch(board),
+                "after {}",
+                notation
+            );
+        }
+    }
+
+    #[test]
+    fn test_incremental_updates_match_refresh() {
+        let network = Arc::new(Network::random(32, 7));
+        let mut state = NnueState::new(Arc::clone(&network));
+        let fen = "r3k2r/pPp2ppp/8/3pP3/8/8/PPP2PPP/R3K2R w KQkq d6 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        state.evaluate(&board);
+
+        // En passant, castling on both sides, a promotion with capture and king moves
+        make(
+            &mut board,
+            &mut state,
+            &[
+                "e5d6", "e8c8", "e1g1", "c7d6", "b7a8q", "c8d7", "a8a7", "d7e6",
+            ],
+        );
+        for _ in 0..4 {
+            board.unmake();
+            state.unmake(&board);
+        }
+        assert_eq!(
+            state.evaluate(&board),
+            network.evaluate_from_scratch(&board)
+        );
+    }
+
+    #[test]
+    fn test_simd_matches_scalar() {
+        let network = Network::random(40, 3);
+        let mut vectorized: Vec<i16> = network.feature_bias.clone();
+        let mut scalar = vectorized.clone();
+        for feature in [0, 99, FEATURES - 1] {
+            simd::add(&mut vectorized, network.weights(feature));
+            simd::scalar::add(&mut scalar, network.weights(feature));
+        }
+        simd::sub(&mut vectorized, network.weights(5));
+        simd::scalar::sub(&mut scalar, network.weights(5));
+        assert_eq!(vectorized, scalar);
+        let weights = &network.output_weights[..40];
+        assert_eq!(
+            simd::clipped_dot(&vectorized, weights),
+            simd::scalar::clipped_dot(&scalar, weights)
+        );
+    }
+
+    #[test]
+    fn test_largest_output_does_not_overflow() {
+        let hidden = 4096;
+        let network = Network {
+            hidden,
+            feature_bias: vec![0; hidden],
+            feature_weights: Vec::new(),
+            output_weights: vec![i8::MAX; 2 * hidden],
+            output_bias: 0,
+        };
+        let saturated = vec![QA as i16; hidden];
+        let sum = 2 * hidden as i64 * QA as i64 * i8::MAX as i64;
+        assert_eq!(
+            network.output(&saturated, &saturated) as i64,
+            sum * OUTPUT_SCALE as i64 / (QA * QB) as i64
+        );
+    }
+
+    #[test]
+    fn test_network_file_round_trip() {
+        let network = Network::random(16, 11);
+        let mut bytes = Vec::new();
+        network.write(&mut bytes).unwrap();
+        assert_eq!(Network::read(&mut bytes.as_slice()), Ok(network));
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 2;
+        assert!(Network::read(&mut wrong_version.as_slice())
+            .unwrap_err()
+            .contains("version"));
+        assert!(Network::read(&mut &bytes[..100]).is_err());
+        assert!(Network::read(&mut &b"NNUE"[..]).is_err());
+    }
+
+    #[test]
+    fn test_search_with_network() {
+        let network = Arc::new(Network::random(16, 5));
+        let mut search = Search::default();
+        search.set_evaluator(Arc::new(NnueEvaluator::new(network)));
+        let mut board = Board::from_fen(STARTING_FEN).unwrap();
+        assert!(search.search(&mut board, 3).best_move().is_some());
+        assert_eq!(board.to_fen(), STARTING_FEN);
+    }
+}
@@ -0,0 +1,239 @@
+//! The weights of the classical evaluation, collected in one struct so that tuning
+//! experiments don't need a rebuild. The compiled-in constants of each evaluation
+//! module are the defaults, and any weight left out of a parameter file keeps its
+//! default value.
+
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    eval::{PieceSquareTables, TaperedScore, PIECE_VALUES, TEMPO},
+    king_safety::KingSafetyParams,
+    pawns::PawnParams,
+    pieces::PieceParams,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvalParams {
+    /// Indexed by `PieceType`.
+    pub piece_values: [TaperedScore; 6],
+    pub tempo: TaperedScore,
+    pub piece_squares: PieceSquareTables,
+    pub pawns: PawnParams,
+    pub king_safety: KingSafetyParams,
+    pub pieces: PieceParams,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        EvalParams {
+            piece_values: PIECE_VALUES,
+            tempo: TEMPO,
+            piece_squares: PieceSquareTables::default(),
+            pawns: PawnParams::default(),
+            king_safety: KingSafetyParams::default(),
+            pieces: PieceParams::default(),
+        }
+    }
+}
+
+impl EvalParams {
+    /// Loads parameters from a `.toml` or `.json` file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        match Format::of(path)? {
+            Format::Toml => Self::from_toml(&text),
+            Format::Json => Self::from_json(&text),
+        }
+    }
+
+    /// Saves the parameters to a `.toml` or `.json` file.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = match Format::of(path)? {
+            Format::Toml => self.to_toml()?,
+            Format::Json => self.to_json()?,
+        };
+        fs::write(path, text).map_err(|err| format!("Could not write {}: {}", path.display(), err))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|err| err.to_string())
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|err| err.to_string())
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text).map_err(|err| err.to_string())
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|err| err.to_string())
+    }
+
+    /// Returns a single weight by its dotted path, as in `pawns.doubled.mg` or
+    /// `piece_squares.knight.27`.
+    pub fn get(&self, name: &str) -> Option<i32> {
+        let value = serde_json::to_value(self).ok()?;
+        let weight = value.pointer(&pointer(name))?.as_i64()?;
+        Some(weight as i32)
+    }
+
+    /// Sets a single weight by its dotted path, see `get`.
+    pub fn set(&mut self, name: &str, weight: i32) -> Result<(), String> {
+        let mut value = serde_json::to_value(&*self).map_err(|err| err.to_string())?;
+        match value.pointer_mut(&pointer(name)) {
+            Some(slot) if slot.is_i64() => *slot = Value::from(weight),
+            _ => return Err(format!("Unknown evaluation weight: {}", name)),
+        }
+        *self = serde_json::from_value(value).map_err(|err| err.to_string())?;
+        Ok(())
+    }
+
+    /// The dotted paths of every single weight, in the order of the parameter file.
+    pub fn weight_names(&self) -> Vec<String> {
+        fn collect(value: &Value, path: String, names: &mut Vec<String>) {
+            let child = |name: &dyn std::fmt::Display| {
+                if path.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{}.{}", path, name)
+                }
+            };
+            match value {
+                Value::Object(fields) => {
+                    for (name, field) in fields {
+                        collect(field, child(name), names);
+                    }
+                }
+                Value::Array(values) => {
+                    for (i, value) in values.iter().enumerate() {
+                        collect(value, child(&i), names);
+                    }
+                }
+                _ => names.push(path),
+            }
+        }
+
+        let mut names = Vec::new();
+        let value = serde_json::to_value(self).expect("Parameters are always serializable");
+        collect(&value, String::new(), &mut names);
+        names
+    }
+}
+
+enum Format {
+    Toml,
+    Json,
+}
+
+impl Format {
+    fn of(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Ok(Format::Toml),
+            Some("json") => Ok(Format::Json),
+            _ => Err(format!(
+                "Unknown parameter file format, expected .toml or .json: {}",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// Turns a dotted path into a JSON pointer.
+fn pointer(name: &str) -> String {
+    name.split('.')
+        .fold(String::new(), |pointer, part| pointer + "/" + part)
+}
+
+/// Serializes arrays longer than serde supports, such as the piece-square tables, as
+/// sequences.
+pub(crate) mod long_array {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        array: &[i32; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        array.as_slice().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[i32; N], D::Error> {
+        let values = Vec::<i32>::deserialize(deserializer)?;
+        let length = values.len();
+        values
+            .try_into()
+            .map_err(|_| D::Error::invalid_length(length, &format!("{} values", N).as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut params = EvalParams::default();
+        params.pawns.doubled = TaperedScore::new(-13, -31);
+        params.piece_squares.knight[27] = 42;
+
+        assert_eq!(
+            EvalParams::from_toml(&params.to_toml().unwrap()),
+            Ok(params.clone())
+        );
+        assert_eq!(
+            EvalParams::from_json(&params.to_json().unwrap()),
+            Ok(params.clone())
+        );
+
+        let path = std::env::temp_dir().join(format!("eval-params-{}.toml", std::process::id()));
+        params.save(&path).unwrap();
+        assert_eq!(EvalParams::load(&path), Ok(params));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_weights_keep_their_defaults() {
+        let params = EvalParams::from_toml("tempo = { mg = 25, eg = 5 }").unwrap();
+        assert_eq!(params.tempo, TaperedScore::new(25, 5));
+        assert_eq!(params.pawns, PawnParams::default());
+
+        let params =
+            EvalParams::from_json(r#"{"pawns": {"doubled": {"mg": -1, "eg": -2}}}"#).unwrap();
+        assert_eq!(params.pawns.doubled, TaperedScore::new(-1, -2));
+        assert_eq!(params.pawns.isolated, PawnParams::default().isolated);
+        assert!(EvalParams::from_json(r#"{"piece_squares": {"pawn": [1, 2]}}"#).is_err());
+    }
+
+    #[test]
+    fn test_set_single_weights() {
+        let mut params = EvalParams::default();
+        params.set("pawns.isolated.eg", -40).unwrap();
+        params.set("piece_values.1.mg", 333).unwrap();
+        params.set("king_safety.danger.10", 99).unwrap();
+        assert_eq!(params.pawns.isolated.eg, -40);
+        assert_eq!(params.piece_values[1].mg, 333);
+        assert_eq!(params.get("king_safety.danger.10"), Some(99));
+        assert!(params.set("pawns.isolated", 1).is_err());
+        assert!(params.set("pawns.unknown", 1).is_err());
+    }
+
+    #[test]
+    fn test_weight_names() {
+        let params = EvalParams::default();
+        let names = params.weight_names();
+        assert_eq!(names[0], "piece_values.0.mg");
+        assert!(names.contains(&"piece_squares.king_endgame.63".to_string()));
+        assert!(names.contains(&"pieces.threat_by_rook.4.eg".to_string()));
+        for name in &names {
+            assert!(params.get(name).is_some(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Pawn structure evaluation. Everything that only depends on the pawns is cached in
+//! a `PawnTable` keyed by `Board::pawn_hash`, since the pawn structure changes far less
+//! often than the rest of the position. The passed pawn terms that depend on the kings
+//! and the other pieces are added on top for every evaluation.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chess::{
+    board::Board,
+    piece::{Color, PieceType},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    eval::TaperedScore,
+    masks::{
+        adjacent_files, distance, forward_ranks, forward_square, front_span, passed_span,
+        pawn_attacks, rank_mask, rank_of, relative_rank,
+    },
+};
+
+const DOUBLED: TaperedScore = TaperedScore::new(-10, -25);
+const ISOLATED: TaperedScore = TaperedScore::new(-10, -15);
+/// A pawn that can no longer be defended by its neighbours and cannot advance safely.
+const BACKWARD: TaperedScore = TaperedScore::new(-8, -12);
+
+/// Bonus for a pawn defended by a pawn or standing next to one, by relative rank.
+const CONNECTED: [TaperedScore; 8] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(4, 2),
+    TaperedScore::new(6, 4),
+    TaperedScore::new(10, 8),
+    TaperedScore::new(18, 16),
+    TaperedScore::new(30, 30),
+    TaperedScore::new(50, 50),
+    TaperedScore::new(0, 0),
+];
+
+/// Bonus for a passed pawn by relative rank.
+const PASSED: [TaperedScore; 8] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(2, 8),
+    TaperedScore::new(5, 12),
+    TaperedScore::new(10, 20),
+    TaperedScore::new(25, 40),
+    TaperedScore::new(45, 75),
+    TaperedScore::new(70, 110),
+    TaperedScore::new(0, 0),
+];
+
+/// Per step of distance between the square in front of a passed pawn and the
+/// enemy king, and the own king. Multiplied by how far the pawn has advanced.
+const PASSED_ENEMY_KING_DISTANCE: i32 = 5;
+const PASSED_OWN_KING_DISTANCE: i32 = 2;
+/// A passed pawn with a piece standing in front of it.
+const PASSED_BLOCKED: TaperedScore = TaperedScore::new(-4, -10);
+/// A passed pawn with no piece anywhere on its way to promotion.
+const PASSED_FREE_PATH: TaperedScore = TaperedScore::new(0, 8);
+
+/// The weights of the pawn terms, the constants above by default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PawnParams {
+    pub doubled: TaperedScore,
+    pub isolated: TaperedScore,
+    pub backward: TaperedScore,
+    pub connected: [TaperedScore; 8],
+    pub passed: [TaperedScore; 8],
+    pub passed_enemy_king_distance: i32,
+    pub passed_own_king_distance: i32,
+    pub passed_blocked: TaperedScore,
+    pub passed_free_path: TaperedScore,
+}
+
+impl Default for PawnParams {
+    fn default() -> Self {
+        PawnParams {
+            doubled: DOUBLED,
+            isolated: ISOLATED,
+            backward: BACKWARD,
+            connected: CONNECTED,
+            passed: PASSED,
+            passed_enemy_king_distance: PASSED_ENEMY_KING_DISTANCE,
+            passed_own_king_distance: PASSED_OWN_KING_DISTANCE,
+            passed_blocked: PASSED_BLOCKED,
+            passed_free_path: PASSED_FREE_PATH,
+        }
+    }
+}
+
+/// The cached evaluation of one pawn structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PawnEntry {
+    /// The pawn structure score of each color, indexed by `Color`.
+    pub scores: [TaperedScore; 2],
+    /// The passed pawns of both colors.
+    pub passed: u64,
+}
+
+impl PawnEntry {
+    /// Packs the four scores into one word. Pawn structure scores are far inside the
+    /// range of an `i16`.
+    fn pack_scores(&self) -> u64 {
+        let [white, black] = self.scores;
+        [white.mg, white.eg, black.mg, black.eg]
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &value)| {
+                bits | (value as i16 as u16 as u64) << (16 * i)
+            })
+    }
+
+    fn unpack_scores(bits: u64) -> [TaperedScore; 2] {
+        let value = |i: u64| (bits >> (16 * i)) as u16 as i16 as i32;
+        [
+            TaperedScore::new(value(0), value(1)),
+            TaperedScore::new(value(2), value(3)),
+        ]
+    }
+}
+
+/// One slot of the table, checked like the slots of the transposition table: the
+/// key is stored xored with the data so torn writes from other threads are detected.
+#[derive(Default)]
+struct Slot {
+    check: AtomicU64,
+    scores: AtomicU64,
+    passed: AtomicU64,
+}
+
+/// A fixed size cache of pawn structure evaluations, shared between search threads.
+/// The entries depend on the `PawnParams` they were computed with, so a table must
+/// not be shared between evaluators with different parameters.
+pub struct PawnTable {
+    slots: Vec<Slot>,
+}
+
+impl PawnTable {
+    /// Creates a table with `2^bits` slots.
+    pub fn new(bits: u32) -> Self {
+        PawnTable {
+            slots: (0..1usize << bits).map(|_| Slot::default()).collect(),
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[(key as usize) & (self.slots.len() - 1)]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<PawnEntry> {
+        let slot = self.slot(key);
+        let scores = slot.scores.load(Ordering::Relaxed);
+        let passed = slot.passed.load(Ordering::Relaxed);
+        if slot.check.load(Ordering::Relaxed) ^ scores ^ passed != key {
+            return None;
+        }
+        Some(PawnEntry {
+            scores: PawnEntry::unpack_scores(scores),
+            passed,
+        })
+    }
+
+    pub fn store(&self, key: u64, entry: &PawnEntry) {
+        let slot = self.slot(key);
+        let scores = entry.pack_scores();
+        slot.check
+            .store(key ^ scores ^ entry.passed, Ordering::Relaxed);
+        slot.scores.store(scores, Ordering::Relaxed);
+        slot.passed.store(entry.passed, Ordering::Relaxed);
+    }
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        Self::new(14)
+    }
+}
+
+/// Evaluates the pawn structure of both colors, indexed by `Color`.
+pub fn evaluate_pawns(board: &Board, table: &PawnTable, params: &PawnParams) -> [TaperedScore; 2] {
+    let key = board.pawn_hash();
+    let entry = table.probe(key).unwrap_or_else(|| {
+        let entry = pawn_structure(board, params);
+        table.store(key, &entry);
+        entry
+    });
+
+    let mut scores = entry.scores;
+    for color in [Color::White, Color::Black] {
+        scores[color as usize] += passed_pawns(board, color, entry.passed, params);
+    }
+    scores
+}
+
+/// Evaluates everything about the pawns that only depends on the pawns.
+pub fn pawn_structure(board: &Board, params: &PawnParams) -> PawnEntry {
+    let mut entry = PawnEntry {
+        scores: [TaperedScore::default(); 2],
+        passed: 0,
+    };
+    for color in [Color::White, Color::Black] {
+        let own = board.bitboards[color as usize][PieceType::Pawn as usize].0;
+        let enemy = board.bitboards[color.opposite() as usize][PieceType::Pawn as usize].0;
+        let enemy_attacks = pawn_attacks(enemy, color.opposite());
+        let score = &mut entry.scores[color as usize];
+
+        for square in board.bitboards[color as usize][PieceType::Pawn as usize] {
+            let bit = 1u64 << square;
+            let file = square % 8;
+            let rank = relative_rank(square, color);
+            let neighbours = own & adjacent_files(file);
+
+            let doubled = front_span(square, color) & own != 0;
+            let isolated = neighbours == 0;
+            // Pawns of `color` that defend this square
+            let supporters = pawn_attacks(bit, color.opposite()) & own;
+            let phalanx = neighbours & rank_mask(rank_of(square));
+            // No neighbour level with or behind the pawn can come to its defence
+            let backward = !isolated
+                && neighbours & !forward_ranks(square, color) == 0
+                && forward_square(square, color)
+                    .is_some_and(|stop| enemy_attacks & (1 << stop) != 0);
+
+            if doubled {
+                *score += params.doubled;
+            }
+            if isolated {
+                *score += params.isolated;
+            } else if backward {
+                *score += params.backward;
+            }
+            if supporters != 0 || phalanx != 0 {
+                *score += params.connected[rank as usize];
+            }
+            if !doubled && passed_span(square, color) & enemy == 0 {
+                *score += params.passed[rank as usize];
+                entry.passed |= bit;
+            }
+        }
+    }
+    entry
+}
+
+/// The passed pawn terms that depend on the pieces: how close the kings are to the
+/// square in front of the pawn, whether it is blocked and whether its path is free.
+fn passed_pawns(board: &Board, color: Color, passed: u64, params: &PawnParams) -> TaperedScore {
+    let own_pawns = board.bitboards[color as usize][PieceType::Pawn as usize].0;
+    let own_king = board.bitboards[color as usize][PieceType::King as usize].to_square();
+    let enemy_king =
+        board.bitboards[color.opposite() as usize][PieceType::King as usize].to_square();
+
+    let mut score = TaperedScore::default();
+    let mut pawns = passed & own_pawns;
+    while pawns != 0 {
+        let square = pawns.trailing_zeros() as u8;
+        pawns &= pawns - 1;
+        let weight = (relative_rank(square, color) as i32 - 2).max(0);
+        let Some(stop) = forward_square(square, color) else {
+            continue;
+        };
+
+        let enemy_distance = distance(enemy_king, stop).min(5) as i32;
+        let own_distance = distance(own_king, stop).min(5) as i32;
+        score.eg += (enemy_distance * params.passed_enemy_king_distance
+            - own_distance * params.passed_own_king_distance)
+            * weight;
+
+        if board.combined.0 & (1 << stop) != 0 {
+            score += params.passed_blocked * weight;
+        } else if board.combined.0 & front_span(square, color) == 0 {
+            score += params.passed_free_path * weight;
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn structure(fen: &str) -> PawnEntry {
+        pawn_structure(&Board::from_fen(fen).unwrap(), &PawnParams::default())
+    }
+
+    #[test]
+    fn test_pawn_terms() {
+        // Doubled and isolated c pawns against a healthy chain
+        let entry = structure("4k3/5ppp/8/8/8/2P5/2P5/4K3 w - - 0 1");
+        let [white, black] = entry.scores;
+        assert!(white.mg < black.mg);
+
+        // d6 is passed and defended by e5, the black pawn on a7 is passed too
+        let entry = structure("4k3/p7/3P4/4P3/8/8/8/4K3 w - - 0 1");
+        assert_eq!(entry.passed, (1 << 43) | (1 << 36) | (1 << 48));
+
+        // The d3 pawn cannot be defended and d4 is covered by the pawn on e5
+        let backward = structure("4k3/8/8/4p3/2P5/3P4/8/4K3 w - - 0 1");
+        let defended = structure("4k3/8/8/4p3/2PP4/8/8/4K3 w - - 0 1");
+        assert!(backward.scores[0].mg < defended.scores[0].mg);
+    }
+
+    #[test]
+    fn test_passed_pawn_depends_on_kings_and_blockers() {
+        let eval = |fen: &str| {
+            let board = Board::from_fen(fen).unwrap();
+            evaluate_pawns(&board, &PawnTable::new(4), &PawnParams::default())
+                [Color::White as usize]
+        };
+        let free = eval("8/8/1P6/8/8/8/8/K6k w - - 0 1");
+        let blocked = eval("8/1n6/1P6/8/8/8/8/K6k w - - 0 1");
+        let escorted = eval("8/8/1PK5/8/8/8/8/7k w - - 0 1");
+        assert!(free.eg > blocked.eg);
+        assert!(escorted.eg > free.eg);
+    }
+
+    #[test]
+    fn test_table_round_trip() {
+        let table = PawnTable::new(4);
+        let entry = PawnEntry {
+            scores: [TaperedScore::new(-35, 120), TaperedScore::new(7, -250)],
+            passed: 0x0000_1000_0000_0800,
+        };
+        table.store(0xABCD, &entry);
+        assert_eq!(table.probe(0xABCD), Some(entry));
+        assert_eq!(table.probe(0xABCD + 16), None);
+    }
+}
@@ -0,0 +1,371 @@
+//! Piece activity: mobility, outposts, the bishop pair, rook files, trapped pieces
+//! and threats. All terms are computed from the attack maps in `Attacks`.
+
+use chess::{
+    board::Board,
+    piece::{Color, PieceType},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    attacks::Attacks,
+    eval::TaperedScore,
+    masks::{attack_span, file_mask, file_of, rank_mask, relative_rank},
+};
+
+/// Bonus by the number of safe squares a piece attacks. Squares occupied by own
+/// pawns or the own king and squares attacked by enemy pawns are not safe.
+#[rustfmt::skip]
+const KNIGHT_MOBILITY: [TaperedScore; 9] = [
+    TaperedScore::new(-30, -40), TaperedScore::new(-15, -20), TaperedScore::new(-5, -8),
+    TaperedScore::new(0, 0), TaperedScore::new(5, 6), TaperedScore::new(10, 12),
+    TaperedScore::new(15, 16), TaperedScore::new(18, 18), TaperedScore::new(20, 20),
+];
+#[rustfmt::skip]
+const BISHOP_MOBILITY: [TaperedScore; 14] = [
+    TaperedScore::new(-25, -35), TaperedScore::new(-12, -18), TaperedScore::new(-2, -6),
+    TaperedScore::new(4, 2), TaperedScore::new(10, 8), TaperedScore::new(15, 14),
+    TaperedScore::new(20, 20), TaperedScore::new(24, 24), TaperedScore::new(27, 28),
+    TaperedScore::new(30, 31), TaperedScore::new(32, 34), TaperedScore::new(34, 36),
+    TaperedScore::new(36, 38), TaperedScore::new(38, 40),
+];
+#[rustfmt::skip]
+const ROOK_MOBILITY: [TaperedScore; 15] = [
+    TaperedScore::new(-20, -35), TaperedScore::new(-10, -15), TaperedScore::new(-4, -5),
+    TaperedScore::new(0, 2), TaperedScore::new(2, 8), TaperedScore::new(4, 14),
+    TaperedScore::new(7, 20), TaperedScore::new(10, 26), TaperedScore::new(12, 30),
+    TaperedScore::new(14, 34), TaperedScore::new(16, 38), TaperedScore::new(18, 41),
+    TaperedScore::new(20, 44), TaperedScore::new(21, 46), TaperedScore::new(22, 48),
+];
+#[rustfmt::skip]
+const QUEEN_MOBILITY: [TaperedScore; 28] = [
+    TaperedScore::new(-20, -40), TaperedScore::new(-12, -25), TaperedScore::new(-6, -15),
+    TaperedScore::new(-2, -8), TaperedScore::new(0, -2), TaperedScore::new(2, 3),
+    TaperedScore::new(4, 8), TaperedScore::new(6, 12), TaperedScore::new(7, 16),
+    TaperedScore::new(8, 20), TaperedScore::new(9, 23), TaperedScore::new(10, 26),
+    TaperedScore::new(11, 29), TaperedScore::new(12, 31), TaperedScore::new(13, 33),
+    TaperedScore::new(14, 35), TaperedScore::new(15, 37), TaperedScore::new(16, 39),
+    TaperedScore::new(17, 40), TaperedScore::new(18, 41), TaperedScore::new(19, 42),
+    TaperedScore::new(20, 43), TaperedScore::new(21, 44), TaperedScore::new(22, 45),
+    TaperedScore::new(23, 46), TaperedScore::new(24, 47), TaperedScore::new(25, 48),
+    TaperedScore::new(26, 49),
+];
+
+/// A knight or bishop on the fourth to sixth rank that no enemy pawn can ever attack.
+/// The bonus is halved when no own pawn defends it.
+const KNIGHT_OUTPOST: TaperedScore = TaperedScore::new(30, 20);
+const BISHOP_OUTPOST: TaperedScore = TaperedScore::new(15, 8);
+
+const BISHOP_PAIR: TaperedScore = TaperedScore::new(30, 50);
+
+const ROOK_OPEN_FILE: TaperedScore = TaperedScore::new(40, 15);
+const ROOK_SEMI_OPEN_FILE: TaperedScore = TaperedScore::new(20, 8);
+/// A rook on the seventh rank, counted when the enemy king is on its back rank or
+/// enemy pawns are still on their starting rank.
+const ROOK_ON_SEVENTH: TaperedScore = TaperedScore::new(20, 30);
+
+/// A rook on the back rank shut in by its own uncastled king.
+const TRAPPED_ROOK: TaperedScore = TaperedScore::new(-40, -5);
+/// The most safe squares a rook may have to count as trapped.
+const TRAPPED_ROOK_MOBILITY: u32 = 3;
+/// A bishop that took the pawn on a7 or h7 and is shut in by the pawn on b6 or g6.
+const TRAPPED_BISHOP: TaperedScore = TaperedScore::new(-80, -80);
+
+/// Bonus for attacking an enemy piece with a pawn, a minor piece or a rook, indexed
+/// by the `PieceType` of the attacked piece. Only pieces worth more than the attacker
+/// count.
+const THREAT_BY_PAWN: [TaperedScore; 6] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(45, 30),
+    TaperedScore::new(45, 30),
+    TaperedScore::new(60, 40),
+    TaperedScore::new(60, 45),
+    TaperedScore::new(0, 0),
+];
+const THREAT_BY_MINOR: [TaperedScore; 6] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(35, 25),
+    TaperedScore::new(40, 30),
+    TaperedScore::new(0, 0),
+];
+const THREAT_BY_ROOK: [TaperedScore; 6] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(35, 25),
+    TaperedScore::new(0, 0),
+];
+
+/// The weights of the piece terms, the constants above by default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PieceParams {
+    pub knight_mobility: [TaperedScore; 9],
+    pub bishop_mobility: [TaperedScore; 14],
+    pub rook_mobility: [TaperedScore; 15],
+    pub queen_mobility: [TaperedScore; 28],
+    pub knight_outpost: TaperedScore,
+    pub bishop_outpost: TaperedScore,
+    pub bishop_pair: TaperedScore,
+    pub rook_open_file: TaperedScore,
+    pub rook_semi_open_file: TaperedScore,
+    pub rook_on_seventh: TaperedScore,
+    pub trapped_rook: TaperedScore,
+    pub trapped_bishop: TaperedScore,
+    pub threat_by_pawn: [TaperedScore; 6],
+    pub threat_by_minor: [TaperedScore; 6],
+    pub threat_by_rook: [TaperedScore; 6],
+}
+
+impl Default for PieceParams {
+    fn default() -> Self {
+        PieceParams {
+            knight_mobility: KNIGHT_MOBILITY,
+            bishop_mobility: BISHOP_MOBILITY,
+            rook_mobility: ROOK_MOBILITY,
+            queen_mobility: QUEEN_MOBILITY,
+            knight_outpost: KNIGHT_OUTPOST,
+            bishop_outpost: BISHOP_OUTPOST,
+            bishop_pair: BISHOP_PAIR,
+            rook_open_file: ROOK_OPEN_FILE,
+            rook_semi_open_file: ROOK_SEMI_OPEN_FILE,
+            rook_on_seventh: ROOK_ON_SEVENTH,
+            trapped_rook: TRAPPED_ROOK,
+            trapped_bishop: TRAPPED_BISHOP,
+            threat_by_pawn: THREAT_BY_PAWN,
+            threat_by_minor: THREAT_BY_MINOR,
+            threat_by_rook: THREAT_BY_ROOK,
+        }
+    }
+}
+
+/// Evaluates the mobility of the knights, bishops, rooks and queens of both colors,
+/// indexed by `Color`.
+pub fn evaluate_mobility(
+    board: &Board,
+    attacks: &Attacks,
+    params: &PieceParams,
+) -> [TaperedScore; 2] {
+    [Color::White, Color::Black].map(|color| {
+        let safe = safe_squares(board, attacks, color);
+        attacks.pieces[color as usize]
+            .iter()
+            .map(|piece| {
+                let count = (piece.attacks & safe).count_ones() as usize;
+                match piece.piece {
+                    PieceType::Knight => params.knight_mobility[count],
+                    PieceType::Bishop => params.bishop_mobility[count],
+                    PieceType::Rook => params.rook_mobility[count],
+                    _ => params.queen_mobility[count],
+                }
+            })
+            .fold(TaperedScore::default(), |score, term| score + term)
+    })
+}
+
+/// Evaluates outposts, the bishop pair, rook files and trapped pieces of both colors,
+/// indexed by `Color`.
+pub fn evaluate_pieces(
+    board: &Board,
+    attacks: &Attacks,
+    params: &PieceParams,
+) -> [TaperedScore; 2] {
+    [Color::White, Color::Black].map(|color| {
+        let own = color as usize;
+        let enemy = color.opposite() as usize;
+        let own_pawns = board.bitboards[own][PieceType::Pawn as usize].0;
+        let enemy_pawns = board.bitboards[enemy][PieceType::Pawn as usize].0;
+        let pawn_defended = attacks.by_piece[own][PieceType::Pawn as usize];
+        let safe = safe_squares(board, attacks, color);
+
+        let mut score = TaperedScore::default();
+        if board.bitboards[own][PieceType::Bishop as usize]
+            .0
+            .count_ones()
+            >= 2
+        {
+            score += params.bishop_pair;
+        }
+
+        for piece in &attacks.pieces[own] {
+            let square = piece.square;
+            let rank = relative_rank(square, color);
+            match piece.piece {
+                PieceType::Knight | PieceType::Bishop => {
+                    if (3..=5).contains(&rank) && attack_span(square, color) & enemy_pawns == 0 {
+                        let mut outpost = match piece.piece {
+                            PieceType::Knight => params.knight_outpost,
+                            _ => params.bishop_outpost,
+                        };
+                        if pawn_defended & (1 << square) == 0 {
+                            outpost = TaperedScore::new(outpost.mg / 2, outpost.eg / 2);
+                        }
+                        score += outpost;
+                    }
+                    if piece.piece == PieceType::Bishop && is_trapped_bishop(board, color, square) {
+                        score += params.trapped_bishop;
+                    }
+                }
+                PieceType::Rook => {
+                    let file = file_mask(file_of(square));
+                    if file & own_pawns == 0 {
+                        score += if file & enemy_pawns == 0 {
+                            params.rook_open_file
+                        } else {
+                            params.rook_semi_open_file
+                        };
+                    }
+                    if rank == 6 {
+                        let enemy_king =
+                            board.bitboards[enemy][PieceType::King as usize].to_square();
+                        let seventh = rank_mask(square / 8);
+                        if relative_rank(enemy_king, color) == 7 || seventh & enemy_pawns != 0 {
+                            score += params.rook_on_seventh;
+                        }
+                    }
+                    if is_trapped_rook(board, color, square, (piece.attacks & safe).count_ones()) {
+                        score += params.trapped_rook;
+                    }
+                }
+                _ => {}
+            }
+        }
+        score
+    })
+}
+
+/// Evaluates the threats of both colors against enemy pieces, indexed by `Color`.
+pub fn evaluate_threats(
+    board: &Board,
+    attacks: &Attacks,
+    params: &PieceParams,
+) -> [TaperedScore; 2] {
+    [Color::White, Color::Black].map(|color| {
+        let own = color as usize;
+        let enemy = color.opposite() as usize;
+        let by_pawns = attacks.by_piece[own][PieceType::Pawn as usize];
+        let by_minors = attacks.by_piece[own][PieceType::Knight as usize]
+            | attacks.by_piece[own][PieceType::Bishop as usize];
+        let by_rooks = attacks.by_piece[own][PieceType::Rook as usize];
+
+        let mut score = TaperedScore::default();
+        for victim in [
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Rook,
+            PieceType::Queen,
+        ] {
+            let pieces = board.bitboards[enemy][victim as usize].0;
+            let v = victim as usize;
+            score += params.threat_by_pawn[v] * (pieces & by_pawns).count_ones() as i32;
+            score += params.threat_by_minor[v] * (pieces & by_minors).count_ones() as i32;
+            score += params.threat_by_rook[v] * (pieces & by_rooks).count_ones() as i32;
+        }
+        score
+    })
+}
+
+/// The squares the pieces of `color` can safely go to: not occupied by their own
+/// pawns or king and not attacked by enemy pawns.
+fn safe_squares(board: &Board, attacks: &Attacks, color: Color) -> u64 {
+    let own = color as usize;
+    let blocked = board.bitboards[own][PieceType::Pawn as usize].0
+        | board.bitboards[own][PieceType::King as usize].0;
+    let enemy_pawn_attacks = attacks.by_piece[color.opposite() as usize][PieceType::Pawn as usize];
+    !(blocked | enemy_pawn_attacks)
+}
+
+/// A rook with few safe squares on the back rank, on the same side of its king which
+/// stands on the back rank too.
+fn is_trapped_rook(board: &Board, color: Color, square: u8, mobility: u32) -> bool {
+    let king = board.bitboards[color as usize][PieceType::King as usize].to_square();
+    if mobility > TRAPPED_ROOK_MOBILITY
+        || relative_rank(square, color) != 0
+        || relative_rank(king, color) != 0
+    {
+        return false;
+    }
+    let (rook_file, king_file) = (file_of(square), file_of(king));
+    (king_file < 4 && rook_file < king_file) || (king_file >= 4 && rook_file > king_file)
+}
+
+/// A bishop on a7 or h7 (a2 or h2 for Black) with an enemy pawn on b6 or g6 (b3 or g3).
+fn is_trapped_bishop(board: &Board, color: Color, square: u8) -> bool {
+    if relative_rank(square, color) != 6 {
+        return false;
+    }
+    let enemy_pawns = board.bitboards[color.opposite() as usize][PieceType::Pawn as usize].0;
+    let blocker = match (file_of(square), color) {
+        (0, Color::White) => square - 7,
+        (7, Color::White) => square - 9,
+        (0, Color::Black) => square + 9,
+        (7, Color::Black) => square + 7,
+        _ => return false,
+    };
+    enemy_pawns & (1 << blocker) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(fen: &str) -> [[TaperedScore; 2]; 3] {
+        let board = Board::from_fen(fen).unwrap();
+        let attacks = Attacks::new(&board);
+        let params = PieceParams::default();
+        [
+            evaluate_mobility(&board, &attacks, &params),
+            evaluate_pieces(&board, &attacks, &params),
+            evaluate_threats(&board, &attacks, &params),
+        ]
+    }
+
+    #[test]
+    fn test_mobility() {
+        let [center, ..] = terms("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1");
+        let [corner, ..] = terms("4k3/8/8/8/8/8/8/N3K3 w - - 0 1");
+        assert_eq!(center[0], KNIGHT_MOBILITY[8]);
+        assert_eq!(corner[0], KNIGHT_MOBILITY[2]);
+
+        // Squares covered by enemy pawns do not count
+        let [covered, ..] = terms("4k3/8/8/2p1p3/8/3N4/8/4K3 w - - 0 1");
+        assert_eq!(covered[0], KNIGHT_MOBILITY[5]);
+    }
+
+    #[test]
+    fn test_piece_terms() {
+        // A knight outpost on d5 defended by e4, and the bishop pair
+        let [_, pieces, _] = terms("4k3/8/8/3N4/4P3/8/8/2B1KB2 w - - 0 1");
+        assert_eq!(pieces[0], KNIGHT_OUTPOST + BISHOP_PAIR);
+
+        // Rooks on an open file and on the seventh rank
+        let [_, open, _] = terms("4k3/pR6/8/8/8/8/8/3RK3 w - - 0 1");
+        assert_eq!(open[0], ROOK_OPEN_FILE * 2 + ROOK_ON_SEVENTH);
+
+        // The rook on h1 is shut in by the king on f1
+        let [_, trapped, _] = terms("4k3/8/8/8/8/8/6PP/5K1R w - - 0 1");
+        assert_eq!(trapped[0], TRAPPED_ROOK);
+
+        // Bxa7 b6 traps the bishop
+        let [_, bishop, _] = terms("4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(bishop[0], TRAPPED_BISHOP);
+    }
+
+    #[test]
+    fn test_threats() {
+        // The e4 pawn forks the knight and the rook, the bishop attacks the queen
+        let [.., threats] = terms("4k3/8/8/3n1r2/4P3/8/1B6/q3K3 w - - 0 1");
+        let expected =
+            THREAT_BY_PAWN[PieceType::Knight as usize] + THREAT_BY_PAWN[PieceType::Rook as usize];
+        assert_eq!(
+            threats[0],
+            expected + THREAT_BY_MINOR[PieceType::Queen as usize]
+        );
+        // A knight attacking a pawn is no threat
+        let [.., none] = terms("4k3/8/8/8/8/8/3p4/1N2K3 w - - 0 1");
+        assert_eq!(none[0], TaperedScore::default());
+    }
+}
@@ -1,206 +1,135 @@
-use chess::{
-    board::Board,
-    chess_move::ChessMove,
-    move_generator::MoveGenerator,
-    piece::{Color, PieceType},
+use std::{
+    fmt,
+    sync::{Arc, LazyLock},
 };
 
-pub fn score(board: &mut Board) -> i32 {
-    let mut score = 0;
-    score += score_piece_value_diff(board);
-    score += score_piece_square(board);
-    score
-}
-
-fn score_piece_value_diff(board: &Board) -> i32 {
-    let piece_values = [
-        (PieceType::Pawn, 100),
-        (PieceType::Knight, 320),
-        (PieceType::Bishop, 330),
-        (PieceType::Rook, 500),
-        (PieceType::Queen, 900),
-        (PieceType::King, 20000),
-    ];
-
-    let mut score = 0;
-    for (piece_type, value) in &piece_values {
-        let white_pieces = board.bitboards[Color::White as usize][*piece_type as usize].popcnt();
-        let black_pieces = board.bitboards[Color::Black as usize][*piece_type as usize].popcnt();
-        score += value * (white_pieces as i32 - black_pieces as i32);
-    }
-    score
-}
-
-fn score_piece_square(board: &Board) -> i32 {
-    let mut score = 0;
-
-    let piece_types = [
-        (PieceType::Pawn, &PAWN_PIECE_TABLE),
-        (PieceType::Knight, &KNIGHT_PIECE_TABLE),
-        (PieceType::Bishop, &BISHOP_PIECE_TABLE),
-        (PieceType::Rook, &ROOK_PIECE_TABLE),
-        (PieceType::Queen, &QUEEN_PIECE_TABLE),
-        (PieceType::King, &KING_PIECE_TABLE),
-    ];
-
-    for (piece_type, table) in &piece_types {
-        for color in [Color::White, Color::Black].iter() {
-            let color_index = *color as usize;
-            let bitboard = board.bitboards[color_index][*piece_type as usize];
-            let color_multiplier = if *color == Color::White { 1 } else { -1 };
+use crate::{
+    eval::{ClassicalEvaluator, Evaluator},
+    search::{Extensions, Search},
+    tt::TranspositionTable,
+};
+use chess::{board::Board, chess_move::ChessMove, move_generator::MoveGenerator, piece::Color};
 
-            // Iterate over all pieces of this type and color
-            let mut pieces = bitboard;
-            while pieces.0 != 0 {
-                let square = pieces.to_square(); // Get the square number of the least significant bit
-                score += table[square as usize] * color_multiplier;
-                pieces.0 ^= 1 << square; // Clear the bit for the current piece
-            }
-        }
-    }
-    score
+/// The default static evaluation, in centipawns for the side to move.
+pub fn score(board: &mut Board) -> i32 {
+    static EVALUATOR: LazyLock<ClassicalEvaluator> = LazyLock::new(ClassicalEvaluator::default);
+    EVALUATOR.evaluate(board)
 }
 
+/// Negamax search without pruning, returning the score for the side to move.
 pub fn minimax(board: &mut Board, depth: u32) -> (i32, Option<ChessMove>) {
-    let maximizing = board.side_to_move == Color::White;
     if depth == 0 {
         return (score(board), None); // No move to return when depth is 0
     }
+    let moves = MoveGenerator::generate_legal_moves(board);
+    if moves.is_empty() && !board.is_king_in_check(board.side_to_move) {
+        return (0, None);
+    }
     let mut best_move = None;
-    let mut best_score = if maximizing { i32::MIN } else { i32::MAX };
-    for mv in MoveGenerator::generate_legal_moves(board) {
+    let mut best_score = -MATE_SCORE;
+    for mv in moves {
         board.make_move(mv);
         let (score, _) = minimax(board, depth - 1);
         board.unmake();
-        if (maximizing && score > best_score) || (!maximizing && score < best_score) {
-            best_score = score;
+        if -score > best_score {
+            best_score = -score;
             best_move = Some(mv);
         }
     }
     (best_score, best_move)
 }
 
-const MATE_SCORE: i32 = 100_000;
-
-pub fn minimax_ab(
-    board: &mut Board,
-    depth: u32,
-    ply: u32,
-    mut alpha: i32,
-    mut beta: i32,
-) -> (i32, Option<ChessMove>) {
-    let maximizing = board.side_to_move == Color::White;
-    let mut best_move = None;
-    let mut best_score = if maximizing { i32::MIN } else { i32::MAX };
+/// The score of a side that is checkmated at the root.
+/// A mate found `ply` plies from the root scores `MATE_SCORE - ply`.
+pub const MATE_SCORE: i32 = 100_000;
+/// Scores at least this large in magnitude are forced mates rather than evaluations.
+pub const MATE_THRESHOLD: i32 = MATE_SCORE - 1000;
+
+/// A search score, either an evaluation or a forced mate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    /// An evaluation in centipawns.
+    Centipawns(i32),
+    /// A forced mate in the given number of moves.
+    /// Positive when the side the score belongs to mates, negative when it gets mated.
+    Mate(i32),
+}
 
-    if depth == 0 {
-        return (score(board), None); // No move to return when depth is 0
+impl Score {
+    /// Converts a raw search score into a `Score`, counting mates in moves rather than plies.
+    pub fn from_value(value: i32) -> Self {
+        if value.abs() < MATE_THRESHOLD {
+            return Score::Centipawns(value);
+        }
+        let plies = MATE_SCORE - value.abs();
+        let moves = (plies + 1) / 2;
+        Score::Mate(if value > 0 { moves } else { -moves })
     }
 
-    let mut moves = MoveGenerator::generate_legal_moves(board);
-    if moves.is_empty() {
-        if board.is_king_in_check(board.side_to_move) {
-            return (
-                if maximizing {
-                    -MATE_SCORE + ply as i32
-                } else {
-                    MATE_SCORE - ply as i32
-                },
-                None,
-            );
+    /// Negates the score for Black and keeps it for White. Search scores are for the
+    /// side to move, so `score.for_color(side_to_move)` is the score for White, and the
+    /// score for White converts back the same way.
+    pub fn for_color(self, color: Color) -> Self {
+        match (self, color) {
+            (_, Color::White) => self,
+            (Score::Centipawns(cp), Color::Black) => Score::Centipawns(-cp),
+            (Score::Mate(moves), Color::Black) => Score::Mate(-moves),
         }
-        return (0, None);
     }
-    if board.is_draw() {
-        return (0, None);
-    }
-
-    order_moves(&mut moves);
+}
 
-    for mv in moves {
-        board.make_move(mv);
-        let (score, _) = minimax_ab(board, depth - 1, ply + 1, alpha, beta);
-        board.unmake();
-        if maximizing {
-            if score > best_score {
-                best_score = score;
-                best_move = Some(mv);
-            }
-            alpha = std::cmp::max(alpha, score);
-        } else {
-            if score < best_score {
-                best_score = score;
-                best_move = Some(mv);
-            }
-            beta = std::cmp::min(beta, score);
-        }
-        if alpha >= beta {
-            break;
+/// Formats the score the way UCI `info` lines expect it, e.g. `cp 35` or `mate -2`.
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Score::Centipawns(cp) => write!(f, "cp {}", cp),
+            Score::Mate(moves) => write!(f, "mate {}", moves),
         }
     }
-    (best_score, best_move)
 }
-const PAWN_PIECE_TABLE: [i32; 64] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 50, 50, 50, 50, 50, 50, 50, 50, 10, 10, 20, 30, 30, 20, 10, 10, 5, 5,
-    10, 25, 25, 10, 5, 5, 0, 0, 0, 20, 20, 0, 0, 0, 5, -5, -10, 0, 0, -10, -5, 5, 5, 10, 10, -20,
-    -20, 10, 10, 5, 0, 0, 0, 0, 0, 0, 0, 0,
-];
-
-const KNIGHT_PIECE_TABLE: [i32; 64] = [
-    -50, -40, -30, -30, -30, -30, -40, -50, -40, -20, 0, 0, 0, 0, -20, -40, -30, 0, 10, 15, 15, 10,
-    0, -30, -30, 5, 15, 20, 20, 15, 5, -30, -30, 0, 15, 20, 20, 15, 0, -30, -30, 5, 10, 15, 15, 10,
-    5, -30, -40, -20, 0, 5, 5, 0, -20, -40, -50, -40, -30, -30, -30, -30, -40, -50,
-];
-
-const BISHOP_PIECE_TABLE: [i32; 64] = [
-    -20, -10, -10, -10, -10, -10, -10, -20, -10, 0, 0, 0, 0, 0, 0, -10, -10, 0, 5, 10, 10, 5, 0,
-    -10, -10, 5, 5, 10, 10, 5, 5, -10, -10, 0, 10, 10, 10, 10, 0, -10, -10, 10, 10, 10, 10, 10, 10,
-    -10, -10, 5, 0, 0, 0, 0, 5, -10, -20, -10, -10, -10, -10, -10, -10, -20,
-];
-
-const ROOK_PIECE_TABLE: [i32; 64] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 5, 10, 10, 10, 10, 10, 10, 5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0,
-    0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, 0, 0,
-    0, 5, 5, 0, 0, 0,
-];
-
-const QUEEN_PIECE_TABLE: [i32; 64] = [
-    -20, -10, -10, -5, -5, -10, -10, -20, -10, 0, 0, 0, 0, 0, 0, -10, -10, 0, 5, 5, 5, 5, 0, -10,
-    -5, 0, 5, 5, 5, 5, 0, -5, 0, 0, 5, 5, 5, 5, 0, -5, -10, 5, 5, 5, 5, 5, 0, -10, -10, 0, 5, 0, 0,
-    0, 0, -10, -20, -10, -10, -5, -5, -10, -10, -20,
-];
-
-const KING_PIECE_TABLE: [i32; 64] = [
-    -30, -40, -40, -50, -50, -40, -40, -30, -30, -40, -40, -50, -50, -40, -40, -30, -30, -40, -40,
-    -50, -50, -40, -40, -30, -30, -40, -40, -50, -50, -40, -40, -30, -20, -30, -30, -40, -40, -30,
-    -30, -20, -10, -20, -20, -20, -20, -20, -20, -10, 20, 20, 0, 0, 0, 0, 20, 20, 20, 30, 10, 0, 0,
-    10, 30, 20,
-];
 
-fn order_moves(moves: &mut [ChessMove]) {
-    moves.sort_by(|a, b| {
-        // Order captures first
-        let a_captures = a.captured_piece.is_some();
-        let b_captures = b.captured_piece.is_some();
-        b_captures.cmp(&a_captures)
-    });
+/// Alpha-beta search to a uniform depth, without extensions, using a fresh transposition table
+/// of a megabyte, small enough to allocate on every call.
+/// Use `Search` directly to keep a larger table between searches.
+/// The window and the returned score are for the side to move.
+pub fn minimax_ab(
+    board: &mut Board,
+    depth: u32,
+    ply: u32,
+    alpha: i32,
+    beta: i32,
+) -> (i32, Option<ChessMove>) {
+    // Keep the window negatable, `i32::MIN` has no positive counterpart
+    let alpha = alpha.max(-MATE_SCORE - 1);
+    let beta = beta.min(MATE_SCORE + 1);
+    let tt = Arc::new(TranspositionTable::new(1));
+    Search::with_table(Extensions::none(), tt).negamax(board, depth, ply, alpha, beta, 0)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // This test is to assert no functional changes have occured while I'm working on linting
-    // changes.
-    // These should be deleted once functional tests changes are added.
     #[test]
-    fn test_score_output_1() {
-        let mut board =
-            Board::from_fen("2b3k1/4pp1p/5np1/Q7/3qP3/5P2/P1PBK1PP/1r3B1R w - - 5 25").unwrap();
-        let (_, mv) = minimax_ab(&mut board, 7, 0, i32::MIN, i32::MAX);
+    fn test_score_from_value() {
+        assert_eq!(Score::from_value(35), Score::Centipawns(35));
+        assert_eq!(Score::from_value(MATE_SCORE - 1), Score::Mate(1));
+        assert_eq!(Score::from_value(MATE_SCORE - 2), Score::Mate(1));
+        assert_eq!(Score::from_value(MATE_SCORE - 3), Score::Mate(2));
+        assert_eq!(Score::from_value(-MATE_SCORE + 4), Score::Mate(-2));
+        assert_eq!(Score::Mate(2).for_color(Color::Black), Score::Mate(-2));
+        assert_eq!(Score::Mate(-3).to_string(), "mate -3");
+        assert_eq!(Score::Centipawns(-12).to_string(), "cp -12");
+    }
+
+    #[test]
+    fn test_knight_fork() {
+        // Nc7+ forks the king and the queen
+        let mut board = Board::from_fen("q3k3/8/8/3N4/8/8/8/4K3 w - - 0 1").unwrap();
+        let (_, mv) = minimax_ab(&mut board, 4, 0, i32::MIN, i32::MAX);
         let mv = mv.unwrap();
-        assert!(mv.from == 11);
-        assert!(mv.to == 18);
+        assert!(mv.from == 35);
+        assert!(mv.to == 50);
     }
     #[test]
     fn test_score_output_2() {
@@ -213,36 +142,35 @@ mod tests {
         assert!(mv.to == 47);
     }
     #[test]
-    fn test_score_output_3() {
+    fn test_scholars_mate() {
+        // Qxf7 is mate
         let mut board =
-            Board::from_fen("rnbqk2r/pp2ppbp/2p2np1/3p4/3P1B2/2NQ1N2/PPP1PPPP/R3KB1R w KQkq - 2 6")
+            Board::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
                 .unwrap();
-        let (_, mv) = minimax_ab(&mut board, 7, 0, i32::MIN, i32::MAX);
+        let (_, mv) = minimax_ab(&mut board, 4, 0, i32::MIN, i32::MAX);
         let mv = mv.unwrap();
-        assert!(mv.from == 29);
-        assert!(mv.to == 38);
+        assert!(mv.from == 39);
+        assert!(mv.to == 53);
     }
     #[test]
-    fn test_score_output_4() {
+    fn test_fools_mate() {
+        // Qh4 is mate
         let mut board =
-            Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2")
+            Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2")
                 .unwrap();
-        let (_, mv) = minimax_ab(&mut board, 7, 0, i32::MIN, i32::MAX);
+        let (_, mv) = minimax_ab(&mut board, 4, 0, i32::MIN, i32::MAX);
         let mv = mv.unwrap();
-        println!("{:?}", mv);
         assert!(mv.from == 59);
-        assert!(mv.to == 45);
+        assert!(mv.to == 31);
     }
     #[test]
-    fn test_score_output_5() {
-        let mut board = Board::from_fen(
-            "r1b1k2r/1ppp1pp1/p1n2q1p/3Pp3/2Bb4/P2PBN2/1PP2PPP/R2Q1RK1 b kq - 0 10",
-        )
-        .unwrap();
-        let (_, mv) = minimax_ab(&mut board, 7, 0, i32::MIN, i32::MAX);
+    fn test_back_rank_mate() {
+        // Ra1 mates on the back rank
+        let mut board = Board::from_fen("r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1").unwrap();
+        let (_, mv) = minimax_ab(&mut board, 4, 0, i32::MIN, i32::MAX);
         let mv = mv.unwrap();
-        assert!(mv.from == 27);
-        assert!(mv.to == 9);
+        assert!(mv.from == 56);
+        assert!(mv.to == 0);
     }
     #[test]
     fn test_score_output_6() {
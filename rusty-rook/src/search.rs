@@ -0,0 +1,1194 @@
+use std::{
+    cmp::Reverse,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use chess::{
+    board::Board,
+    chess_move::ChessMove,
+    move_generator::MoveGenerator,
+    piece::{Color, PieceType},
+    variant::{Outcome, Variant},
+};
+
+use crate::{
+    eval::{evaluate_antichess, ClassicalEvaluator, EvalState, Evaluator},
+    score::{Score, MATE_SCORE, MATE_THRESHOLD},
+    smp,
+    syzygy::{self, Tablebase},
+    time::{Limits, TimeManager},
+    tt::{score_from_tt, score_to_tt, Bound, TranspositionTable, TtEntry, TtMove},
+};
+
+/// Selects which moves are searched deeper than the nominal depth.
+/// Every extension adds one ply, and a line can never be extended by more than
+/// `max_per_line` plies in total so that long forcing sequences cannot explode the tree.
+#[derive(Debug, Clone, Copy)]
+pub struct Extensions {
+    /// Extend moves that give check.
+    pub check: bool,
+    /// Extend the transposition table move when every other move is clearly worse.
+    pub singular: bool,
+    /// Extend a capture on the square the opponent just captured on.
+    pub recapture: bool,
+    /// Extend pawn pushes to the seventh rank.
+    pub passed_pawn: bool,
+    /// The maximum number of plies a single line may be extended by.
+    pub max_per_line: u32,
+}
+
+impl Extensions {
+    /// No extensions, every move reduces the depth by exactly one ply.
+    pub fn none() -> Self {
+        Extensions {
+            check: false,
+            singular: false,
+            recapture: false,
+            passed_pawn: false,
+            max_per_line: 0,
+        }
+    }
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Extensions {
+            check: true,
+            singular: true,
+            recapture: true,
+            passed_pawn: true,
+            max_per_line: 4,
+        }
+    }
+}
+
+/// Singular extensions are only tried at nodes with at least this much depth left.
+const SINGULAR_MIN_DEPTH: u32 = 4;
+/// How much worse than the transposition table score every alternative has to be,
+/// per ply of remaining depth, for the table move to count as singular.
+const SINGULAR_MARGIN_PER_PLY: i32 = 20;
+
+/// A bound outside of every reachable score, used as the initial search window.
+const INFINITY: i32 = MATE_SCORE + 1;
+
+/// Killer moves and principal variations are kept for this many plies from the root.
+const MAX_PLY: usize = 128;
+/// The deepest iteration of a search without a depth limit.
+const MAX_DEPTH: u32 = 64;
+/// The main thread checks the clock every this many nodes.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// The deepest iteration a search thread completed.
+#[derive(Debug, Clone)]
+pub(crate) struct ThreadResult {
+    pub depth: u32,
+    /// The score and principal variation of every line, best first.
+    pub lines: Vec<(i32, Vec<ChessMove>)>,
+}
+
+impl ThreadResult {
+    /// The score of the best line.
+    pub fn score(&self) -> i32 {
+        self.lines.first().map_or(0, |(score, _)| *score)
+    }
+
+    pub fn has_move(&self) -> bool {
+        self.lines.first().is_some_and(|(_, pv)| !pv.is_empty())
+    }
+
+    fn to_search_result(&self, nodes: u64, time: Duration) -> SearchResult {
+        let lines: Vec<PvLine> = self
+            .lines
+            .iter()
+            .map(|(score, pv)| PvLine {
+                score: Score::from_value(*score),
+                pv: pv.clone(),
+            })
+            .collect();
+        let best = lines.first().cloned().unwrap_or(PvLine {
+            score: Score::Centipawns(0),
+            pv: Vec::new(),
+        });
+        SearchResult {
+            depth: self.depth,
+            score: best.score,
+            pv: best.pv,
+            lines,
+            nodes,
+            time,
+        }
+    }
+}
+
+/// One root move with its score and principal variation.
+#[derive(Debug, Clone)]
+pub struct PvLine {
+    /// Score for the side to move.
+    pub score: Score,
+    /// The principal variation, starting with the root move.
+    pub pv: Vec<ChessMove>,
+}
+
+/// The outcome of a search, or of one of its iterations.
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub depth: u32,
+    /// Score of the best line for the side to move.
+    pub score: Score,
+    /// The principal variation of the best line, starting with the best move.
+    pub pv: Vec<ChessMove>,
+    /// With MultiPV, the best lines sorted from best to worst.
+    /// Otherwise only the best line.
+    pub lines: Vec<PvLine>,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+impl SearchResult {
+    pub fn best_move(&self) -> Option<ChessMove> {
+        self.pv.first().copied()
+    }
+
+    /// The reply the search expects from the opponent, which the engine can ponder on.
+    pub fn ponder_move(&self) -> Option<ChessMove> {
+        self.pv.get(1).copied()
+    }
+}
+
+/// A handle to control a running search from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct SearchControl {
+    stop: Arc<AtomicBool>,
+    pondering: Arc<AtomicBool>,
+}
+
+impl SearchControl {
+    /// Ends the search as soon as possible. The search still returns its best move.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// The opponent played the expected move: the ponder search goes on as a normal
+    /// search, under the time limits it was started with.
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+}
+
+/// An alpha-beta search with a transposition table and configurable extensions.
+///
+/// With more than one thread the search runs Lazy SMP, see `smp`. Each thread is a
+/// `Search` of its own with private move ordering tables, sharing the transposition
+/// table and the control flags with the others.
+pub struct Search {
+    tt: Arc<TranspositionTable>,
+    evaluator: Arc<dyn Evaluator>,
+    /// The incremental state of the evaluator for this thread, if it has one.
+    eval_state: Option<Box<dyn EvalState>>,
+    extensions: Extensions,
+    threads: usize,
+    /// The number of root moves searched with a full window, see `set_multi_pv`.
+    multi_pv: usize,
+    /// Root moves already reported by earlier lines of the current MultiPV iteration.
+    excluded_root_moves: Vec<ChessMove>,
+    /// Endgame tables probed inside the tree and used to filter the root moves.
+    tablebase: Option<Arc<dyn Tablebase>>,
+    /// The root moves that keep the tablebase result, empty to search every move.
+    root_moves: Vec<ChessMove>,
+    /// The index of this thread, 0 for the main thread.
+    id: usize,
+    control: SearchControl,
+    /// Limits the main thread, none for helpers and searches without a time limit.
+    time: Option<TimeManager>,
+    /// Whether the main thread has completed an iteration it may fall back on.
+    has_result: bool,
+    /// Two quiet moves per ply that recently caused a beta cutoff.
+    killers: Vec<[Option<TtMove>; 2]>,
+    /// How often each quiet move caused a cutoff, indexed by color, from and to square.
+    history: Vec<i32>,
+    /// The principal variation found below each ply, see `update_pv`.
+    pv_table: Vec<Vec<ChessMove>>,
+    pub nodes: u64,
+}
+
+impl Search {
+    pub fn new(extensions: Extensions) -> Self {
+        Self::with_table(extensions, Arc::new(TranspositionTable::default()))
+    }
+
+    /// Creates a search using `tt` as its transposition table, which may be shared
+    /// with other searches.
+    pub fn with_table(extensions: Extensions, tt: Arc<TranspositionTable>) -> Self {
+        Search {
+            tt,
+            evaluator: Arc::new(ClassicalEvaluator::default()),
+            eval_state: None,
+            extensions,
+            threads: 1,
+            multi_pv: 1,
+            excluded_root_moves: Vec::new(),
+            tablebase: None,
+            root_moves: Vec::new(),
+            id: 0,
+            control: SearchControl::default(),
+            time: None,
+            has_result: false,
+            killers: vec![[None; 2]; MAX_PLY],
+            history: vec![0; 2 * 64 * 64],
+            pv_table: vec![Vec::new(); MAX_PLY],
+            nodes: 0,
+        }
+    }
+
+    /// Sets how many threads `search` uses. A single thread is fully deterministic.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Replaces the static evaluation used at the leaves of the search.
+    pub fn set_evaluator(&mut self, evaluator: Arc<dyn Evaluator>) {
+        self.eval_state = evaluator.new_state();
+        self.evaluator = evaluator;
+    }
+
+    /// Sets the endgame tablebase, or removes it with None.
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<dyn Tablebase>>) {
+        self.tablebase = tablebase;
+    }
+
+    /// Forgets everything learned in earlier searches, for a new game.
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+    }
+
+    /// Sets how many of the best root moves are searched, each with its own score and
+    /// principal variation. Searching more than one costs a search per line.
+    pub fn set_multi_pv(&mut self, lines: usize) {
+        self.multi_pv = lines.max(1);
+    }
+
+    /// Returns a handle that stops the search or ends pondering from another thread.
+    pub fn control(&self) -> SearchControl {
+        self.control.clone()
+    }
+
+    /// Creates the search for helper thread `id`, sharing the table and control flags.
+    pub(crate) fn helper(&self, id: usize) -> Search {
+        Search {
+            evaluator: Arc::clone(&self.evaluator),
+            eval_state: self.evaluator.new_state(),
+            control: self.control.clone(),
+            multi_pv: self.multi_pv,
+            tablebase: self.tablebase.clone(),
+            root_moves: self.root_moves.clone(),
+            id,
+            ..Search::with_table(self.extensions, Arc::clone(&self.tt))
+        }
+    }
+
+    pub(crate) fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub(crate) fn stop(&self) {
+        self.control.stop();
+    }
+
+    fn stopped(&self) -> bool {
+        self.control.is_stopped()
+    }
+
+    /// Searches `board` with iterative deepening up to `depth` plies.
+    /// Earlier iterations fill the transposition table, which orders moves
+    /// and drives singular extensions in the later ones.
+    pub fn search(&mut self, board: &mut Board, depth: u32) -> SearchResult {
+        self.search_with_limits(board, &Limits::depth(depth), |_| {})
+    }
+
+    /// Searches `board` until one of `limits` is reached or the search is stopped
+    /// through its `SearchControl`, calling `info` after every completed iteration.
+    /// With a `mate` limit it looks for a forced mate instead, see `mate_search`.
+    ///
+    /// A ponder or infinite search does not return before it is stopped, even if it
+    /// reached its depth limit, since the GUI does not expect a move before that.
+    pub fn search_with_limits(
+        &mut self,
+        board: &mut Board,
+        limits: &Limits,
+        mut info: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        let start = Instant::now();
+        self.nodes = 0;
+        self.has_result = false;
+        self.time = Some(TimeManager::new(limits, start));
+        self.control.stop.store(false, Ordering::Relaxed);
+        self.control
+            .pondering
+            .store(limits.ponder, Ordering::Relaxed);
+        self.root_moves = self
+            .tablebase
+            .as_deref()
+            .and_then(|tablebase| syzygy::filter_root_moves(tablebase, board))
+            .unwrap_or_default();
+
+        let depth = limits.depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH);
+        let result = if let Some(moves) = limits.mate {
+            self.find_mate(board, moves, &mut info)
+        } else if self.threads > 1 {
+            smp::search(self, board, depth, &mut info)
+        } else {
+            self.iterative_deepening(board, depth, Some(&mut info))
+        };
+        self.time = None;
+        result.to_search_result(self.nodes, start.elapsed())
+    }
+
+    /// Runs the iterations of one search thread until `depth` is reached or the
+    /// search is stopped. Helper threads skip some depths so that the threads
+    /// spread out over different iterations. The main thread reports every
+    /// completed iteration to `info` and ends the search when its time is up.
+    pub(crate) fn iterative_deepening(
+        &mut self,
+        board: &mut Board,
+        depth: u32,
+        mut info: Option<&mut dyn FnMut(&SearchResult)>,
+    ) -> ThreadResult {
+        self.killers
+            .iter_mut()
+            .for_each(|killers| *killers = [None; 2]);
+        self.history.iter_mut().for_each(|count| *count = 0);
+
+        let mut result = ThreadResult {
+            depth: 0,
+            lines: Vec::new(),
+        };
+        for current_depth in 1..=depth {
+            if self.id > 0 && smp::skip_depth(self.id, current_depth) {
+                continue;
+            }
+            let Some(lines) = self.search_lines(board, current_depth) else {
+                break;
+            };
+            result = ThreadResult {
+                depth: current_depth,
+                lines,
+            };
+            self.has_result = true;
+
+            if let Some(time) = &self.time {
+                if let Some(info) = info.as_mut() {
+                    info(&result.to_search_result(self.nodes, time.elapsed()));
+                }
+                let limit_reached =
+                    time.soft_limit_reached() || time.node_limit_reached(self.nodes);
+                if !self.control.is_pondering() && limit_reached {
+                    break;
+                }
+            }
+        }
+
+        self.wait_until_stopped();
+        result
+    }
+
+    /// Keeps a ponder or infinite search from returning before it is stopped.
+    fn wait_until_stopped(&self) {
+        if let Some(time) = &self.time {
+            while !self.stopped() && (time.is_infinite() || self.control.is_pondering()) {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    /// Searches the root once per MultiPV line, every time excluding the moves of the
+    /// lines found before. Returns the lines sorted from best to worst, or None if the
+    /// search was stopped.
+    fn search_lines(
+        &mut self,
+        board: &mut Board,
+        depth: u32,
+    ) -> Option<Vec<(i32, Vec<ChessMove>)>> {
+        let root_moves = match self.root_moves.len() {
+            0 => MoveGenerator::generate_legal_moves(board).len(),
+            filtered => filtered,
+        };
+        let mut lines = Vec::new();
+        self.excluded_root_moves.clear();
+        for _ in 0..self.multi_pv.min(root_moves).max(1) {
+            let (score, _) = self.negamax(board, depth, 0, -INFINITY, INFINITY, 0);
+            if self.stopped() {
+                self.excluded_root_moves.clear();
+                return None;
+            }
+            let pv = self.complete_pv(board, depth);
+            self.excluded_root_moves.extend(pv.first());
+            lines.push((score, pv));
+        }
+        self.excluded_root_moves.clear();
+
+        lines.sort_by_key(|(score, _)| Reverse(*score));
+        Some(lines)
+    }
+
+    /// Stops the search when the main thread runs out of time or nodes. The first
+    /// iteration always completes so that there is a move to play. Nodes are only
+    /// counted as often as the clock is checked, so a node limit may be overshot by
+    /// up to `TIME_CHECK_INTERVAL` nodes.
+    fn check_time(&self) {
+        if let Some(time) = &self.time {
+            let limit_reached = time.hard_limit_reached() || time.node_limit_reached(self.nodes);
+            if self.has_result && !self.control.is_pondering() && limit_reached {
+                self.stop();
+            }
+        }
+    }
+
+    /// Returns the principal variation of the last iteration. Where it was cut short
+    /// by a transposition table cutoff it is continued with the moves stored in the
+    /// table, up to `depth` moves.
+    fn complete_pv(&self, board: &mut Board, depth: u32) -> Vec<ChessMove> {
+        let mut pv = self.pv_table[0].clone();
+        for mv in &pv {
+            board.make_move(*mv);
+        }
+        while pv.len() < depth as usize && !board.is_draw() {
+            let next = self.tt.probe(board.hash()).and_then(|entry| {
+                let tt_move = entry.best_move?;
+                MoveGenerator::generate_legal_moves(board)
+                    .into_iter()
+                    .find(|mv| tt_move.matches(mv))
+            });
+            let Some(mv) = next else {
+                break;
+            };
+            board.make_move(mv);
+            pv.push(mv);
+        }
+        for _ in &pv {
+            board.unmake();
+        }
+        pv
+    }
+
+    /// The evaluation state for the moves of `board`. Antichess is evaluated without
+    /// one, and Atomic explosions remove pieces the state does not follow.
+    fn eval_state_for(&mut self, board: &Board) -> Option<&mut Box<dyn EvalState>> {
+        self.eval_state
+            .as_mut()
+            .filter(|_| !matches!(board.variant, Variant::Antichess | Variant::Atomic))
+    }
+
+    /// Makes a move on the board and updates the evaluation state to match.
+    fn make_move(&mut self, board: &mut Board, mv: ChessMove) {
+        board.make_move(mv);
+        if let Some(state) = self.eval_state_for(board) {
+            state.make_move(board);
+        }
+    }
+
+    fn unmake(&mut self, board: &mut Board) {
+        board.unmake();
+        if let Some(state) = self.eval_state_for(board) {
+            state.unmake(board);
+        }
+    }
+
+    /// Makes `mv` followed by the principal variation of the child node the
+    /// principal variation of the node at `ply`.
+    fn update_pv(&mut self, ply: u32, mv: ChessMove) {
+        let ply = ply as usize;
+        if ply + 1 >= self.pv_table.len() {
+            return;
+        }
+        let (parents, children) = self.pv_table.split_at_mut(ply + 1);
+        let pv = &mut parents[ply];
+        pv.clear();
+        pv.push(mv);
+        pv.extend_from_slice(&children[0]);
+    }
+
+    /// Negamax alpha-beta search returning the score for the side to move and the best move.
+    ///
+    /// # Arguments
+    /// * `depth` - The remaining depth, before extensions
+    /// * `ply` - The distance from the root
+    /// * `extended` - How many plies this line has already been extended by
+    pub fn negamax(
+        &mut self,
+        board: &mut Board,
+        depth: u32,
+        ply: u32,
+        mut alpha: i32,
+        mut beta: i32,
+        extended: u32,
+    ) -> (i32, Option<ChessMove>) {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) {
+            self.check_time();
+        }
+        if let Some(pv) = self.pv_table.get_mut(ply as usize) {
+            pv.clear();
+        }
+
+        if depth == 0 {
+            // No move to return when depth is 0. A game the variant ended, such as one
+            // without a king, is scored rather than evaluated
+            if let Some(outcome) = board.variant_outcome() {
+                return (outcome_score(outcome, board, ply), None);
+            }
+            // The evaluation only shrinks a known endgame to half over the fifty move
+            // rule, a leaf the rule has already drawn is worth nothing
+            if board.half_move_clock >= 100 {
+                return (0, None);
+            }
+            let score = if board.variant == Variant::Antichess {
+                evaluate_antichess(board)
+            } else if let Some(state) = self.eval_state_for(board) {
+                state.evaluate(board)
+            } else {
+                self.evaluator.evaluate(board)
+            };
+            return (score, None);
+        }
+
+        // Mate distance pruning: nothing below this node can mate faster than mating
+        // right here, so shrink the window to the scores that are still reachable
+        if ply > 0 {
+            alpha = alpha.max(-MATE_SCORE + ply as i32);
+            beta = beta.min(MATE_SCORE - ply as i32);
+            if alpha >= beta {
+                return (alpha, None);
+            }
+        }
+
+        let mut moves = MoveGenerator::generate_legal_moves(board);
+        if moves.is_empty() {
+            // Mate, stalemate or an end by the rules of the variant
+            return (
+                outcome_score(board.outcome_without_moves(), board, ply),
+                None,
+            );
+        }
+        if board.is_draw() {
+            return (0, None);
+        }
+
+        let key = board.hash();
+        let tt_entry = self.tt.probe(key).map(|entry| TtEntry {
+            score: score_from_tt(entry.score, ply),
+            ..entry
+        });
+        if let Some(entry) = tt_entry {
+            // Never cut at the root, the caller needs a move
+            if ply > 0 && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return (entry.score, None),
+                    Bound::Lower if entry.score >= beta => return (entry.score, None),
+                    Bound::Upper if entry.score <= alpha => return (entry.score, None),
+                    _ => {}
+                }
+            }
+        }
+        let tt_move = tt_entry.and_then(|entry| entry.best_move);
+
+        // Right after a capture or pawn move the tables know the exact result
+        if ply > 0 && board.half_move_clock == 0 {
+            let wdl = self
+                .tablebase
+                .as_deref()
+                .filter(|tablebase| tablebase.covers(board))
+                .and_then(|tablebase| tablebase.probe_wdl(board));
+            if let Some(wdl) = wdl {
+                return (wdl.score(ply), None);
+            }
+        }
+
+        if ply == 0 && !self.root_moves.is_empty() {
+            let root_moves = &self.root_moves;
+            moves.retain(|mv| root_moves.iter().any(|root| root.is_same_move(mv)));
+        }
+
+        // The root entry would describe the position without the excluded moves
+        let excluding = ply == 0 && !self.excluded_root_moves.is_empty();
+        if excluding {
+            let excluded = &self.excluded_root_moves;
+            moves.retain(|mv| !excluded.iter().any(|excluded| excluded.is_same_move(mv)));
+        }
+
+        self.order_moves(&mut moves, tt_move, board.side_to_move, ply);
+
+        // The table score has to be a score the side to move is guaranteed to reach
+        let singular_move = match (tt_entry, tt_move) {
+            (Some(entry), Some(tt_move))
+                if self.extensions.singular
+                    && ply > 0
+                    && extended < self.extensions.max_per_line
+                    && depth >= SINGULAR_MIN_DEPTH
+                    && entry.depth + 3 >= depth
+                    && entry.bound != Bound::Upper
+                    && entry.score.abs() < MATE_THRESHOLD =>
+            {
+                self.is_singular(board, &moves, tt_move, entry.score, depth, ply, extended)
+                    .then_some(tt_move)
+            }
+            _ => None,
+        };
+
+        let alpha_orig = alpha;
+        let mut best_move = None;
+        let mut best_score = -INFINITY;
+        let previous = board.last_move().copied();
+        for mv in moves {
+            self.make_move(board, mv);
+            let extension = self.extension(board, &mv, previous, singular_move, extended);
+            let (score, _) = self.negamax(
+                board,
+                depth - 1 + extension,
+                ply + 1,
+                -beta,
+                -alpha,
+                extended + extension,
+            );
+            let score = -score;
+            self.unmake(board);
+            if self.stopped() {
+                return (0, None);
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mv);
+                self.update_pv(ply, mv);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                self.update_heuristics(&mv, board.side_to_move, depth, ply);
+                break;
+            }
+        }
+
+        let bound = if best_score <= alpha_orig {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        if !excluding {
+            self.tt.store(TtEntry {
+                key,
+                depth,
+                score: score_to_tt(best_score, ply),
+                bound,
+                best_move: best_move.map(TtMove::from),
+            });
+        }
+
+        (best_score, best_move)
+    }
+
+    /// Returns how many plies `mv`, which has just been made on `board`, should be extended by.
+    fn extension(
+        &self,
+        board: &Board,
+        mv: &ChessMove,
+        previous: Option<ChessMove>,
+        singular_move: Option<TtMove>,
+        extended: u32,
+    ) -> u32 {
+        if extended >= self.extensions.max_per_line {
+            return 0;
+        }
+        let mover = board.side_to_move.opposite();
+
+        let gives_check = self.extensions.check && board.is_king_in_check(board.side_to_move);
+        let singular = singular_move.is_some_and(|singular| singular.matches(mv));
+        let recapture = self.extensions.recapture
+            && mv.captured_piece.is_some()
+            && previous.is_some_and(|prev| prev.captured_piece.is_some() && prev.to == mv.to);
+        // A pawn on the seventh rank has no squares left in front of it that an
+        // enemy pawn could guard, so it is always passed
+        let seventh_rank = match mover {
+            Color::White => 6,
+            Color::Black => 1,
+        };
+        let passed_pawn = self.extensions.passed_pawn
+            && mv.to / 8 == seventh_rank
+            && board.piece_at(mv.to, mover) == Some(PieceType::Pawn);
+
+        u32::from(gives_check || singular || recapture || passed_pawn)
+    }
+
+    /// Checks whether `tt_move` is the only good move in the position by searching every
+    /// other move at reduced depth against a window just below the table score.
+    #[allow(clippy::too_many_arguments)]
+    fn is_singular(
+        &mut self,
+        board: &mut Board,
+        moves: &[ChessMove],
+        tt_move: TtMove,
+        tt_score: i32,
+        depth: u32,
+        ply: u32,
+        extended: u32,
+    ) -> bool {
+        let singular_beta = tt_score - SINGULAR_MARGIN_PER_PLY * depth as i32;
+        let reduced_depth = (depth - 1) / 2;
+
+        for mv in moves.iter().filter(|mv| !tt_move.matches(mv)) {
+            self.make_move(board, *mv);
+            let (score, _) = self.negamax(
+                board,
+                reduced_depth,
+                ply + 1,
+                -singular_beta,
+                -singular_beta + 1,
+                extended,
+            );
+            self.unmake(board);
+            if -score >= singular_beta {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Orders the transposition table move first, followed by captures, the killer
+    /// moves of this ply and finally the quiet moves by their history.
+    fn order_moves(
+        &self,
+        moves: &mut [ChessMove],
+        tt_move: Option<TtMove>,
+        color: Color,
+        ply: u32,
+    ) {
+        let killers = self.killers.get(ply as usize).copied().unwrap_or([None; 2]);
+        moves.sort_by_key(|mv| {
+            let priority = if tt_move.is_some_and(|tt_move| tt_move.matches(mv)) {
+                i32::MAX
+            } else if mv.captured_piece.is_some() {
+                i32::MAX - 1
+            } else if killers[0].is_some_and(|killer| killer.matches(mv)) {
+                i32::MAX - 2
+            } else if killers[1].is_some_and(|killer| killer.matches(mv)) {
+                i32::MAX - 3
+            } else {
+                self.history[history_index(color, mv)]
+            };
+            Reverse(priority)
+        });
+    }
+
+    /// Remembers a quiet move that caused a beta cutoff.
+    fn update_heuristics(&mut self, mv: &ChessMove, color: Color, depth: u32, ply: u32) {
+        if mv.captured_piece.is_some() || mv.promoted_piece.is_some() {
+            return;
+        }
+        if let Some(killers) = self.killers.get_mut(ply as usize) {
+            if !killers[0].is_some_and(|killer| killer.matches(mv)) {
+                killers[1] = killers[0];
+                killers[0] = Some(TtMove::from(*mv));
+            }
+        }
+        let count = &mut self.history[history_index(color, mv)];
+        *count = count.saturating_add((depth * depth) as i32);
+    }
+
+    /// Looks for a forced mate for the side to move in at most `max_moves` moves.
+    /// Unlike `search` the result is a proof: every defence is tried and nothing is
+    /// left to the evaluation. Returns the score of the shortest mate and its first move.
+    pub fn mate_search(&mut self, board: &mut Board, max_moves: u32) -> Option<(Score, ChessMove)> {
+        let limits = Limits {
+            mate: Some(max_moves),
+            ..Limits::default()
+        };
+        let result = self.search_with_limits(board, &limits, |_| {});
+        match result.score {
+            Score::Mate(_) => Some((result.score, result.best_move()?)),
+            Score::Centipawns(_) => None,
+        }
+    }
+
+    /// The mate search of `search_with_limits`, trying the shortest mates first until
+    /// one is found or the search is stopped. Without a mate the first legal move is
+    /// kept with a score of 0, so that there is still a move to play.
+    fn find_mate(
+        &mut self,
+        board: &mut Board,
+        max_moves: u32,
+        info: &mut impl FnMut(&SearchResult),
+    ) -> ThreadResult {
+        // There is no iteration to complete first, the limits apply right away
+        self.has_result = true;
+        let mut moves = MoveGenerator::generate_legal_moves(board);
+        self.order_moves(&mut moves, None, board.side_to_move, 0);
+        let mut result = ThreadResult {
+            depth: 0,
+            lines: moves.first().map(|mv| (0, vec![*mv])).into_iter().collect(),
+        };
+        for moves in 1..=max_moves {
+            let plies = 2 * moves - 1;
+            if let Some(mv) = self.find_mating_move(board, plies) {
+                result = ThreadResult {
+                    depth: plies,
+                    lines: vec![(MATE_SCORE - plies as i32, vec![mv])],
+                };
+                if let Some(time) = &self.time {
+                    info(&result.to_search_result(self.nodes, time.elapsed()));
+                }
+                break;
+            }
+            if self.stopped() {
+                break;
+            }
+        }
+        self.wait_until_stopped();
+        result
+    }
+
+    /// Returns a move that mates within `plies` plies, `plies` being odd. Returns None
+    /// once the search is stopped.
+    fn find_mating_move(&mut self, board: &mut Board, plies: u32) -> Option<ChessMove> {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) {
+            self.check_time();
+        }
+        if self.stopped() {
+            return None;
+        }
+        let mut moves = MoveGenerator::generate_legal_moves(board);
+        self.order_moves(&mut moves, None, board.side_to_move, 0);
+        for mv in moves {
+            board.make_move(mv);
+            // The final move of a mate always gives check, in standard chess
+            let mates = (plies > 1
+                || board.variant != Variant::Standard
+                || board.is_king_in_check(board.side_to_move))
+                && self.is_mated_by_force(board, plies - 1);
+            board.unmake();
+            if mates {
+                return Some(mv);
+            }
+        }
+        None
+    }
+
+    /// Returns true if the side to move gets mated within `plies` plies whatever it plays.
+    /// Returns false once the search is stopped, nothing is proven then.
+    fn is_mated_by_force(&mut self, board: &mut Board, plies: u32) -> bool {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) {
+            self.check_time();
+        }
+        if self.stopped() {
+            return false;
+        }
+        let moves = MoveGenerator::generate_legal_moves(board);
+        if moves.is_empty() {
+            return board.outcome_without_moves() == Outcome::Win(board.side_to_move.opposite());
+        }
+        if plies == 0 || board.is_draw() {
+            return false;
+        }
+        for mv in moves {
+            board.make_move(mv);
+            let mated = self.find_mating_move(board, plies - 1).is_some();
+            board.unmake();
+            if !mated {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn history_index(color: Color, mv: &ChessMove) -> usize {
+    (color as usize * 64 + mv.from as usize) * 64 + mv.to as usize
+}
+
+/// The score of a finished game for the side to move, a win the sooner the better.
+fn outcome_score(outcome: Outcome, board: &Board, ply: u32) -> i32 {
+    match outcome {
+        Outcome::Win(winner) if winner == board.side_to_move => MATE_SCORE - ply as i32,
+        Outcome::Win(_) => -MATE_SCORE + ply as i32,
+        Outcome::Draw => 0,
+    }
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self::new(Extensions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_extension_finds_mate_beyond_horizon() {
+        // Re8 is a back rank mate, but at depth one the mate is only seen when the
+        // check is extended so the reply search finds no legal moves
+        let fen = "6k1/5ppp/8/8/8/8/5PPP/4RRK1 w - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let (score, mv) = Search::default().negamax(&mut board, 1, 0, -INFINITY, INFINITY, 0);
+        assert!(score > MATE_THRESHOLD, "score was {}", score);
+        let mv = mv.unwrap();
+        assert_eq!(mv.to_standard_notation(), "e1e8");
+
+        let mut board = Board::from_fen(fen).unwrap();
+        let (score, _) =
+            Search::new(Extensions::none()).negamax(&mut board, 1, 0, -INFINITY, INFINITY, 0);
+        assert!(score < MATE_THRESHOLD);
+    }
+
+    #[test]
+    fn test_search_reports_mate() {
+        let fen = "6k1/5ppp/8/8/8/8/5PPP/4RRK1 w - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let result = Search::default().search(&mut board, 3);
+        assert_eq!(result.score, Score::Mate(1));
+
+        // Seen from the side getting mated: 1... Kg8 2. Ra8#
+        let fen = "7k/8/6K1/8/8/8/8/R7 b - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let score = Search::default().search(&mut board, 4).score;
+        assert_eq!(score, Score::Mate(-1));
+    }
+
+    #[test]
+    fn test_mate_search() {
+        // Morphy's mate in two: 1. Ra6 bxa6 2. b7#
+        let fen = "kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut search = Search::default();
+        assert!(search.mate_search(&mut board, 1).is_none());
+        let (score, mv) = search.mate_search(&mut board, 3).unwrap();
+        assert_eq!(score, Score::Mate(2));
+        assert_eq!(mv.to_standard_notation(), "a1a6");
+        assert_eq!(board.to_fen(), fen);
+    }
+
+    #[test]
+    fn test_mate_search_keeps_to_its_limits() {
+        // No mate can be proven from the starting position in time, the search still
+        // returns a move
+        let mut board = Board::from_fen(chess::board::STARTING_FEN).unwrap();
+        for limits in [
+            Limits {
+                move_time: Some(Duration::from_millis(200)),
+                ..Limits::default()
+            },
+            Limits {
+                nodes: Some(10_000),
+                ..Limits::default()
+            },
+        ] {
+            let limits = Limits {
+                mate: Some(20),
+                ..limits
+            };
+            let result = Search::default().search_with_limits(&mut board, &limits, |_| {});
+            assert_eq!(result.score, Score::Centipawns(0));
+            assert!(result.best_move().is_some());
+            assert!(result.time < Duration::from_secs(5));
+        }
+    }
+
+    #[test]
+    fn test_variant_wins() {
+        // The third check wins
+        let fen = "4k3/8/8/8/8/8/8/3QK3 w - - 1+3 0 1";
+        let mut board = Board::from_variant_fen(fen, Variant::ThreeCheck).unwrap();
+        let result = Search::default().search(&mut board, 2);
+        assert_eq!(result.score, Score::Mate(1));
+
+        // exd5 is forced and leaves White without pieces, which wins for Black
+        let fen = "8/8/8/3p4/4P3/8/8/8 w - - 0 1";
+        let mut board = Board::from_variant_fen(fen, Variant::Antichess).unwrap();
+        let result = Search::default().search(&mut board, 2);
+        assert_eq!(result.score, Score::Mate(-1));
+
+        let fen = "4k3/8/8/8/8/4K3/8/8 w - - 0 1";
+        let mut board = Board::from_variant_fen(fen, Variant::KingOfTheHill).unwrap();
+        let (score, _) = Search::default().mate_search(&mut board, 1).unwrap();
+        assert_eq!(score, Score::Mate(1));
+
+        // A rook dropped on the back rank mates
+        let fen = "6k1/5ppp/8/8/8/8/8/4K3[R] w - - 0 1";
+        let mut board = Board::from_variant_fen(fen, Variant::Crazyhouse).unwrap();
+        let result = Search::default().search(&mut board, 2);
+        assert_eq!(result.score, Score::Mate(1));
+        let mv = result.best_move().unwrap();
+        assert_eq!(mv.dropped_piece, Some(PieceType::Rook));
+        assert_eq!(board.to_fen(), fen);
+
+        // Qxe7 blows up the king, which is scored at the horizon without evaluating
+        let fen = "4k3/4p3/8/8/8/8/8/4QK2 w - - 0 1";
+        let mut board = Board::from_variant_fen(fen, Variant::Atomic).unwrap();
+        let result = Search::default().search(&mut board, 1);
+        assert_eq!(result.score, Score::Mate(1));
+        assert_eq!(result.best_move().unwrap().to_standard_notation(), "e1e7");
+    }
+
+    #[test]
+    fn test_extension_budget_is_respected() {
+        let extensions = Extensions {
+            max_per_line: 0,
+            ..Extensions::default()
+        };
+        let fen = "6k1/5ppp/8/8/8/8/5PPP/4RRK1 w - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let (score, _) = Search::new(extensions).negamax(&mut board, 1, 0, -INFINITY, INFINITY, 0);
+        assert!(score < MATE_THRESHOLD);
+    }
+
+    #[test]
+    fn test_search_restores_board() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let mut board = Board::from_fen(fen).unwrap();
+        let result = Search::default().search(&mut board, 3);
+        assert!(result.best_move().is_some());
+        assert_eq!(board.to_fen(), fen);
+    }
+
+    #[test]
+    fn test_search_returns_pv_and_ponder_move() {
+        // 1. Ra6 bxa6 2. b7#, every other defence is mated a move earlier
+        let fen = "kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let result = Search::default().search(&mut board, 4);
+        let pv: Vec<String> = result
+            .pv
+            .iter()
+            .map(|mv| mv.to_standard_notation())
+            .collect();
+        assert_eq!(pv, ["a1a6", "b7a6", "b6b7"]);
+        assert_eq!(result.ponder_move().unwrap().to_standard_notation(), "b7a6");
+    }
+
+    #[test]
+    fn test_multi_pv_lines_are_sorted() {
+        // The mate in one is the best line, and the three lines are sorted from the
+        // point of view of the side to move
+        let fen = "3r2k1/8/8/8/8/8/5PPP/6K1 b - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut search = Search::default();
+        search.set_multi_pv(3);
+        let result = search.search(&mut board, 3);
+        assert_eq!(result.lines.len(), 3);
+        assert_eq!(result.lines[0].pv[0].to_standard_notation(), "d8d1");
+        assert_eq!(result.score, Score::Mate(1));
+        assert!(result
+            .best_move()
+            .unwrap()
+            .is_same_move(&result.lines[0].pv[0]));
+        let centipawns = |score: Score| match score {
+            Score::Centipawns(cp) => cp,
+            Score::Mate(_) => panic!("unexpected mate score"),
+        };
+        assert!(centipawns(result.lines[1].score) >= centipawns(result.lines[2].score));
+
+        let first_moves: Vec<String> = result
+            .lines
+            .iter()
+            .map(|line| line.pv[0].to_standard_notation())
+            .collect();
+        assert!(first_moves[1] != first_moves[0] && first_moves[2] != first_moves[1]);
+        assert_eq!(board.to_fen(), fen);
+    }
+
+    #[test]
+    fn test_multi_pv_is_limited_by_legal_moves() {
+        let fen = "7k/8/6K1/8/8/8/8/R7 b - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut search = Search::default();
+        search.set_multi_pv(5);
+        let result = search.search(&mut board, 2);
+        assert_eq!(result.lines.len(), 1);
+        assert_eq!(result.pv[0].to_standard_notation(), "h8g8");
+    }
+
+    #[test]
+    fn test_ponder_search_waits_for_ponderhit() {
+        let mut board = Board::from_fen(chess::board::STARTING_FEN).unwrap();
+        let mut search = Search::default();
+        let control = search.control();
+        let limits = Limits {
+            depth: Some(2),
+            ponder: true,
+            ..Limits::default()
+        };
+        let handle = thread::spawn(move || search.search_with_limits(&mut board, &limits, |_| {}));
+        thread::sleep(Duration::from_millis(200));
+        assert!(!handle.is_finished());
+        control.ponderhit();
+        let result = handle.join().unwrap();
+        assert_eq!(result.depth, 2);
+        assert!(result.best_move().is_some());
+    }
+
+    #[test]
+    fn test_search_respects_move_time() {
+        let mut board = Board::from_fen(chess::board::STARTING_FEN).unwrap();
+        let limits = Limits {
+            move_time: Some(Duration::from_millis(200)),
+            ..Limits::default()
+        };
+        let mut iterations = 0;
+        let result = Search::default().search_with_limits(&mut board, &limits, |_| iterations += 1);
+        assert!(
+            result.time < Duration::from_secs(2),
+            "took {:?}",
+            result.time
+        );
+        assert!(result.best_move().is_some());
+        assert_eq!(iterations, result.depth);
+    }
+
+    #[test]
+    fn test_search_respects_node_limit() {
+        let mut board = Board::from_fen(chess::board::STARTING_FEN).unwrap();
+        let limits = Limits {
+            nodes: Some(5000),
+            ..Limits::default()
+        };
+        let result = Search::default().search_with_limits(&mut board, &limits, |_| {});
+        assert!(result.best_move().is_some());
+        assert!(
+            result.nodes < 5000 + TIME_CHECK_INTERVAL,
+            "{}",
+            result.nodes
+        );
+    }
+
+    #[test]
+    fn test_search_plays_the_tablebase_win() {
+        let Some(tablebase) = syzygy::tests::fixtures() else {
+            return;
+        };
+        let mut board = Board::from_fen("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap();
+        let mut search = Search::default();
+        search.set_tablebase(Some(Arc::new(tablebase)));
+        let result = search.search(&mut board, 4);
+        let best_move = result.best_move().unwrap().to_standard_notation();
+        assert!(
+            ["e6d6", "e6f6"].contains(&best_move.as_str()),
+            "{}",
+            best_move
+        );
+        assert!(matches!(result.score, Score::Centipawns(score) if score > syzygy::TB_WIN - 10));
+    }
+}
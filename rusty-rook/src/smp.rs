@@ -0,0 +1,99 @@
+//! Lazy SMP: every thread runs its own iterative deepening search of the same
+//! position, and the threads cooperate only through the shared transposition table.
+//! Helper threads skip some depths so that they work ahead of the main thread and
+//! fill the table with results the main thread can use.
+
+use std::thread;
+
+use chess::board::Board;
+
+use crate::search::{Search, SearchResult, ThreadResult};
+
+/// Depth skipping pattern for the helper threads, taken from the tables Stockfish
+/// used for its Lazy SMP: helper `i` skips depths in blocks of `SKIP_SIZE[i]`,
+/// offset by `SKIP_PHASE[i]`.
+const SKIP_SIZE: [u32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+/// Returns true if helper thread `id` should skip the iteration at `depth`.
+pub(crate) fn skip_depth(id: usize, depth: u32) -> bool {
+    let index = (id - 1) % SKIP_SIZE.len();
+    ((depth + SKIP_PHASE[index]) / SKIP_SIZE[index]) % 2 == 1
+}
+
+/// Searches `board` with `main.threads()` threads and returns the best result.
+/// The search ends when the main thread has finished its last iteration, at which
+/// point the helpers are stopped and their unfinished iterations discarded.
+/// Only the main thread reports its iterations to `info`.
+pub(crate) fn search(
+    main: &mut Search,
+    board: &mut Board,
+    depth: u32,
+    info: &mut dyn FnMut(&SearchResult),
+) -> ThreadResult {
+    let helpers: Vec<Search> = (1..main.threads()).map(|id| main.helper(id)).collect();
+
+    let (main_result, helper_results) = thread::scope(|scope| {
+        let handles: Vec<_> = helpers
+            .into_iter()
+            .map(|mut helper| {
+                let mut board = board.clone();
+                scope.spawn(move || {
+                    let result = helper.iterative_deepening(&mut board, depth, None);
+                    (result, helper.nodes)
+                })
+            })
+            .collect();
+
+        let main_result = main.iterative_deepening(board, depth, Some(info));
+        main.stop();
+
+        let helper_results: Vec<(ThreadResult, u64)> = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("Search thread panicked"))
+            .collect();
+        (main_result, helper_results)
+    });
+
+    let mut best = main_result;
+    for (result, nodes) in helper_results {
+        main.nodes += nodes;
+        if is_better(&result, &best) {
+            best = result;
+        }
+    }
+    best
+}
+
+/// Prefers the deeper result, and the better score between equal depths.
+fn is_better(result: &ThreadResult, best: &ThreadResult) -> bool {
+    if !result.has_move() {
+        return false;
+    }
+    result.depth > best.depth || (result.depth == best.depth && result.score() > best.score())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::score::Score;
+
+    #[test]
+    fn test_helpers_skip_different_depths() {
+        let skipped = |id| (1..=8).filter(|&depth| skip_depth(id, depth)).count();
+        assert!((1..8).all(|id| skipped(id) > 0 && skipped(id) < 8));
+        assert!((1..=8).any(|depth| skip_depth(1, depth) != skip_depth(2, depth)));
+    }
+
+    #[test]
+    fn test_multithreaded_search_finds_mate() {
+        let fen = "kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut search = Search::default();
+        search.set_threads(4);
+        let result = search.search(&mut board, 4);
+        assert_eq!(result.score, Score::Mate(2));
+        assert_eq!(result.best_move().unwrap().to_standard_notation(), "a1a6");
+        assert_eq!(board.to_fen(), fen);
+    }
+}
@@ -0,0 +1,1304 @@
+//! Endgame tablebases in the Syzygy format: win/draw/loss (WDL) tables probed during
+//! the search and distance-to-zero (DTZ) tables that pick the root moves converting a
+//! won position under the fifty-move rule.
+//!
+//! The search talks to a `Tablebase`. `SyzygyTablebase` finds the `.rtbw` and `.rtbz`
+//! files of a `SyzygyPath` and reads a file the first time a position of its material
+//! is probed. A table maps the placement of the pieces, up to the symmetries of the
+//! board, to an index, and stores the value of every index in blocks of Huffman codes
+//! whose symbols each expand to a run of values. The tables hold no useful value where
+//! the best move is a capture, or for DTZ a pawn move, so probes search those first.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{LazyLock, OnceLock},
+};
+
+use chess::{
+    board::Board,
+    chess_move::{ChessMove, FLAG_EN_PASSANT},
+    move_generator::MoveGenerator,
+    piece::{Color, PieceType},
+    variant::Variant,
+};
+
+/// The score of a tablebase win, below the mate scores and above every evaluation.
+pub const TB_WIN: i32 = 20_000;
+
+/// The first bytes of a WDL and a DTZ table file.
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+/// The outcome of a position for the side to move. Cursed wins and blessed losses
+/// would be wins and losses without the fifty-move rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    pub fn opposite(self) -> Self {
+        match self {
+            Wdl::Loss => Wdl::Win,
+            Wdl::BlessedLoss => Wdl::CursedWin,
+            Wdl::Draw => Wdl::Draw,
+            Wdl::CursedWin => Wdl::BlessedLoss,
+            Wdl::Win => Wdl::Loss,
+        }
+    }
+
+    /// The search score of the outcome `ply` plies from the root. Cursed wins and
+    /// blessed losses are draws, kept apart from them by a single centipawn.
+    pub fn score(self, ply: u32) -> i32 {
+        match self {
+            Wdl::Loss => -TB_WIN + ply as i32,
+            Wdl::BlessedLoss => -1,
+            Wdl::Draw => 0,
+            Wdl::CursedWin => 1,
+            Wdl::Win => TB_WIN - ply as i32,
+        }
+    }
+
+    /// The outcome given the distance to the next capture or pawn move `dtz`, from a
+    /// DTZ table, and the half moves already played towards the fifty-move rule. A
+    /// win or loss that takes too long to zero the clock becomes a draw.
+    pub fn from_dtz(dtz: i32, half_move_clock: u32) -> Self {
+        let too_slow = dtz.unsigned_abs() + half_move_clock > 100;
+        match dtz {
+            0 => Wdl::Draw,
+            _ if dtz > 0 && too_slow => Wdl::CursedWin,
+            _ if dtz > 0 => Wdl::Win,
+            _ if too_slow => Wdl::BlessedLoss,
+            _ => Wdl::Loss,
+        }
+    }
+
+    /// The outcome stored in a WDL table, 0 for a loss to 4 for a win.
+    fn from_stored(value: u32) -> Option<Self> {
+        [
+            Wdl::Loss,
+            Wdl::BlessedLoss,
+            Wdl::Draw,
+            Wdl::CursedWin,
+            Wdl::Win,
+        ]
+        .get(value as usize)
+        .copied()
+    }
+
+    /// The DTZ of a position whose best move resets the fifty-move counter: the move
+    /// itself, or a hundred plies more for a cursed win or blessed loss.
+    fn zeroing_dtz(self) -> i32 {
+        match self {
+            Wdl::Loss => -1,
+            Wdl::BlessedLoss => -101,
+            Wdl::Draw => 0,
+            Wdl::CursedWin => 101,
+            Wdl::Win => 1,
+        }
+    }
+}
+
+/// A source of endgame tablebase results.
+pub trait Tablebase: Send + Sync {
+    /// The most pieces, kings included, of the positions it has results for.
+    fn max_pieces(&self) -> u32;
+
+    /// The outcome for the side to move, assuming the half move clock is zero.
+    fn probe_wdl(&self, board: &Board) -> Option<Wdl>;
+
+    /// The number of half moves to the next capture or pawn move with best play,
+    /// positive when the side to move wins and negative when it loses, 0 for a draw.
+    fn probe_dtz(&self, board: &Board) -> Option<i32>;
+
+    /// Returns true if the tables can have a result for `board`. Tables know nothing
+    /// of castling or variants.
+    fn covers(&self, board: &Board) -> bool {
+        board.variant == Variant::Standard
+            && board.combined.popcnt() <= self.max_pieces()
+            && !board.castling_rights.contains(&true)
+    }
+}
+
+/// Returns the root moves that keep the best outcome according to the DTZ tables, or
+/// None when the tables have no result for the position or one of its moves. Winning
+/// moves are narrowed down to the fastest conversions, so that the search cannot
+/// shuffle in a won position until the fifty-move rule draws it.
+pub fn filter_root_moves(tablebase: &dyn Tablebase, board: &mut Board) -> Option<Vec<ChessMove>> {
+    if !tablebase.covers(board) {
+        return None;
+    }
+    let mut ranked = Vec::new();
+    for mv in MoveGenerator::generate_legal_moves(board) {
+        let zeroing = zeroes(board, &mv);
+        board.make_move(mv);
+        let dtz = tablebase.probe_dtz(board);
+        let half_move_clock = board.half_move_clock;
+        board.unmake();
+        // The outcome after the move is the opponent's, the other way round
+        let dtz = -dtz?;
+        let wdl = Wdl::from_dtz(dtz, half_move_clock);
+        // A capture or pawn move converts at once, another move a ply before the
+        // position it reaches does
+        let plies = if zeroing { 1 } else { dtz.abs() + 1 };
+        // Win as fast as possible and lose as slowly as possible
+        let rank = match wdl {
+            Wdl::Win => (wdl, -plies),
+            Wdl::Loss => (wdl, plies),
+            _ => (wdl, 0),
+        };
+        ranked.push((rank, mv));
+    }
+    let best = ranked.iter().map(|(rank, _)| *rank).max()?;
+    Some(
+        ranked
+            .into_iter()
+            .filter(|(rank, _)| *rank == best)
+            .map(|(_, mv)| mv)
+            .collect(),
+    )
+}
+
+/// Returns true if `mv` captures a piece.
+fn captures(board: &Board, mv: &ChessMove) -> bool {
+    mv.flags & FLAG_EN_PASSANT != 0
+        || board
+            .piece_at(mv.to, board.side_to_move.opposite())
+            .is_some()
+}
+
+/// Returns true if `mv` resets the fifty-move counter, as captures and pawn moves do.
+fn zeroes(board: &Board, mv: &ChessMove) -> bool {
+    captures(board, mv) || board.piece_at(mv.from, board.side_to_move) == Some(PieceType::Pawn)
+}
+
+/// The name of the table for the material of `board` with `first` named first, as in
+/// `KRPvKR`.
+pub fn table_name(board: &Board, first: Color) -> String {
+    let side = |color: Color| {
+        let mut name = String::from("K");
+        for (piece, letter) in [
+            (PieceType::Queen, 'Q'),
+            (PieceType::Rook, 'R'),
+            (PieceType::Bishop, 'B'),
+            (PieceType::Knight, 'N'),
+            (PieceType::Pawn, 'P'),
+        ] {
+            let count = board.bitboards[color as usize][piece as usize].popcnt();
+            name.extend(std::iter::repeat_n(letter, count as usize));
+        }
+        name
+    };
+    format!("{}v{}", side(first), side(first.opposite()))
+}
+
+/// Flags of a subtable: the side to move stored by a DTZ table, whether its values
+/// go through a map of 8-bit or 16-bit distances, whether wins and losses count plies
+/// rather than moves, and whether every position has the same value.
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+/// The lookup tables that turn the squares of the pieces into a table index.
+struct Indexing {
+    /// `binomial[k][n]` is n choose k, the placements of k like pieces on n squares.
+    binomial: [[u64; 64]; 7],
+    /// The squares a2 to h7 numbered from 47 down, in the order a2 h2 a3 h3 ... b2
+    /// g2 ..., so that the leading pawn, nearest the edge and lowest, has the highest.
+    map_pawns: [u64; 64],
+    /// The index of the leading pawns, by their count and the square of the first.
+    lead_pawn_idx: [[u64; 64]; 6],
+    /// The number of placements of the leading pawns with the first on files a to d.
+    lead_pawns_size: [[u64; 4]; 6],
+    /// The squares below the a1-h8 diagonal, numbered 0 to 27.
+    map_b1h1h7: [u64; 64],
+    /// The squares of the a1-d1-d4 triangle, numbered 0 to 9 with the diagonal last.
+    map_a1d1d4: [u64; 64],
+    /// The 462 placements of two kings with the first in the triangle and the second
+    /// not above the diagonal when the first is on it.
+    map_kk: [[u64; 64]; 10],
+}
+
+static INDEXING: LazyLock<Indexing> = LazyLock::new(Indexing::new);
+
+/// How far `square` is above the a1-h8 diagonal, negative below it.
+fn off_diagonal(square: u8) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+impl Indexing {
+    fn new() -> Self {
+        let mut binomial = [[0; 64]; 7];
+        binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..7.min(n + 1) {
+                binomial[k][n] = if k > 0 { binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        let mut map_b1h1h7 = [0; 64];
+        for (code, square) in (0..64)
+            .filter(|&square| off_diagonal(square) < 0)
+            .enumerate()
+        {
+            map_b1h1h7[square as usize] = code as u64;
+        }
+
+        let below = (0..28).filter(|&square| square % 8 <= 3 && off_diagonal(square) < 0);
+        let triangle: Vec<u8> = below.chain([0, 9, 18, 27]).collect();
+        let mut map_a1d1d4 = [0; 64];
+        for (code, &square) in triangle.iter().enumerate() {
+            map_a1d1d4[square as usize] = code as u64;
+        }
+
+        let mut map_kk = [[0; 64]; 10];
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for (index, &first) in triangle.iter().enumerate() {
+            let touching = MoveGenerator::king_attacks(first) | 1 << first;
+            for second in (0..64).filter(|&square| touching & 1 << square == 0) {
+                match (off_diagonal(first), off_diagonal(second)) {
+                    (0, above) if above > 0 => {}
+                    (0, 0) => both_on_diagonal.push((index, second)),
+                    _ => {
+                        map_kk[index][second as usize] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal {
+            map_kk[index][second as usize] = code;
+            code += 1;
+        }
+
+        let mut map_pawns = [0; 64];
+        let mut lead_pawn_idx = [[0; 64]; 6];
+        let mut lead_pawns_size = [[0; 4]; 6];
+        for count in 1..6 {
+            for (file, size) in lead_pawns_size[count].iter_mut().enumerate() {
+                let mut index = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if count == 1 {
+                        let taken = 2 * (file * 6 + rank - 1) as u64;
+                        map_pawns[square] = 47 - taken;
+                        map_pawns[square ^ 7] = 46 - taken;
+                    }
+                    lead_pawn_idx[count][square] = index;
+                    index += binomial[count - 1][map_pawns[square] as usize];
+                }
+                *size = index;
+            }
+        }
+
+        Indexing {
+            binomial,
+            map_pawns,
+            lead_pawn_idx,
+            lead_pawns_size,
+            map_b1h1h7,
+            map_a1d1d4,
+            map_kk,
+        }
+    }
+
+    /// The index of three unique pieces, the first in the a1-d1-d4 triangle and the
+    /// first of them off the diagonal below it.
+    fn unique_index(&self, squares: &[u8]) -> u64 {
+        let [first, second, third] = [squares[0], squares[1], squares[2]].map(u64::from);
+        let adjust1 = (second > first) as u64;
+        let adjust2 = (third > first) as u64 + (third > second) as u64;
+        let rank = |square: u64| square / 8;
+        if off_diagonal(squares[0]) != 0 {
+            (self.map_a1d1d4[first as usize] * 63 + second - adjust1) * 62 + third - adjust2
+        } else if off_diagonal(squares[1]) != 0 {
+            (6 * 63 + rank(first) * 28 + self.map_b1h1h7[second as usize]) * 62 + third - adjust2
+        } else if off_diagonal(squares[2]) != 0 {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + rank(first) * 7 * 28
+                + (rank(second) - adjust1) * 28
+                + self.map_b1h1h7[third as usize]
+        } else {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + 4 * 7 * 28
+                + rank(first) * 7 * 6
+                + (rank(second) - adjust1) * 6
+                + rank(third)
+                - adjust2
+        }
+    }
+}
+
+/// The little-endian number of `bytes` bytes at `at`, or None past the end.
+fn little_endian(data: &[u8], at: usize, bytes: usize) -> Option<u64> {
+    let bytes = data.get(at..at.checked_add(bytes)?)?;
+    Some(
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |number, &byte| number << 8 | u64::from(byte)),
+    )
+}
+
+/// The big-endian number of `bytes` bytes at `at`, reading zeros past the end: the
+/// last block of codes may end close to the end of the file.
+fn big_endian(data: &[u8], at: usize, bytes: usize) -> u64 {
+    (at..at + bytes).fold(0, |number, i| {
+        number << 8 | u64::from(data.get(i).copied().unwrap_or(0))
+    })
+}
+
+/// Reads the header of a table file from the front.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn number(&mut self, bytes: usize) -> Result<u64, String> {
+        let number = little_endian(self.data, self.position, bytes)
+            .ok_or_else(|| "Truncated Syzygy table".to_string())?;
+        self.position += bytes;
+        Ok(number)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        self.number(1).map(|byte| byte as u8)
+    }
+
+    fn skip(&mut self, bytes: usize) {
+        self.position = self.position.saturating_add(bytes);
+    }
+
+    fn align(&mut self, bytes: usize) {
+        self.position = self.position.next_multiple_of(bytes);
+    }
+}
+
+/// The positions of one side to move, and with pawns one file of the leading pawn:
+/// how their pieces make an index and where the compressed values are.
+#[derive(Debug, Default, Clone)]
+struct Subtable {
+    flags: u8,
+    /// The pieces in the order they are indexed, 1 to 6 for a white pawn to king and
+    /// 9 to 14 for Black's.
+    pieces: Vec<u8>,
+    /// The number of pieces of each group indexed together, and the factor of each
+    /// group's index, with the number of indices last.
+    group_len: Vec<usize>,
+    group_idx: Vec<u64>,
+    block_size: usize,
+    span: u64,
+    sparse_index_size: usize,
+    blocks: usize,
+    block_length_size: usize,
+    /// The length of the shortest code, or the value of a single-valued subtable.
+    min_sym_len: u32,
+    /// The offset of the lowest symbol of each code length.
+    lowest_sym: usize,
+    /// The lowest code of each length, left-aligned, longer codes being lower.
+    base64: Vec<u64>,
+    /// The number of values each symbol expands to, less one.
+    symlen: Vec<u32>,
+    /// The offsets of the symbol pairs, the sparse index, the block lengths and the
+    /// blocks of codes in the file.
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    /// The offsets of the DTZ maps of wins, losses, cursed wins and blessed losses.
+    map_idx: [usize; 4],
+}
+
+impl Subtable {
+    /// Splits the pieces into groups and orders the groups by `order`, the position
+    /// of the leading group and of the other side's pawns.
+    fn set_groups(&mut self, table: &Table, order: [u8; 2], file: usize) {
+        let indexing = &*INDEXING;
+        let mut first_len = match (table.has_pawns, table.unique) {
+            (true, _) => 0,
+            (false, true) => 3,
+            (false, false) => 2,
+        };
+        self.group_len = vec![1];
+        for i in 1..self.pieces.len() {
+            first_len -= 1;
+            if first_len > 0 || self.pieces[i] == self.pieces[i - 1] {
+                *self.group_len.last_mut().unwrap() += 1;
+            } else {
+                self.group_len.push(1);
+            }
+        }
+
+        let groups = self.group_len.len();
+        self.group_idx = vec![0; groups + 1];
+        let mut next = if table.both_pawns { 2 } else { 1 };
+        let mut free = 64 - self.group_len[0];
+        if table.both_pawns {
+            free -= self.group_len[1];
+        }
+        let mut index = 1u64;
+        let mut k = 0;
+        while next < groups || k == order[0] || k == order[1] {
+            if k == order[0] {
+                self.group_idx[0] = index;
+                index *= match (table.has_pawns, table.unique) {
+                    (true, _) => indexing.lead_pawns_size[self.group_len[0]][file],
+                    (false, true) => 31332,
+                    (false, false) => 462,
+                };
+            } else if k == order[1] {
+                self.group_idx[1] = index;
+                index *= indexing.binomial[self.group_len[1]][48 - self.group_len[0]];
+            } else {
+                self.group_idx[next] = index;
+                index *= indexing.binomial[self.group_len[next]][free];
+                free -= self.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        self.group_idx[groups] = index;
+    }
+
+    /// Reads the parameters of the compression, and finds the number of values each
+    /// symbol expands to.
+    fn set_sizes(&mut self, reader: &mut Reader) -> Result<(), String> {
+        let corrupt = || "Corrupt Syzygy table".to_string();
+        self.flags = reader.byte()?;
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            self.min_sym_len = reader.byte()?.into();
+            return Ok(());
+        }
+
+        let size = *self.group_idx.last().ok_or_else(corrupt)?;
+        self.block_size = 1usize
+            .checked_shl(reader.byte()?.into())
+            .ok_or_else(corrupt)?;
+        self.span = 1u64
+            .checked_shl(reader.byte()?.into())
+            .ok_or_else(corrupt)?;
+        self.sparse_index_size = size.div_ceil(self.span) as usize;
+        let padding = reader.byte()? as usize;
+        self.blocks = reader.number(4)? as usize;
+        self.block_length_size = self.blocks + padding;
+        let max_sym_len: u32 = reader.byte()?.into();
+        self.min_sym_len = reader.byte()?.into();
+        if self.min_sym_len == 0 || max_sym_len < self.min_sym_len || max_sym_len > 32 {
+            return Err(corrupt());
+        }
+
+        self.lowest_sym = reader.position;
+        let lengths = (max_sym_len - self.min_sym_len + 1) as usize;
+        let lowest = (0..lengths)
+            .map(|_| reader.number(2))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Codes are canonical: the codes of one length are consecutive, after the
+        // longer ones, so the lowest code of each length follows from the number of
+        // symbols of the next longer length
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            self.base64[i] = (self.base64[i + 1] + lowest[i]).wrapping_sub(lowest[i + 1]) / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base <<= 64 - i as u32 - self.min_sym_len;
+        }
+
+        let symbols = reader.number(2)? as usize;
+        self.btree = reader.position;
+        reader.skip(3 * symbols + (symbols & 1));
+        if reader.position > reader.data.len() {
+            return Err(corrupt());
+        }
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                self.symlen[symbol] = self.set_symlen(reader.data, symbol, &mut visited)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of values `symbol` expands to less one: a symbol is a value or a
+    /// pair of symbols, which may be pairs in turn.
+    fn set_symlen(
+        &mut self,
+        data: &[u8],
+        symbol: usize,
+        visited: &mut [bool],
+    ) -> Result<u32, String> {
+        visited[symbol] = true;
+        let (left, right) = self
+            .children(data, symbol)
+            .ok_or_else(|| "Corrupt Syzygy table".to_string())?;
+        if right == 0xfff {
+            return Ok(0);
+        }
+        for child in [left, right] {
+            if child >= self.symlen.len() {
+                return Err("Corrupt Syzygy table".to_string());
+            }
+            if !visited[child] {
+                self.symlen[child] = self.set_symlen(data, child, visited)?;
+            }
+        }
+        Ok(self.symlen[left] + self.symlen[right] + 1)
+    }
+
+    /// The two symbols that `symbol` expands to, 12 bits each. A value has itself on
+    /// the left and 0xfff on the right.
+    fn children(&self, data: &[u8], symbol: usize) -> Option<(usize, usize)> {
+        let bytes = little_endian(data, self.btree + 3 * symbol, 3)? as usize;
+        Some((bytes & 0xfff, bytes >> 12))
+    }
+
+    /// The value at `index`. The sparse index gives the block holding a value near
+    /// `index`, the block lengths the block holding `index` itself, whose codes are
+    /// read until the symbol that expands to it.
+    fn value(&self, data: &[u8], index: u64) -> Option<u32> {
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(self.min_sym_len);
+        }
+
+        let entry = self.sparse_index + 6 * (index / self.span) as usize;
+        let mut block = little_endian(data, entry, 4)? as usize;
+        let mut offset = little_endian(data, entry + 4, 2)? as i64 + (index % self.span) as i64
+            - (self.span / 2) as i64;
+        let block_length = |block: usize| {
+            (block < self.block_length_size)
+                .then(|| little_endian(data, self.block_length + 2 * block, 2))
+                .flatten()
+                .map(|length| length as i64 + 1)
+        };
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)?;
+        }
+        while offset >= block_length(block)? {
+            offset -= block_length(block)?;
+            block += 1;
+        }
+
+        let lowest_sym = |length: usize| little_endian(data, self.lowest_sym + 2 * length, 2);
+        let mut position = self.data + block * self.block_size;
+        let mut buffer = big_endian(data, position, 8);
+        let mut buffered = 64;
+        position += 8;
+        let mut symbol = loop {
+            let mut length = 0;
+            while buffer < self.base64[length] {
+                length += 1;
+            }
+            let code = (buffer - self.base64[length]) >> (64 - length as u32 - self.min_sym_len);
+            let symbol = (code + lowest_sym(length)?) as usize;
+            let expands_to = *self.symlen.get(symbol)? as i64 + 1;
+            if offset < expands_to {
+                break symbol;
+            }
+            offset -= expands_to;
+            let bits = length as u32 + self.min_sym_len;
+            buffer <<= bits;
+            buffered -= bits;
+            if buffered <= 32 {
+                buffered += 32;
+                buffer |= big_endian(data, position, 4) << (64 - buffered);
+                position += 4;
+            }
+        };
+
+        while self.symlen[symbol] != 0 {
+            let (left, right) = self.children(data, symbol)?;
+            let left_expands_to = *self.symlen.get(left)? as i64 + 1;
+            if offset < left_expands_to {
+                symbol = left;
+            } else {
+                offset -= left_expands_to;
+                symbol = right;
+            }
+            self.symlen.get(symbol)?;
+        }
+        self.children(data, symbol).map(|(value, _)| value as u32)
+    }
+}
+
+/// What a table holds for a position.
+enum Entry {
+    /// The stored value, found in the subtable of `file`.
+    Value { file: usize, value: u32 },
+    /// The DTZ table only stores the positions with the other side to move.
+    OtherSide,
+}
+
+/// A table file read into memory with the layout of its subtables.
+#[derive(Debug)]
+struct Table {
+    data: Vec<u8>,
+    has_pawns: bool,
+    both_pawns: bool,
+    symmetric: bool,
+    /// Whether one side has a single piece besides the king, so that three pieces
+    /// lead the index instead of the two kings.
+    unique: bool,
+    /// The subtables by the file of the leading pawn, a to d, then by the side to
+    /// move. Tables without pawns have one file, DTZ and symmetric tables one side.
+    subtables: Vec<Vec<Subtable>>,
+}
+
+impl Table {
+    /// Reads the table of the material `name`, such as `KRPvKR`, from the bytes of
+    /// its WDL or DTZ file.
+    fn parse(data: Vec<u8>, name: &str, wdl: bool) -> Result<Self, String> {
+        let corrupt = || format!("Corrupt Syzygy table {}", name);
+        let (white, black) = name.split_once('v').ok_or_else(corrupt)?;
+        let mut table = Table {
+            data: Vec::new(),
+            has_pawns: name.contains('P'),
+            both_pawns: white.contains('P') && black.contains('P'),
+            symmetric: white == black,
+            unique: [white, black].iter().any(|side| {
+                "QRBNP"
+                    .chars()
+                    .any(|piece| side.matches(piece).count() == 1)
+            }),
+            subtables: Vec::new(),
+        };
+        let sides = if wdl && !table.symmetric { 2 } else { 1 };
+        let files = if table.has_pawns { 4 } else { 1 };
+
+        let mut reader = Reader {
+            data: &data,
+            position: 4,
+        };
+        if (reader.byte()? & 2 != 0) != table.has_pawns {
+            return Err(corrupt());
+        }
+        let mut subtables = vec![vec![Subtable::default(); sides]; files];
+        for (file, subtables) in subtables.iter_mut().enumerate() {
+            let order = reader.byte()?;
+            let other_pawns_order = if table.both_pawns {
+                reader.byte()?
+            } else {
+                0xff
+            };
+            for _ in 0..name.len() - 1 {
+                let pieces = reader.byte()?;
+                for (side, subtable) in subtables.iter_mut().enumerate() {
+                    subtable.pieces.push(pieces >> (4 * side) & 0xf);
+                }
+            }
+            for (side, subtable) in subtables.iter_mut().enumerate() {
+                let order = [order, other_pawns_order].map(|order| order >> (4 * side) & 0xf);
+                subtable.set_groups(&table, order, file);
+            }
+        }
+        reader.align(2);
+
+        for subtable in subtables.iter_mut().flatten() {
+            subtable.set_sizes(&mut reader)?;
+        }
+        if !wdl {
+            for subtable in subtables.iter_mut().flatten() {
+                if subtable.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                let wide = subtable.flags & FLAG_WIDE != 0;
+                if wide {
+                    reader.align(2);
+                }
+                for map_idx in &mut subtable.map_idx {
+                    let length = reader.number(if wide { 2 } else { 1 })? as usize;
+                    *map_idx = reader.position;
+                    reader.skip(if wide { 2 * length } else { length });
+                }
+            }
+            reader.align(2);
+        }
+        for subtable in subtables.iter_mut().flatten() {
+            subtable.sparse_index = reader.position;
+            reader.skip(6 * subtable.sparse_index_size);
+        }
+        for subtable in subtables.iter_mut().flatten() {
+            subtable.block_length = reader.position;
+            reader.skip(2 * subtable.block_length_size);
+        }
+        // The blocks of each subtable start on 64 bytes, and a single value has none
+        for subtable in subtables.iter_mut().flatten() {
+            if subtable.blocks > 0 {
+                reader.align(64);
+            }
+            subtable.data = reader.position;
+            reader.skip(subtable.blocks * subtable.block_size);
+        }
+        if reader.position > data.len() {
+            return Err(corrupt());
+        }
+
+        table.data = data;
+        table.subtables = subtables;
+        Ok(table)
+    }
+
+    /// Looks up `board` in the table, its colors swapped if `flipped` to match the
+    /// material the table is named for. DTZ tables may hold the other side to move.
+    fn probe(&self, board: &Board, flipped: bool, dtz: bool) -> Option<Entry> {
+        let indexing = &*INDEXING;
+        let flip_color = if flipped { 8 } else { 0 };
+        let flip_squares = if flipped { 56 } else { 0 };
+        let stm = (board.side_to_move == Color::Black) != flipped;
+
+        // The leading pawns come first, the one with the highest number in front,
+        // and pick the subtable of its file
+        let mut squares = Vec::with_capacity(7);
+        let mut pieces = Vec::with_capacity(7);
+        let mut lead_pawns = 0;
+        let mut file = 0;
+        if self.has_pawns {
+            let pawn = self.subtables[0][0].pieces[0];
+            let color = if pawn ^ flip_color >= 8 {
+                Color::Black
+            } else {
+                Color::White
+            };
+            let pawns = board.bitboards[color as usize][PieceType::Pawn as usize];
+            lead_pawns = pawns.0;
+            for square in pawns {
+                squares.push(square ^ flip_squares);
+                pieces.push(pawn);
+            }
+            let lead =
+                (0..squares.len()).max_by_key(|&i| indexing.map_pawns[squares[i] as usize])?;
+            squares.swap(0, lead);
+            file = (squares[0] % 8).min(7 - squares[0] % 8) as usize;
+        }
+        let lead_count = squares.len();
+
+        let subtables = self.subtables.get(file)?;
+        let subtable = &subtables[stm as usize % subtables.len()];
+        // A symmetric table without pawns serves both sides to move by swapping colors
+        let one_side = self.has_pawns || !self.symmetric;
+        if dtz && one_side && (subtable.flags & FLAG_STM != 0) != stm {
+            return Some(Entry::OtherSide);
+        }
+
+        for square in board
+            .combined
+            .filter(|square| lead_pawns & 1 << square == 0)
+        {
+            let (color, piece) = board.piece(square)?;
+            squares.push(square ^ flip_squares);
+            pieces.push((piece as u8 + 1 + 8 * color as u8) ^ flip_color);
+        }
+        // Put the pieces in the order of the table
+        if pieces.len() != subtable.pieces.len() {
+            return None;
+        }
+        for i in lead_count..pieces.len() {
+            if let Some(j) = (i + 1..pieces.len()).find(|&j| pieces[j] == subtable.pieces[i]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+        if pieces != subtable.pieces {
+            return None;
+        }
+
+        // Mirror the board so that the first piece is on the files a to d, and without
+        // pawns on the ranks 1 to 4 and on or below the a1-h8 diagonal
+        if squares[0] % 8 > 3 {
+            squares.iter_mut().for_each(|square| *square ^= 7);
+        }
+        let mut index = if self.has_pawns {
+            let mut index = indexing.lead_pawn_idx[lead_count][squares[0] as usize];
+            squares[1..lead_count].sort_by_key(|&square| indexing.map_pawns[square as usize]);
+            for (i, &square) in squares.iter().enumerate().take(lead_count).skip(1) {
+                index += indexing.binomial[i][indexing.map_pawns[square as usize] as usize];
+            }
+            index
+        } else {
+            if squares[0] / 8 > 3 {
+                squares.iter_mut().for_each(|square| *square ^= 56);
+            }
+            let first_off = squares[..subtable.group_len[0]]
+                .iter()
+                .map(|&square| off_diagonal(square))
+                .find(|&off| off != 0);
+            if first_off.is_some_and(|off| off > 0) {
+                squares
+                    .iter_mut()
+                    .for_each(|square| *square = (*square >> 3 | *square << 3) & 63);
+            }
+            if self.unique {
+                indexing.unique_index(&squares)
+            } else {
+                let first = indexing.map_a1d1d4[squares[0] as usize] as usize;
+                indexing.map_kk[first][squares[1] as usize]
+            }
+        };
+        index *= subtable.group_idx[0];
+
+        // Each further group of like pieces is indexed by its combination of the
+        // squares the earlier groups leave free
+        let mut start = subtable.group_len[0];
+        let mut remaining_pawns = self.both_pawns;
+        for (group, &length) in subtable.group_len.iter().enumerate().skip(1) {
+            let (earlier, group_squares) = squares.split_at_mut(start);
+            let group_squares = &mut group_squares[..length];
+            group_squares.sort_unstable();
+            let mut combination = 0;
+            for (i, &square) in group_squares.iter().enumerate() {
+                let adjust = earlier.iter().filter(|&&earlier| square > earlier).count();
+                let free = square as usize - adjust - if remaining_pawns { 8 } else { 0 };
+                combination += indexing.binomial[i + 1][free];
+            }
+            remaining_pawns = false;
+            index += combination * subtable.group_idx[group];
+            start += length;
+        }
+        if index >= *subtable.group_idx.last()? {
+            return None;
+        }
+        let value = subtable.value(&self.data, index)?;
+        Some(Entry::Value { file, value })
+    }
+
+    /// The distance to zero in plies of a position of `file` with the outcome `wdl`,
+    /// from the `value` stored for it in a DTZ table.
+    fn plies(&self, file: usize, value: u32, wdl: Wdl) -> Option<i32> {
+        let subtable = &self.subtables[file][0];
+        let mut value = value as usize;
+        if subtable.flags & FLAG_MAPPED != 0 {
+            let map = match wdl {
+                Wdl::Win | Wdl::Draw => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3,
+            };
+            let start = subtable.map_idx[map];
+            value = if subtable.flags & FLAG_WIDE != 0 {
+                little_endian(&self.data, start + 2 * value, 2)? as usize
+            } else {
+                *self.data.get(start + value)? as usize
+            };
+        }
+        // Wins and losses may be stored in moves, and cursed ones always are
+        let in_plies = match wdl {
+            Wdl::Win => subtable.flags & FLAG_WIN_PLIES != 0,
+            Wdl::Loss => subtable.flags & FLAG_LOSS_PLIES != 0,
+            _ => false,
+        };
+        if !in_plies {
+            value *= 2;
+        }
+        Some(value as i32 + 1)
+    }
+}
+
+/// A table file, read the first time a position of its material is probed.
+#[derive(Debug, Default)]
+struct TableFile {
+    path: PathBuf,
+    table: OnceLock<Option<Table>>,
+}
+
+impl TableFile {
+    /// The table, or None when the file cannot be read or is corrupt.
+    fn table(&self, name: &str, wdl: bool) -> Option<&Table> {
+        self.table
+            .get_or_init(|| {
+                let data = fs::read(&self.path).ok()?;
+                Table::parse(data, name, wdl).ok()
+            })
+            .as_ref()
+    }
+}
+
+/// The table files of one material combination.
+#[derive(Debug, Default)]
+struct TableFiles {
+    wdl: Option<TableFile>,
+    dtz: Option<TableFile>,
+}
+
+/// The Syzygy table files found in the directories of a `SyzygyPath`.
+#[derive(Debug, Default)]
+pub struct SyzygyTablebase {
+    tables: HashMap<String, TableFiles>,
+    max_pieces: u32,
+}
+
+impl SyzygyTablebase {
+    /// Finds the tables in `paths`, directories separated by `:` or `;` as for the UCI
+    /// option. Files whose header is not a Syzygy table are an error.
+    pub fn open(paths: &str) -> Result<Self, String> {
+        let mut tablebase = SyzygyTablebase::default();
+        for directory in paths.split([':', ';']).filter(|path| !path.is_empty()) {
+            let entries = fs::read_dir(directory)
+                .map_err(|err| format!("Could not read {}: {}", directory, err))?;
+            for entry in entries {
+                let path = entry.map_err(|err| err.to_string())?.path();
+                tablebase.add(&path)?;
+            }
+        }
+        Ok(tablebase)
+    }
+
+    fn add(&mut self, path: &Path) -> Result<(), String> {
+        let (Some(name), Some(extension)) = (
+            path.file_stem().and_then(|stem| stem.to_str()),
+            path.extension().and_then(|extension| extension.to_str()),
+        ) else {
+            return Ok(());
+        };
+        let magic = match extension {
+            "rtbw" => WDL_MAGIC,
+            "rtbz" => DTZ_MAGIC,
+            _ => return Ok(()),
+        };
+        let valid_name = name.split('v').count() == 2
+            && name.split('v').all(|side| {
+                side.starts_with('K') && side.chars().all(|piece| "KQRBNP".contains(piece))
+            });
+        if !valid_name {
+            return Ok(());
+        }
+
+        let mut header = [0; 4];
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut header))
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        if header != magic {
+            return Err(format!("Not a Syzygy table: {}", path.display()));
+        }
+
+        let files = self.tables.entry(name.to_string()).or_default();
+        let file = Some(TableFile {
+            path: path.to_path_buf(),
+            table: OnceLock::new(),
+        });
+        match extension {
+            "rtbw" => files.wdl = file,
+            _ => files.dtz = file,
+        }
+        self.max_pieces = self.max_pieces.max(name.len() as u32 - 1);
+        Ok(())
+    }
+
+    /// The number of WDL and DTZ files found.
+    pub fn files(&self) -> usize {
+        self.tables
+            .values()
+            .map(|files| files.wdl.is_some() as usize + files.dtz.is_some() as usize)
+            .sum()
+    }
+
+    /// The WDL or DTZ table for the material of `board`, and whether the colors of
+    /// `board` are swapped to match it: when Black has the pieces the table names
+    /// first, or both sides have the same pieces and Black is to move.
+    fn table(&self, board: &Board, wdl: bool) -> Option<(&Table, bool)> {
+        let white = table_name(board, Color::White);
+        let black = table_name(board, Color::Black);
+        let (name, flipped) = if self.tables.contains_key(&white) {
+            let flipped = white == black && board.side_to_move == Color::Black;
+            (white, flipped)
+        } else {
+            (black, true)
+        };
+        let files = self.tables.get(&name)?;
+        let file = if wdl { &files.wdl } else { &files.dtz };
+        Some((file.as_ref()?.table(&name, wdl)?, flipped))
+    }
+
+    /// The outcome stored in the WDL table, a draw when only the kings are left.
+    fn probe_wdl_table(&self, board: &Board) -> Option<Wdl> {
+        if board.combined.popcnt() == 2 {
+            return Some(Wdl::Draw);
+        }
+        let (table, flipped) = self.table(board, true)?;
+        match table.probe(board, flipped, false)? {
+            Entry::Value { value, .. } => Wdl::from_stored(value),
+            Entry::OtherSide => None,
+        }
+    }
+
+    /// The distance to zero stored in the DTZ table for a position with the outcome
+    /// `wdl`, or Some(None) when the table holds the other side to move.
+    fn probe_dtz_table(&self, board: &Board, wdl: Wdl) -> Option<Option<i32>> {
+        let (table, flipped) = self.table(board, false)?;
+        match table.probe(board, flipped, true)? {
+            Entry::Value { file, value } => table.plies(file, value, wdl).map(Some),
+            Entry::OtherSide => Some(None),
+        }
+    }
+
+    /// The outcome for the side to move, searching the captures, and with
+    /// `pawn_moves` the pawn moves, before trusting the table. Also returns whether
+    /// one of those moves is the best, for which DTZ tables hold no useful value.
+    fn search_wdl(&self, board: &mut Board, pawn_moves: bool) -> Option<(Wdl, bool)> {
+        let moves = MoveGenerator::generate_legal_moves(board);
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for mv in &moves {
+            let searches = if pawn_moves {
+                zeroes(board, mv)
+            } else {
+                captures(board, mv)
+            };
+            if !searches {
+                continue;
+            }
+            searched += 1;
+            board.make_move(*mv);
+            let wdl = self.search_wdl(board, false);
+            board.unmake();
+            let wdl = wdl?.0.opposite();
+            if wdl > best {
+                best = wdl;
+                if wdl == Wdl::Win {
+                    return Some((wdl, true));
+                }
+            }
+        }
+
+        // With only captures to play the table may not know the position at all
+        let only_searched_moves = searched > 0 && searched == moves.len();
+        let stored = if only_searched_moves {
+            best
+        } else {
+            self.probe_wdl_table(board)?
+        };
+        if best >= stored {
+            Some((best, best > Wdl::Draw || only_searched_moves))
+        } else {
+            Some((stored, false))
+        }
+    }
+
+    /// The distance to zero of `board`, searching a ply when the DTZ table holds only
+    /// the other side to move.
+    fn search_dtz(&self, board: &mut Board) -> Option<i32> {
+        let (wdl, zeroing) = self.search_wdl(board, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing {
+            return Some(wdl.zeroing_dtz());
+        }
+        let sign = if wdl > Wdl::Draw { 1 } else { -1 };
+        if let Some(plies) = self.probe_dtz_table(board, wdl)? {
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            return Some(sign * (plies + if cursed { 100 } else { 0 }));
+        }
+
+        // Winning, take the fastest win; losing, the slowest loss
+        let mut best = None;
+        for mv in MoveGenerator::generate_legal_moves(board) {
+            let zeroing = zeroes(board, &mv);
+            board.make_move(mv);
+            let dtz = if zeroing {
+                self.search_wdl(board, false)
+                    .map(|(wdl, _)| -wdl.zeroing_dtz())
+            } else {
+                self.search_dtz(board).map(|dtz| -dtz + (-dtz).signum())
+            };
+            let mates = board.is_king_in_check(board.side_to_move)
+                && MoveGenerator::generate_legal_moves(board).is_empty();
+            board.unmake();
+            let dtz = if mates { 1 } else { dtz? };
+            if dtz.signum() == sign && best.is_none_or(|best| dtz < best) {
+                best = Some(dtz);
+            }
+        }
+        Some(best.unwrap_or(-1))
+    }
+}
+
+impl Tablebase for SyzygyTablebase {
+    fn max_pieces(&self) -> u32 {
+        self.max_pieces
+    }
+
+    fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        self.search_wdl(&mut board.clone(), false)
+            .map(|(wdl, _)| wdl)
+    }
+
+    fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        self.search_dtz(&mut board.clone())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// The published KQvK, KRvK, KPvK and KRvKP tables in tests/fixtures/syzygy, or None
+    /// while they are missing, in which case the tests that read them are skipped.
+    pub(crate) fn fixtures() -> Option<SyzygyTablebase> {
+        let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/syzygy");
+        let tablebase = SyzygyTablebase::open(directory)
+            .ok()
+            .filter(|tablebase| tablebase.files() > 0);
+        if tablebase.is_none() {
+            eprintln!("Skipped, no Syzygy tables in {}", directory);
+        }
+        tablebase
+    }
+
+    #[test]
+    fn test_fifty_move_rule_curses_slow_wins() {
+        assert_eq!(Wdl::from_dtz(10, 0), Wdl::Win);
+        assert_eq!(Wdl::from_dtz(10, 95), Wdl::CursedWin);
+        assert_eq!(Wdl::from_dtz(-30, 80), Wdl::BlessedLoss);
+        assert_eq!(Wdl::from_dtz(0, 0), Wdl::Draw);
+        assert_eq!(Wdl::CursedWin.opposite(), Wdl::BlessedLoss);
+        assert!(Wdl::Win.score(10) > Wdl::CursedWin.score(0));
+        assert!(Wdl::CursedWin.score(0) > Wdl::Draw.score(0));
+    }
+
+    #[test]
+    fn test_table_names() {
+        let board = Board::from_fen("8/8/4k3/3r4/8/8/2PR4/4K3 w - - 0 1").unwrap();
+        assert_eq!(table_name(&board, Color::White), "KRPvKR");
+        assert_eq!(table_name(&board, Color::Black), "KRvKRP");
+    }
+
+    #[test]
+    fn test_open_finds_table_files() {
+        let directory = std::env::temp_dir().join(format!("syzygy-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("KQvK.rtbw"), WDL_MAGIC).unwrap();
+        fs::write(directory.join("KQvK.rtbz"), DTZ_MAGIC).unwrap();
+        fs::write(directory.join("KRPvKR.rtbw"), WDL_MAGIC).unwrap();
+        fs::write(directory.join("README.txt"), "not a table").unwrap();
+
+        let tablebase = SyzygyTablebase::open(directory.to_str().unwrap()).unwrap();
+        assert_eq!((tablebase.files(), tablebase.max_pieces()), (3, 5));
+        assert!(tablebase
+            .tables
+            .get("KQvK")
+            .is_some_and(|files| files.dtz.is_some()));
+        // The files only hold the magic, so there is nothing to decode
+        let board = Board::from_fen("8/8/4k3/8/8/8/8/3QK3 b - - 0 1").unwrap();
+        assert_eq!(tablebase.probe_wdl(&board), None);
+
+        fs::write(directory.join("KPvK.rtbz"), WDL_MAGIC).unwrap();
+        assert!(SyzygyTablebase::open(directory.to_str().unwrap()).is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_probe_wdl() {
+        let Some(tablebase) = fixtures() else {
+            return;
+        };
+        assert_eq!(tablebase.max_pieces(), 4);
+        let wdl = |fen: &str| tablebase.probe_wdl(&Board::from_fen(fen).unwrap());
+        assert_eq!(wdl("8/8/8/4k3/8/8/8/R3K3 w - - 0 1"), Some(Wdl::Win));
+        // The king cannot take the protected rook, but takes the hanging one
+        assert_eq!(wdl("8/8/8/8/8/3k4/3R4/4K3 b - - 0 1"), Some(Wdl::Loss));
+        assert_eq!(wdl("8/8/8/8/8/3k4/3R4/7K b - - 0 1"), Some(Wdl::Draw));
+        // Stalemate
+        assert_eq!(wdl("8/8/8/8/8/8/2R5/k1K5 b - - 0 1"), Some(Wdl::Draw));
+        // Black holds the rook, and the board is flipped to look it up
+        assert_eq!(wdl("r3k3/8/8/8/4K3/8/8/8 b - - 0 1"), Some(Wdl::Win));
+
+        assert_eq!(wdl("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), Some(Wdl::Win));
+        assert_eq!(wdl("4k3/8/3K4/4P3/8/8/8/8 b - - 0 1"), Some(Wdl::Loss));
+        assert_eq!(wdl("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1"), Some(Wdl::Win));
+        assert_eq!(wdl("k7/8/8/8/8/8/P7/7K w - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(wdl("8/8/8/8/8/8/8/k1K5 w - - 0 1"), Some(Wdl::Draw));
+
+        // The rook stops the pawn, unless the king supports it on the seventh rank
+        assert_eq!(wdl("k7/7p/8/8/4K3/8/8/4R3 w - - 0 1"), Some(Wdl::Win));
+        assert_eq!(wdl("k7/7p/8/8/4K3/8/8/4R3 b - - 0 1"), Some(Wdl::Loss));
+        assert_eq!(wdl("1R5K/8/8/8/8/8/1pk5/8 w - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(wdl("8/5KP1/8/8/8/8/8/1r5k b - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(wdl("8/8/8/4k3/8/8/8/RQ2K3 w - - 0 1"), None);
+    }
+
+    #[test]
+    fn test_probe_dtz() {
+        let Some(tablebase) = fixtures() else {
+            return;
+        };
+        let dtz = |fen: &str| tablebase.probe_dtz(&Board::from_fen(fen).unwrap());
+        // The tables may store a distance in moves rather than plies, which makes it up
+        // to a ply longer
+        let dtz_within = |fen: &str, plies: i32| {
+            let dtz = dtz(fen).unwrap();
+            assert!(
+                dtz.signum() == plies.signum() && (dtz.abs() - plies.abs()).abs() <= 1,
+                "{} {}",
+                fen,
+                dtz
+            );
+        };
+        // Mate in 1 and mated
+        dtz_within("4k3/8/4K3/8/8/8/8/R7 w - - 0 1", 1);
+        assert_eq!(dtz("R3k3/8/4K3/8/8/8/8/8 b - - 0 1"), Some(-1));
+        dtz_within("8/8/8/4k3/8/8/8/R3K3 w - - 0 1", 27);
+        dtz_within("r3k3/8/8/8/4K3/8/8/8 b - - 0 1", 27);
+        dtz_within("8/8/8/8/8/3k4/3R4/4K3 b - - 0 1", -28);
+        assert_eq!(dtz("8/8/8/8/8/3k4/3R4/7K b - - 0 1"), Some(0));
+
+        // A pawn move zeroes the counter, so a pawn that can advance safely is close
+        dtz_within("4k3/8/3K4/4P3/8/8/8/8 b - - 0 1", -2);
+        dtz_within("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", 3);
+        dtz_within("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1", 3);
+        assert_eq!(dtz("8/8/8/4k3/8/4K3/4P3/8 w - - 0 1"), Some(0));
+        // Promoting stalemates, so the king has to make room first
+        dtz_within("8/1P6/8/8/8/K7/8/k7 w - - 0 1", 3);
+
+        // Taking the pawn is a capture, so the rook converts at once
+        assert_eq!(dtz("8/8/8/8/8/8/k6p/4K2R w - - 0 1"), Some(1));
+        assert_eq!(dtz("1R5K/8/8/8/8/8/1pk5/8 w - - 0 1"), Some(0));
+    }
+
+    #[test]
+    fn test_root_moves_convert_the_win() {
+        let Some(tablebase) = fixtures() else {
+            return;
+        };
+        let root_moves = |fen: &str| {
+            let mut board = Board::from_fen(fen).unwrap();
+            let moves = filter_root_moves(&tablebase, &mut board).unwrap();
+            let mut moves: Vec<String> = moves.iter().map(|mv| mv.to_standard_notation()).collect();
+            moves.sort();
+            moves
+        };
+        // Only the king moves that take the opposition keep the win
+        assert_eq!(
+            root_moves("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"),
+            ["e6d6", "e6f6"]
+        );
+        // A drawn position keeps every move that does not lose
+        assert_eq!(
+            root_moves("8/8/8/4k3/8/4K3/4P3/8 w - - 0 1"),
+            ["e3d2", "e3d3", "e3f2", "e3f3"]
+        );
+        // The defender keeps the moves that win the pawn or still attack it
+        assert_eq!(
+            root_moves("8/8/8/8/8/8/3kP3/7K b - - 0 1"),
+            ["d2e2", "d2e3"]
+        );
+        // Only taking the rook draws
+        assert_eq!(root_moves("8/8/8/8/8/3k4/3R4/7K b - - 0 1"), ["d3d2"]);
+        // Taking the pawn converts at once, every other move is slower
+        assert_eq!(root_moves("8/8/8/8/8/8/k6p/4K2R w - - 0 1"), ["h1h2"]);
+    }
+}
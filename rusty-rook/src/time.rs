@@ -0,0 +1,148 @@
+use std::time::{Duration, Instant};
+
+/// Time kept in reserve for communication with the GUI on every move.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+/// Assumed number of moves left in the game when the GUI does not send `movestogo`.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+/// The limits of one search, as given by a UCI `go` command.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub depth: Option<u32>,
+    pub move_time: Option<Duration>,
+    /// The clock time left for the side to move.
+    pub time_left: Option<Duration>,
+    /// The increment of the side to move.
+    pub increment: Duration,
+    pub moves_to_go: Option<u32>,
+    /// The most nodes the main thread searches.
+    pub nodes: Option<u64>,
+    /// Search until told to stop.
+    pub infinite: bool,
+    /// Look for a mate in at most this many moves instead of the best move.
+    pub mate: Option<u32>,
+    /// Search the position expected after the ponder move, on the opponent's time.
+    pub ponder: bool,
+}
+
+impl Limits {
+    /// Limits for a search to a fixed depth without any time limit.
+    pub fn depth(depth: u32) -> Self {
+        Limits {
+            depth: Some(depth),
+            ..Limits::default()
+        }
+    }
+}
+
+/// Decides how long a search may run.
+///
+/// The soft limit is checked between iterations: no new iteration is started once it
+/// has passed. The hard limit aborts the running iteration. Both are measured from
+/// the start of the search, so when a ponder search turns into a real one the time
+/// already spent pondering counts towards them.
+#[derive(Debug, Clone)]
+pub struct TimeManager {
+    start: Instant,
+    infinite: bool,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+    max_nodes: Option<u64>,
+}
+
+impl TimeManager {
+    pub fn new(limits: &Limits, start: Instant) -> Self {
+        let (soft_limit, hard_limit) = if limits.infinite {
+            (None, None)
+        } else if let Some(move_time) = limits.move_time {
+            let move_time = move_time.saturating_sub(MOVE_OVERHEAD);
+            (Some(move_time), Some(move_time))
+        } else if let Some(time_left) = limits.time_left {
+            let remaining = time_left.saturating_sub(MOVE_OVERHEAD);
+            let moves = limits.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+            let hard = remaining * 3 / 4;
+            let soft = (remaining / moves + limits.increment * 3 / 4).min(hard);
+            (Some(soft), Some(hard.min(soft * 3)))
+        } else {
+            (None, None)
+        };
+        TimeManager {
+            start,
+            infinite: limits.infinite,
+            soft_limit,
+            hard_limit,
+            max_nodes: limits.nodes,
+        }
+    }
+
+    /// Returns true if the search only ends when it is stopped.
+    pub fn is_infinite(&self) -> bool {
+        self.infinite
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Returns true if there is not enough time left to start another iteration.
+    pub fn soft_limit_reached(&self) -> bool {
+        self.soft_limit.is_some_and(|limit| self.elapsed() >= limit)
+    }
+
+    /// Returns true if the running iteration has to be aborted.
+    pub fn hard_limit_reached(&self) -> bool {
+        self.hard_limit.is_some_and(|limit| self.elapsed() >= limit)
+    }
+
+    /// Returns true once `nodes` nodes are too many to go on searching.
+    pub fn node_limit_reached(&self, nodes: u64) -> bool {
+        self.max_nodes.is_some_and(|limit| nodes >= limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocation_from_clock() {
+        let limits = Limits {
+            time_left: Some(Duration::from_secs(60)),
+            increment: Duration::from_secs(1),
+            ..Limits::default()
+        };
+        let time = TimeManager::new(&limits, Instant::now());
+        let soft = time.soft_limit.unwrap();
+        let hard = time.hard_limit.unwrap();
+        assert!(soft > Duration::from_secs(2) && soft < Duration::from_secs(4));
+        assert!(hard > soft && hard < Duration::from_secs(45));
+    }
+
+    #[test]
+    fn test_last_move_before_time_control() {
+        let limits = Limits {
+            time_left: Some(Duration::from_secs(10)),
+            moves_to_go: Some(1),
+            ..Limits::default()
+        };
+        let time = TimeManager::new(&limits, Instant::now());
+        assert!(time.hard_limit.unwrap() < Duration::from_secs(10));
+        assert!(time.soft_limit <= time.hard_limit);
+    }
+
+    #[test]
+    fn test_no_limits() {
+        let time = TimeManager::new(&Limits::depth(5), Instant::now());
+        assert!(!time.soft_limit_reached());
+        assert!(!time.hard_limit_reached());
+        let time = TimeManager::new(
+            &Limits {
+                infinite: true,
+                move_time: Some(Duration::ZERO),
+                ..Limits::default()
+            },
+            Instant::now(),
+        );
+        assert!(!time.hard_limit_reached());
+    }
+}
@@ -0,0 +1,286 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chess::{
+    chess_move::{ChessMove, FLAG_CASTLE},
+    piece::PieceType,
+};
+
+use crate::score::MATE_THRESHOLD;
+
+/// How a stored score relates to the true value of the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    /// The search failed high, the true score is at least the stored score.
+    Lower,
+    /// The search failed low, the true score is at most the stored score.
+    Upper,
+}
+
+/// Converts a score measured from the root into one measured from the node at `ply`.
+/// Mate scores have to be stored relative to the node because the same position can
+/// be reached at different distances from the root.
+pub fn score_to_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score + ply as i32
+    } else if score <= -MATE_THRESHOLD {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+/// Inverse of `score_to_tt`, converts a stored score back into one measured from the root.
+pub fn score_from_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score - ply as i32
+    } else if score <= -MATE_THRESHOLD {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+/// The squares, promotion, dropped piece and castling flag of a move, which is all the
+/// table keeps of it. Enough to recognise the move again among the legal moves of the
+/// position. The flag tells a Chess960 castling move from a king step to the same square.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtMove {
+    pub from: u8,
+    pub to: u8,
+    pub promoted_piece: Option<PieceType>,
+    pub dropped_piece: Option<PieceType>,
+    pub castle: bool,
+}
+
+impl TtMove {
+    pub fn matches(&self, mv: &ChessMove) -> bool {
+        self.from == mv.from
+            && self.to == mv.to
+            && self.promoted_piece == mv.promoted_piece
+            && self.dropped_piece == mv.dropped_piece
+            && self.castle == (mv.flags & FLAG_CASTLE != 0)
+    }
+
+    /// Packs the move into 19 bits. Zero is never a valid move since only castling
+    /// moves and drops can have `from` equal to `to`, and they set the castling bit or
+    /// the dropped piece, which is stored one above its `PieceType` so pawns count.
+    fn pack(self) -> u64 {
+        let promotion = match self.promoted_piece {
+            None => 0,
+            Some(piece) => piece as u64,
+        };
+        let drop = self.dropped_piece.map_or(0, |piece| piece as u64 + 1);
+        self.from as u64
+            | (self.to as u64) << 6
+            | promotion << 12
+            | (self.castle as u64) << 15
+            | drop << 16
+    }
+
+    fn unpack(bits: u64) -> Option<Self> {
+        if bits == 0 {
+            return None;
+        }
+        let promotion = (bits >> 12) & 0x7;
+        let drop = (bits >> 16) & 0x7;
+        Some(TtMove {
+            from: (bits & 0x3F) as u8,
+            to: ((bits >> 6) & 0x3F) as u8,
+            promoted_piece: (promotion != 0).then(|| PieceType::from(promotion as usize)),
+            dropped_piece: (drop != 0).then(|| PieceType::from(drop as usize - 1)),
+            castle: bits & 1 << 15 != 0,
+        })
+    }
+}
+
+impl From<ChessMove> for TtMove {
+    fn from(mv: ChessMove) -> Self {
+        TtMove {
+            from: mv.from,
+            to: mv.to,
+            promoted_piece: mv.promoted_piece,
+            dropped_piece: mv.dropped_piece,
+            castle: mv.flags & FLAG_CASTLE != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TtEntry {
+    pub key: u64,
+    pub depth: u32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<TtMove>,
+}
+
+impl TtEntry {
+    /// Packs everything but the key into one word:
+    /// score in bits 0-31, depth in 32-39, bound in 40-41 and the move in 42-60.
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+        self.score as u32 as u64
+            | (self.depth.min(u8::MAX as u32) as u64) << 32
+            | bound << 40
+            | self.best_move.map_or(0, TtMove::pack) << 42
+    }
+
+    fn unpack(key: u64, data: u64) -> Option<Self> {
+        let bound = match (data >> 40) & 0x3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None, // Empty slot
+        };
+        Some(TtEntry {
+            key,
+            depth: ((data >> 32) & 0xFF) as u32,
+            score: data as u32 as i32,
+            bound,
+            best_move: TtMove::unpack(data >> 42),
+        })
+    }
+}
+
+/// One slot of the table. The key is stored xored with the data, so a slot torn by two
+/// threads writing at the same time fails the key check instead of returning a mix of
+/// two entries. This lets every search thread share the table without locking.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// A fixed size hash table of previously searched positions, indexed by Zobrist hash.
+/// The table can be shared between threads.
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+impl TranspositionTable {
+    /// Creates a table using roughly `size_mb` megabytes.
+    /// The number of slots is rounded down to a power of two.
+    pub fn new(size_mb: usize) -> Self {
+        let count = (size_mb * 1024 * 1024 / std::mem::size_of::<Slot>()).max(1);
+        let count = 1 << (usize::BITS - 1 - count.leading_zeros());
+        TranspositionTable {
+            slots: (0..count).map(|_| Slot::default()).collect(),
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[(key as usize) & (self.slots.len() - 1)]
+    }
+
+    /// Returns the entry for `key` if one is stored.
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+        if slot.key.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        TtEntry::unpack(key, data)
+    }
+
+    /// Stores an entry, replacing the existing one unless it belongs to the same
+    /// position and was searched deeper.
+    pub fn store(&self, entry: TtEntry) {
+        if let Some(existing) = self.probe(entry.key) {
+            if existing.depth > entry.depth {
+                return;
+            }
+        }
+        let slot = self.slot(entry.key);
+        let data = entry.pack();
+        slot.key.store(entry.key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::score::MATE_SCORE;
+
+    #[test]
+    fn test_mate_scores_are_stored_relative_to_the_node() {
+        // Mate found 5 plies from the root by a node 3 plies from the root
+        let stored = score_to_tt(MATE_SCORE - 5, 3);
+        assert_eq!(stored, MATE_SCORE - 2);
+        // Reached again 1 ply from the root the mate is 3 plies away
+        assert_eq!(score_from_tt(stored, 1), MATE_SCORE - 3);
+        assert_eq!(
+            score_from_tt(score_to_tt(-MATE_SCORE + 6, 4), 2),
+            -MATE_SCORE + 4
+        );
+        assert_eq!(score_from_tt(score_to_tt(250, 4), 2), 250);
+    }
+
+    #[test]
+    fn test_store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let entry = TtEntry {
+            key: 0xDEAD_BEEF_1234_5678,
+            depth: 7,
+            score: -MATE_SCORE + 3,
+            bound: Bound::Lower,
+            best_move: Some(TtMove {
+                from: 52,
+                to: 60,
+                promoted_piece: Some(PieceType::Knight),
+                dropped_piece: None,
+                castle: false,
+            }),
+        };
+        tt.store(entry);
+        let stored = tt.probe(entry.key).unwrap();
+        assert_eq!(stored.depth, 7);
+        assert_eq!(stored.score, -MATE_SCORE + 3);
+        assert_eq!(stored.bound, Bound::Lower);
+        assert_eq!(stored.best_move, entry.best_move);
+        assert!(tt.probe(entry.key ^ 1).is_none());
+
+        // A shallower result for the same position does not replace a deeper one
+        tt.store(TtEntry {
+            depth: 3,
+            best_move: None,
+            ..entry
+        });
+        assert_eq!(tt.probe(entry.key).unwrap().depth, 7);
+
+        // A pawn drop keeps its piece
+        let drop = TtMove {
+            from: 28,
+            to: 28,
+            promoted_piece: None,
+            dropped_piece: Some(PieceType::Pawn),
+            castle: false,
+        };
+        tt.store(TtEntry {
+            depth: 9,
+            best_move: Some(drop),
+            ..entry
+        });
+        assert_eq!(tt.probe(entry.key).unwrap().best_move, Some(drop));
+        tt.clear();
+        assert!(tt.probe(entry.key).is_none());
+    }
+}
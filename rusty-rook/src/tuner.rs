@@ -0,0 +1,322 @@
+//! Texel tuning of the evaluation parameters. The static evaluation of a quiet
+//! position, passed through a sigmoid, predicts the result of the game it was taken
+//! from. The tuner fits the scaling constant of the sigmoid once and then changes one
+//! weight at a time as long as the mean squared error of the predictions shrinks.
+
+use std::{fs, path::Path, thread};
+
+use chess::{board::Board, piece::Color};
+
+use crate::{
+    eval::{ClassicalEvaluator, Evaluator},
+    params::EvalParams,
+};
+
+/// A position labeled with the result of its game from White's point of view: 1 for
+/// a win, 0.5 for a draw and 0 for a loss.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub board: Board,
+    pub result: f64,
+}
+
+impl Sample {
+    /// Parses an EPD or FEN line labeled with its result, either in a `c9` opcode as in
+    /// `... c9 "1-0";`, in brackets at the end as in `... [0.5]` or as the last of the
+    /// `|` separated fields written by datagen. Returns `None` for blank lines and
+    /// comments.
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let (position, label) = if let Some(opcode) = line.find(" c9 ") {
+            (&line[..opcode], &line[opcode + 4..])
+        } else if let (Some(first), Some(last)) = (line.find('|'), line.rfind('|')) {
+            (&line[..first], &line[last + 1..])
+        } else if let Some(bracket) = line.rfind('[') {
+            (&line[..bracket], &line[bracket..])
+        } else {
+            return Err(format!("No result in: {}", line));
+        };
+        let label = label.trim_matches(|c: char| c.is_whitespace() || "\"[];".contains(c));
+        let result = match label {
+            "1-0" | "1.0" | "1" => 1.0,
+            "1/2-1/2" | "0.5" => 0.5,
+            "0-1" | "0.0" | "0" => 0.0,
+            _ => return Err(format!("Invalid result {} in: {}", label, line)),
+        };
+
+        // EPD positions leave out the move counters
+        let fields: Vec<&str> = position.split_whitespace().collect();
+        let fen = match fields.len() {
+            4 => format!("{} 0 1", fields.join(" ")),
+            _ => fields.join(" "),
+        };
+        let board = Board::from_fen(&fen).map_err(|err| format!("{} in: {}", err, line))?;
+        Ok(Some(Sample { board, result }))
+    }
+}
+
+/// Reads every labeled position of a dataset file, see `Sample::parse`.
+pub fn load_samples(path: &Path) -> Result<Vec<Sample>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    let mut samples = Vec::new();
+    for line in text.lines() {
+        if let Some(sample) = Sample::parse(line)? {
+            samples.push(sample);
+        }
+    }
+    Ok(samples)
+}
+
+/// Splits off `fraction` of the samples, spread evenly over the dataset, to measure
+/// how well the tuned parameters generalize. Returns the training and validation
+/// samples.
+pub fn split(samples: Vec<Sample>, fraction: f64) -> (Vec<Sample>, Vec<Sample>) {
+    let every = if fraction > 0.0 {
+        (1.0 / fraction).round().max(1.0) as usize
+    } else {
+        usize::MAX
+    };
+    let (validation, training): (Vec<_>, Vec<_>) = samples
+        .into_iter()
+        .enumerate()
+        .partition(|(i, _)| i % every == every - 1);
+    let strip = |samples: Vec<(usize, Sample)>| samples.into_iter().map(|(_, s)| s).collect();
+    (strip(training), strip(validation))
+}
+
+/// The expected result for White of a position evaluated at `score` centipawns.
+pub fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+/// The static evaluation of every sample from White's point of view.
+pub fn evaluate_samples(samples: &[Sample], params: &EvalParams) -> Vec<f64> {
+    let evaluator = ClassicalEvaluator::new(params.clone());
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = samples.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = samples
+            .chunks(chunk)
+            .map(|chunk| {
+                let evaluator = &evaluator;
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|sample| {
+                            let score = evaluator.evaluate(&sample.board) as f64;
+                            match sample.board.side_to_move {
+                                Color::White => score,
+                                Color::Black => -score,
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Evaluation thread panicked"))
+            .collect()
+    })
+}
+
+/// The mean squared error between the results and the predictions for `scores`.
+pub fn mean_error(samples: &[Sample], scores: &[f64], k: f64) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let total: f64 = samples
+        .iter()
+        .zip(scores)
+        .map(|(sample, &score)| (sample.result - sigmoid(score, k)).powi(2))
+        .sum();
+    total / samples.len() as f64
+}
+
+/// Finds the scaling constant that best predicts the results from the evaluations,
+/// narrowing down the search step by a factor of ten per round.
+pub fn fit_k(samples: &[Sample], params: &EvalParams) -> f64 {
+    let scores = evaluate_samples(samples, params);
+    let error = |k: f64| mean_error(samples, &scores, k);
+    let (mut k, mut step): (f64, f64) = (1.0, 1.0);
+    for _ in 0..4 {
+        let (low, high) = ((k - 10.0 * step).max(step), k + 10.0 * step);
+        let mut best = (error(k), k);
+        let mut candidate = low;
+        while candidate <= high {
+            let candidate_error = error(candidate);
+            if candidate_error < best.0 {
+                best = (candidate_error, candidate);
+            }
+            candidate += step;
+        }
+        k = best.1;
+        step /= 10.0;
+    }
+    k
+}
+
+/// The losses after one pass over all weights.
+#[derive(Debug, Clone, Copy)]
+pub struct Epoch {
+    pub epoch: usize,
+    pub training_error: f64,
+    pub validation_error: f64,
+    /// How many weights changed during the pass.
+    pub changed: usize,
+}
+
+/// Local search over the evaluation weights.
+pub struct Tuner {
+    pub params: EvalParams,
+    pub k: f64,
+    /// The dotted paths of the weights being tuned.
+    pub weights: Vec<String>,
+    training: Vec<Sample>,
+    validation: Vec<Sample>,
+}
+
+impl Tuner {
+    /// Tunes every weight whose path starts with one of `prefixes`, or all of them
+    /// when there are none.
+    pub fn new(
+        params: EvalParams,
+        k: f64,
+        prefixes: &[String],
+        training: Vec<Sample>,
+        validation: Vec<Sample>,
+    ) -> Self {
+        let weights = params
+            .weight_names()
+            .into_iter()
+            .filter(|name| {
+                prefixes.is_empty() || prefixes.iter().any(|prefix| name.starts_with(prefix))
+            })
+            .collect();
+        Tuner {
+            params,
+            k,
+            weights,
+            training,
+            validation,
+        }
+    }
+
+    pub fn training_error(&self, params: &EvalParams) -> f64 {
+        let scores = evaluate_samples(&self.training, params);
+        mean_error(&self.training, &scores, self.k)
+    }
+
+    pub fn validation_error(&self) -> f64 {
+        let scores = evaluate_samples(&self.validation, &self.params);
+        mean_error(&self.validation, &scores, self.k)
+    }
+
+    /// Runs up to `epochs` passes, trying to move each weight by `step` in both
+    /// directions, and stops early once a pass changes nothing. `on_epoch` is called
+    /// after every pass with the current parameters.
+    pub fn run(
+        &mut self,
+        epochs: usize,
+        step: i32,
+        mut on_epoch: impl FnMut(&Epoch, &EvalParams),
+    ) -> Result<(), String> {
+        let mut best_error = self.training_error(&self.params);
+        for epoch in 1..=epochs {
+            let mut changed = 0;
+            for name in &self.weights {
+                let value = self
+                    .params
+                    .get(name)
+                    .ok_or(format!("Unknown weight {}", name))?;
+                for delta in [step, -step] {
+                    let mut candidate = self.params.clone();
+                    candidate.set(name, value + delta)?;
+                    let error = self.training_error(&candidate);
+                    if error < best_error {
+                        best_error = error;
+                        self.params = candidate;
+                        changed += 1;
+                        break;
+                    }
+                }
+            }
+            let result = Epoch {
+                epoch,
+                training_error: best_error,
+                validation_error: self.validation_error(),
+                changed,
+            };
+            on_epoch(&result, &self.params);
+            if changed == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(lines: &[&str]) -> Vec<Sample> {
+        lines
+            .iter()
+            .map(|line| Sample::parse(line).unwrap().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        let epd = Sample::parse(r#"4k3/8/8/8/8/8/8/R3K3 w - - c9 "1-0";"#).unwrap();
+        assert_eq!(epd.unwrap().result, 1.0);
+        let fen = Sample::parse("4k3/8/8/8/8/8/8/r3K3 b - - 3 40 [0.0]").unwrap();
+        assert_eq!(fen.unwrap().board.half_move_clock, 3);
+        let draw = Sample::parse(r#"4k3/8/8/8/8/8/8/4K3 w - - c9 "1/2-1/2";"#).unwrap();
+        assert_eq!(draw.unwrap().result, 0.5);
+        let datagen = Sample::parse("4k3/8/8/8/8/8/8/4K3 b - - 0 1 | -12 | 0.0").unwrap();
+        assert_eq!(datagen.unwrap().result, 0.0);
+        assert!(Sample::parse("# comment").unwrap().is_none());
+        assert!(Sample::parse("4k3/8/8/8/8/8/8/4K3 w - -").is_err());
+        assert!(Sample::parse("4k3/8/8/8/8/8/8/4K3 w - - [2]").is_err());
+    }
+
+    #[test]
+    fn test_split_and_sigmoid() {
+        let lines = ["4k3/8/8/8/8/8/8/4K3 w - - [0.5]"; 10];
+        let (training, validation) = split(samples(&lines), 0.2);
+        assert_eq!((training.len(), validation.len()), (8, 2));
+        let (training, validation) = split(samples(&lines), 0.0);
+        assert_eq!((training.len(), validation.len()), (10, 0));
+
+        assert_eq!(sigmoid(0.0, 1.0), 0.5);
+        assert!((sigmoid(400.0, 1.0) - 10.0 / 11.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tuning_lowers_the_error() {
+        // White wins every game with the move, so a bigger tempo predicts better
+        let data = samples(&[
+            "r3k3/8/8/8/8/8/8/R3K3 w - - 0 1 [1.0]",
+            "2r1k3/8/8/8/8/8/8/2R1K3 w - - 0 1 [1.0]",
+            "4k3/3p4/8/8/8/8/3P4/4K3 w - - 0 1 [1.0]",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1 [0.5]",
+        ]);
+        let params = EvalParams::default();
+        let k = fit_k(&data, &params);
+        assert!(k > 0.0);
+
+        let mut tuner = Tuner::new(params, k, &["tempo.eg".to_string()], data, Vec::new());
+        assert_eq!(tuner.weights, ["tempo.eg"]);
+        let before = tuner.training_error(&tuner.params);
+        let mut curve = Vec::new();
+        tuner.run(3, 5, |epoch, _| curve.push(*epoch)).unwrap();
+        assert!(curve.last().unwrap().training_error < before);
+        assert!(tuner.params.tempo.eg > EvalParams::default().tempo.eg);
+    }
+}
@@ -0,0 +1,90 @@
+//! Tests that talk to the `uci` binary the way a GUI does.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+/// The engine process with a thread forwarding its output lines.
+struct Engine {
+    child: Child,
+    input: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Engine {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_uci"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Could not start the engine");
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in output.lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Engine {
+            child,
+            input,
+            lines,
+        }
+    }
+
+    fn send(&mut self, command: &str) {
+        writeln!(self.input, "{}", command).unwrap();
+        self.input.flush().unwrap();
+    }
+
+    /// The `bestmove` line printed within `timeout`, if any.
+    fn bestmove(&self, timeout: Duration) -> Option<String> {
+        loop {
+            let line = self.lines.recv_timeout(timeout).ok()?;
+            if line.starts_with("bestmove") {
+                return Some(line);
+            }
+        }
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.send("quit");
+        self.child.wait().unwrap();
+    }
+}
+
+#[test]
+fn test_go_ponder_waits_for_stop() {
+    // The Ponder option is off, which must not turn `go ponder` into a normal search
+    let mut engine = Engine::start();
+    engine.send("position startpos moves e2e4");
+    engine.send("go ponder wtime 100 btime 100");
+    assert_eq!(engine.bestmove(Duration::from_millis(1000)), None);
+
+    engine.send("stop");
+    let bestmove = engine.bestmove(Duration::from_secs(10)).unwrap();
+    // Without the option no move to ponder on is named
+    assert_eq!(bestmove.split_whitespace().count(), 2, "{}", bestmove);
+}
+
+#[test]
+fn test_go_mate_stops() {
+    // There is no mate in the starting position, so the search only ends when stopped
+    let mut engine = Engine::start();
+    engine.send("position startpos");
+    engine.send("go mate 20");
+    assert_eq!(engine.bestmove(Duration::from_millis(500)), None);
+
+    engine.send("stop");
+    let bestmove = engine.bestmove(Duration::from_secs(10)).unwrap();
+    assert_ne!(bestmove, "bestmove none");
+}